@@ -2,7 +2,7 @@ use warp::Filter;
 use tokio::sync::{mpsc, oneshot};
 use serde::{Deserialize, Serialize};
 use crate::block::Block;
-use crate::transaction::Transaction;
+use crate::transaction::{BalanceProof, Transaction};
 
 /// Commands sent from the API server to the main node loop
 #[derive(Debug)]
@@ -25,6 +25,12 @@ pub enum ApiCommand {
     RegisterValidator(u64, oneshot::Sender<Result<String, String>>),
     GetConsensusState(oneshot::Sender<ConsensusStateResponse>),
     Faucet(FaucetRequest, oneshot::Sender<Result<FaucetResponse, String>>),
+    ChannelPay(ChannelPaymentRequest, oneshot::Sender<Result<BalanceProof, String>>),
+    ChannelReceive(BalanceProof, oneshot::Sender<Result<BalanceProof, String>>),
+    ChannelConfirm(BalanceProof, oneshot::Sender<Result<BalanceProof, String>>),
+    GetChannelState(String, oneshot::Sender<Option<BalanceProof>>),
+    CloseChannel(ChannelCloseStep, CloseChannelRequest, oneshot::Sender<Result<String, String>>),
+    GetNFT(String, u64, oneshot::Sender<Option<NFTResponse>>),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -56,6 +62,16 @@ pub struct ValidatorStatusResponse {
     pub last_voted_slot: u64, // Placeholder, implementation might vary
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFTResponse {
+    pub collection_id: String,
+    pub item_id: u64,
+    pub owner: String,
+    pub approved: Option<String>,
+    pub metadata: String,
+    pub metadata_frozen: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConsensusStateResponse {
     pub finalized_block_hash: String,
@@ -106,6 +122,33 @@ pub struct PeerInfo {
 pub struct CreateTransactionRequest {
     pub receiver: String,
     pub amount: u64,
+    pub fee: Option<u64>, // Defaults to the minimum fee
+    pub valid_until_slot: Option<u64>, // No expiry if unset
+}
+
+/// DTO for paying through a payment channel opened by this node
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChannelPaymentRequest {
+    pub channel_id: String,
+    pub amount: u64,
+    #[serde(default)]
+    pub is_final: bool, // Last payment; the state can then be settled with CloseChannel
+}
+
+/// DTO for closing a channel, see `ChannelCloseStep`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CloseChannelRequest {
+    pub channel_id: String,
+    pub fee: Option<u64>, // Defaults to the minimum fee
+}
+
+/// Transaction a close request submits, each with the channel state it needs
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelCloseStep {
+    Cooperative, // CloseChannel on the final co-signed state
+    Start, // StartChannelClose on the latest co-signed state (a refund once expired)
+    Challenge, // ChallengeChannelClose with a state newer than the pending close
+    Settle, // SettleChannel once the challenge window has passed
 }
 
 /// DTO for Node Statistics
@@ -116,6 +159,7 @@ pub struct NodeStats {
     pub peer_count: usize, // Placeholder, implementing real peer count might require more wiring
     pub balance: u64,
     pub address: String,
+    pub chain_id: u32, // Transactions must be signed for this network
 }
 
 /// Configuration for the API server
@@ -247,6 +291,78 @@ pub fn start_api_server(
         .and(cmd_tx_filter.clone())
         .and_then(handle_faucet);
 
+    // POST /channel/pay
+    // Expects JSON body: ChannelPaymentRequest; returns the state to hand to the recipient
+    let channel_pay_route = warp::path!("channel" / "pay")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_channel_pay);
+
+    // POST /channel/receive
+    // Expects JSON body: BalanceProof signed by the payer; returns it counter-signed
+    let channel_receive_route = warp::path!("channel" / "receive")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_channel_receive);
+
+    // POST /channel/confirm
+    // Expects JSON body: BalanceProof counter-signed by the recipient
+    let channel_confirm_route = warp::path!("channel" / "confirm")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_channel_confirm);
+
+    // GET /channel/:id/state
+    let channel_state_route = warp::path!("channel" / String / "state")
+        .and(warp::get())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_get_channel_state);
+
+    // POST /channel/close
+    // Expects JSON body: CloseChannelRequest
+    let channel_close_route = warp::path!("channel" / "close")
+        .and(warp::post())
+        .and(warp::any().map(|| ChannelCloseStep::Cooperative))
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_close_channel);
+
+    // POST /channel/close/start
+    // Expects JSON body: CloseChannelRequest; closes without the partner
+    let channel_start_close_route = warp::path!("channel" / "close" / "start")
+        .and(warp::post())
+        .and(warp::any().map(|| ChannelCloseStep::Start))
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_close_channel);
+
+    // POST /channel/close/challenge
+    // Expects JSON body: CloseChannelRequest
+    let channel_challenge_close_route = warp::path!("channel" / "close" / "challenge")
+        .and(warp::post())
+        .and(warp::any().map(|| ChannelCloseStep::Challenge))
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_close_channel);
+
+    // POST /channel/close/settle
+    // Expects JSON body: CloseChannelRequest
+    let channel_settle_route = warp::path!("channel" / "close" / "settle")
+        .and(warp::post())
+        .and(warp::any().map(|| ChannelCloseStep::Settle))
+        .and(warp::body::json())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_close_channel);
+
+    // GET /nft/:collection_id/:item_id
+    let nft_route = warp::path!("nft" / String / u64)
+        .and(warp::get())
+        .and(cmd_tx_filter.clone())
+        .and_then(handle_get_nft);
+
     let routes = stats_route
         .or(blocks_route)
         .or(block_route)
@@ -264,7 +380,16 @@ pub fn start_api_server(
         .or(validators_list_route)
         .or(register_validator_route)
         .or(consensus_route)
-        .or(faucet_route);
+        .or(faucet_route)
+        .or(channel_pay_route)
+        .or(channel_receive_route)
+        .or(channel_confirm_route)
+        .or(channel_state_route)
+        .or(channel_close_route)
+        .or(channel_start_close_route)
+        .or(channel_challenge_close_route)
+        .or(channel_settle_route)
+        .or(nft_route);
 
     println!("API server starting on http://0.0.0.0:{}", config.port);
     warp::serve(routes).run(([0, 0, 0, 0], config.port))
@@ -758,3 +883,179 @@ async fn handle_faucet(
         )),
     }
 }
+
+async fn handle_channel_pay(
+    request: ChannelPaymentRequest,
+    cmd_tx: mpsc::UnboundedSender<ApiCommand>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    if cmd_tx.send(ApiCommand::ChannelPay(request, tx)).is_err() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Internal Server Error"),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    match rx.await {
+        Ok(Ok(response)) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(Err(e)) => Ok(warp::reply::with_status(
+            warp::reply::json(&e),
+            warp::http::StatusCode::BAD_REQUEST,
+        )),
+        Err(_) => Ok(warp::reply::with_status(
+            warp::reply::json(&"Request timed out"),
+            warp::http::StatusCode::REQUEST_TIMEOUT,
+        )),
+    }
+}
+
+async fn handle_channel_receive(
+    request: BalanceProof,
+    cmd_tx: mpsc::UnboundedSender<ApiCommand>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    if cmd_tx.send(ApiCommand::ChannelReceive(request, tx)).is_err() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Internal Server Error"),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    match rx.await {
+        Ok(Ok(response)) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(Err(e)) => Ok(warp::reply::with_status(
+            warp::reply::json(&e),
+            warp::http::StatusCode::BAD_REQUEST,
+        )),
+        Err(_) => Ok(warp::reply::with_status(
+            warp::reply::json(&"Request timed out"),
+            warp::http::StatusCode::REQUEST_TIMEOUT,
+        )),
+    }
+}
+
+async fn handle_channel_confirm(
+    request: BalanceProof,
+    cmd_tx: mpsc::UnboundedSender<ApiCommand>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    if cmd_tx.send(ApiCommand::ChannelConfirm(request, tx)).is_err() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Internal Server Error"),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    match rx.await {
+        Ok(Ok(response)) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(Err(e)) => Ok(warp::reply::with_status(
+            warp::reply::json(&e),
+            warp::http::StatusCode::BAD_REQUEST,
+        )),
+        Err(_) => Ok(warp::reply::with_status(
+            warp::reply::json(&"Request timed out"),
+            warp::http::StatusCode::REQUEST_TIMEOUT,
+        )),
+    }
+}
+
+async fn handle_get_channel_state(
+    channel_id: String,
+    cmd_tx: mpsc::UnboundedSender<ApiCommand>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    if cmd_tx.send(ApiCommand::GetChannelState(channel_id, tx)).is_err() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Internal Server Error"),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    match rx.await {
+        Ok(Some(state)) => Ok(warp::reply::with_status(
+            warp::reply::json(&state),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&"No channel state"),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+        Err(_) => Ok(warp::reply::with_status(
+            warp::reply::json(&"Request timed out"),
+            warp::http::StatusCode::REQUEST_TIMEOUT,
+        )),
+    }
+}
+
+async fn handle_get_nft(
+    collection_id: String,
+    item_id: u64,
+    cmd_tx: mpsc::UnboundedSender<ApiCommand>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    if cmd_tx.send(ApiCommand::GetNFT(collection_id, item_id, tx)).is_err() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Internal Server Error"),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    match rx.await {
+        Ok(Some(nft)) => Ok(warp::reply::with_status(
+            warp::reply::json(&nft),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&"NFT not found"),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+        Err(_) => Ok(warp::reply::with_status(
+            warp::reply::json(&"Request timed out"),
+            warp::http::StatusCode::REQUEST_TIMEOUT,
+        )),
+    }
+}
+
+async fn handle_close_channel(
+    step: ChannelCloseStep,
+    request: CloseChannelRequest,
+    cmd_tx: mpsc::UnboundedSender<ApiCommand>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    if cmd_tx.send(ApiCommand::CloseChannel(step, request, tx)).is_err() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Internal Server Error"),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    match rx.await {
+        Ok(Ok(response)) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            warp::http::StatusCode::OK,
+        )),
+        Ok(Err(e)) => Ok(warp::reply::with_status(
+            warp::reply::json(&e),
+            warp::http::StatusCode::BAD_REQUEST,
+        )),
+        Err(_) => Ok(warp::reply::with_status(
+            warp::reply::json(&"Request timed out"),
+            warp::http::StatusCode::REQUEST_TIMEOUT,
+        )),
+    }
+}
@@ -2,7 +2,7 @@ use crate::transaction::Transaction;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 pub struct BlockHeader {
     pub parent_hash: String,
     pub slot: u64,
@@ -65,6 +65,26 @@ impl Block {
         hex::encode(&hashes[0])
     }
 
+    /// Hash of the header with the producer signature cleared; this is what the producer signs
+    pub fn signing_hash(header: &BlockHeader) -> Vec<u8> {
+        let mut unsigned = header.clone();
+        unsigned.producer_signature = vec![];
+        let serialized = bincode::serialize(&unsigned).unwrap();
+        Sha256::digest(serialized).to_vec()
+    }
+
+    /// Serialized size of the block in bytes
+    pub fn size(&self) -> u64 {
+        bincode::serialized_size(self).unwrap_or(u64::MAX)
+    }
+
+    /// Sign the header with the producer's validator key and refresh the block hash
+    pub fn sign(&mut self, keypair: &schnorrkel::Keypair) {
+        let message = Self::signing_hash(&self.header);
+        self.header.producer_signature = crate::crypto::Crypto::sign_block(keypair, &message);
+        self.hash = Self::calculate_hash(&self.header);
+    }
+
     pub fn calculate_hash(header: &BlockHeader) -> String {
         use sha2::{Digest, Sha256};
         let serialized = bincode::serialize(header).unwrap();
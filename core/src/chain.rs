@@ -1,15 +1,24 @@
-use crate::block::Block;
-use crate::consensus::Consensus;
-use crate::state::State;
-use crate::validator::ValidatorSet;
+use crate::block::{Block, BlockHeader};
+use crate::config::{GenesisConfig, GENESIS_VALIDATOR_BALANCE, GENESIS_VALIDATOR_STAKE};
+use crate::crypto::Crypto;
+use crate::consensus::{Consensus, RandomnessMix, SLOTS_PER_EPOCH};
+use crate::state::{State, UndoJournal};
+use crate::validator::{ValidatorInfo, ValidatorSet};
 use crate::transaction::Transaction;
 use crate::storage::Storage;
 use crate::vote::Vote;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use schnorrkel::PublicKey;
+use serde::{Deserialize, Serialize};
 use metrics::{gauge, counter, histogram};
 use std::time::Instant;
 
+/// Everything needed to take a committed block back off the canonical chain
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockUndo {
+    pub state: UndoJournal,
+}
+
 pub struct Chain {
     pub storage: Storage,
     pub head: String, // Hash of the current head
@@ -17,18 +26,37 @@ pub struct Chain {
     pub state: State,
     pub validators: ValidatorSet,
     /// Track seen block headers per slot to detect equivocation
-    /// Key: (slot, validator_pubkey), Value: block_hash
-    seen_headers: HashMap<(u64, Vec<u8>), String>,
+    /// Key: (slot, validator_pubkey), Value: block header
+    seen_headers: HashMap<(u64, Vec<u8>), BlockHeader>,
     
     /// Votes for blocks: BlockHash -> List of Votes
     pub votes: HashMap<String, Vec<Vote>>,
     
     /// Hash of the latest finalized block
     pub finalized_head: String,
+
+    /// Blocks that failed execution, and so does every descendant
+    invalid_blocks: HashSet<String>,
+
+    /// Transactions from blocks reverted by a reorg, for the node to re-queue
+    reverted_transactions: Vec<Transaction>,
+
+    /// Conflicting header pairs seen since the last call, for the node to report on-chain
+    equivocation_evidence: Vec<(BlockHeader, BlockHeader)>,
+
+    /// Genesis parameters (block reward schedule)
+    genesis: GenesisConfig,
+
+    /// Network identifier every transaction must be signed for
+    chain_id: u32,
 }
 
 impl Chain {
-    pub fn new(storage: Storage, genesis_block: Block, genesis_time: i64) -> Self {
+    pub fn new(storage: Storage, genesis_block: Block, genesis: &GenesisConfig) -> Self {
+        let mut state = State::new(storage.clone());
+        state.set_unbonding_epochs(genesis.unbonding_epochs);
+        state.set_channel_challenge_slots(genesis.channel_challenge_slots);
+
         // Check if we have a head in storage
         let head = if let Ok(Some(h)) = storage.get_head() {
             tracing::info!("Loaded existing chain head: {}", h);
@@ -36,25 +64,93 @@ impl Chain {
         } else {
             // Initialize with genesis
             tracing::info!("Initializing new chain with genesis: {}", genesis_block.hash);
+            Self::allocate_genesis(&mut state, genesis);
             storage.store_block(&genesis_block).expect("Failed to store genesis block");
             storage.store_head(&genesis_block.hash).expect("Failed to store genesis head");
+            storage.store_block_by_height(0, &genesis_block.hash).expect("Failed to index genesis block");
             genesis_block.hash.clone()
         };
+        if let Ok(None) = storage.get_block_weight(&genesis_block.hash) {
+            storage.store_block_weight(&genesis_block.hash, 0).expect("Failed to store genesis weight");
+        }
+        if let Ok(None) = storage.get_block_randomness(&genesis_block.hash) {
+            storage.store_block_randomness(&genesis_block.hash, &RandomnessMix::genesis(&genesis_block.hash))
+                .expect("Failed to store genesis randomness");
+        }
         
-        Self {
-            storage: storage.clone(),
+        // Genesis is final until votes finalize something later
+        let finalized_head = storage.get_finalized().ok().flatten().unwrap_or(genesis_block.hash.clone());
+
+        let mut chain = Self {
+            storage,
             head: head.clone(),
-            consensus: Consensus::new(genesis_time),
-            state: State::new(storage),
+            consensus: Consensus::new(genesis.genesis_time, genesis.active_slot_coeff_bps, genesis.limits.clone()),
+            state,
             validators: ValidatorSet::new(),
             seen_headers: HashMap::new(),
             votes: HashMap::new(),
-            finalized_head: head, // Initially genesis is finalized
+            finalized_head,
+            invalid_blocks: HashSet::new(),
+            reverted_transactions: Vec::new(),
+            equivocation_evidence: Vec::new(),
+            genesis: genesis.clone(),
+            chain_id: genesis.chain_id(&genesis_block.hash),
+        };
+        chain.reload_validators();
+        chain.reload_votes();
+        chain
+    }
+
+    /// Commit the genesis state: every initial validator is bonded and its wallet funded
+    /// Runs once, on empty storage, so every node starts from the same state root.
+    fn allocate_genesis(state: &mut State, genesis: &GenesisConfig) {
+        for seed in &genesis.initial_validators {
+            let validator = Crypto::validator_keypair_from_seed(seed).public.to_bytes().to_vec();
+            let wallet = Crypto::wallet_key_from_seed(seed).verifying_key().to_bytes().to_vec();
+            state.add_validator(ValidatorInfo::new(validator, GENESIS_VALIDATOR_STAKE, 0).with_reward_address(wallet.clone()));
+            state.credit_balance(&wallet, GENESIS_VALIDATOR_BALANCE);
         }
+        state.apply_changes().expect("Failed to commit genesis state");
+    }
+
+    /// Load stored votes for blocks that are not yet final
+    fn reload_votes(&mut self) {
+        let finalized_slot = self.finalized_slot();
+        for vote in self.storage.get_all_votes().unwrap_or_default() {
+            if vote.slot > finalized_slot {
+                self.votes.entry(vote.block_hash.clone()).or_default().push(vote);
+            }
+        }
+    }
+
+    /// Rebuild the in-memory validator set for the head's epoch from committed state
+    pub fn reload_validators(&mut self) {
+        let epoch = self.get_head().header.slot / SLOTS_PER_EPOCH;
+        self.validators = self.validators_for_epoch(epoch);
+    }
+
+    /// Validator set for `epoch`, as seen from committed state
+    /// Set changes only ever take effect from the next epoch on, so this holds for
+    /// the head's descendants in any epoch from the head's onwards.
+    pub fn validators_for_epoch(&self, epoch: u64) -> ValidatorSet {
+        ValidatorSet::from_validators(self.state.get_all_validators(), epoch)
     }
 
     pub fn add_block(&mut self, block: Block) -> bool {
         let start = Instant::now();
+        // 0. Known blocks and descendants of invalid blocks
+        if self.invalid_blocks.contains(&block.hash) || self.invalid_blocks.contains(&block.header.parent_hash) {
+            return false;
+        }
+        if self.get_block(&block.hash).is_some() {
+            return false; // Already have it
+        }
+
+        // 0b. Finalized history is fixed; nothing new fits at or below it
+        if block.header.slot <= self.finalized_slot() {
+            return false;
+        }
+
         // 1. Basic Validation
         // Check if parent exists in storage
         let parent_block = match self.storage.get_block(&block.header.parent_hash) {
@@ -64,156 +160,452 @@ impl Chain {
 
 
 
-        // 1b. Equivocation Detection (Double Signing)
+        // 2. Consensus / PoS Validation
+        // The VRF seed comes from the randomness along the block's own ancestry. Leadership
+        // needs the epoch's validator set as of the parent, which committed state only holds
+        // when the parent is the head; side-fork blocks are checked in full when a reorg
+        // replays them, and until then only stored if their producer is a known validator.
+        let parent_mix = match self.block_randomness(&parent_block.hash) {
+            Ok(mix) => mix,
+            Err(e) => {
+                tracing::warn!("Block rejected: {}", e);
+                return false;
+            }
+        };
+        let block_epoch = block.header.slot / SLOTS_PER_EPOCH;
+        let extends_head = parent_block.hash == self.head;
+        let epoch_validators = (extends_head && block_epoch != self.validators.epoch()).then(|| self.validators_for_epoch(block_epoch));
+        let validators = extends_head.then(|| epoch_validators.as_ref().unwrap_or(&self.validators));
+        let randomness = parent_mix.advance_to(block_epoch).randomness;
+        if let Err(e) = self.consensus.validate_block(&block, &parent_block, validators, &randomness) {
+            tracing::warn!("Block rejected by consensus: {}", e);
+            return false;
+        }
+        if !extends_head {
+            let known = self.state.get_validator(&block.header.validator_pubkey)
+                .is_some_and(|validator| !validator.slashed && validator.stake > 0);
+            if !known {
+                tracing::warn!("Block rejected: fork block producer is not a known validator");
+                return false;
+            }
+        }
+
+        // 2b. Equivocation Detection (Double Signing)
+        // Only signed, consensus-valid headers count as evidence
         let slot = block.header.slot;
         let validator_pubkey = block.header.validator_pubkey.clone();
         let key = (slot, validator_pubkey.clone());
         
         // Check memory first, then disk
-        let existing_hash_opt = self.seen_headers.get(&key).cloned().or_else(|| {
+        let existing_header_opt = self.seen_headers.get(&key).cloned().or_else(|| {
              self.storage.get_seen_header(slot, &validator_pubkey).ok().flatten()
         });
 
-        if let Some(existing_hash) = existing_hash_opt {
-            if existing_hash != block.hash {
+        if let Some(existing_header) = existing_header_opt {
+            // A re-signed copy of the same header differs only in its randomized signature
+            let existing_hash = Block::calculate_hash(&existing_header);
+            if Block::signing_hash(&existing_header) != Block::signing_hash(&block.header) {
                 tracing::error!("EQUIVOCATION DETECTED! Validator {:?} signed two different blocks for slot {}", 
                     validator_pubkey, slot);
                 tracing::error!("Existing block: {}, New block: {}", existing_hash, block.hash);
                 
-                // Slash the validator
-                if let Err(e) = self.validators.slash_validator(&validator_pubkey) {
-                    tracing::error!("Failed to slash validator: {}", e);
-                } else {
-                    tracing::info!("Validator slashed successfully");
-                }
+                // Slashing happens on-chain through an evidence transaction
+                self.equivocation_evidence.push((existing_header, block.header.clone()));
                 
                 // Reject this block
                 return false;
             }
         } else {
             // Record this block header in memory and disk
-            self.seen_headers.insert(key, block.hash.clone());
-            if let Err(e) = self.storage.store_seen_header(slot, &validator_pubkey, &block.hash) {
+            self.seen_headers.insert(key, block.header.clone());
+            if let Err(e) = self.storage.store_seen_header(&block.header) {
                 tracing::warn!("Failed to persist seen header: {}", e);
             }
         }
 
-        // 2. Consensus / PoS Validation
-        if let Err(e) = self.consensus.validate_block(&block, &parent_block, &self.validators) {
-            tracing::warn!("Block rejected by consensus: {}", e);
+        // 3. Fork Choice
+        // State is only ever committed along the canonical chain
+        let current_head_block = self.get_head();
+        let weights = self.block_weight(&parent_block.hash)
+            .and_then(|parent_weight| Ok((parent_weight + 1, self.block_weight(&self.head)?)));
+        let (weight, head_weight) = match weights {
+            Ok(w) => w,
+            Err(e) => {
+                tracing::error!("Failed to get chain weight: {}", e);
+                return false;
+            }
+        };
+        if let Err(e) = self.storage.store_block_weight(&block.hash, weight) {
+            tracing::error!("Failed to store block weight: {}", e);
+            return false;
+        }
+        if let Err(e) = self.storage.store_block_randomness(&block.hash, &parent_mix.child(&block.header)) {
+            tracing::error!("Failed to store block randomness: {}", e);
             return false;
         }
 
-        // 3. Validate and process transactions (ATOMIC)
-        self.state.discard_changes(); // Ensure clean slate
-        for tx in &block.transactions {
-            if let Err(_) = self.process_transaction(tx) {
+        if block.header.parent_hash == self.head {
+            // Extends the canonical chain
+            if let Err(e) = self.connect_block(&block) {
+                tracing::warn!("Block rejected: {}", e);
                 self.state.discard_changes(); // Rollback
-                return false; 
+                self.invalid_blocks.insert(block.hash.clone());
+                return false;
             }
-        }
-
-        // 3b. Verify state_root (if provided in block header)
-        // Note: For genesis or blocks without state_root, we skip this check
-        if !block.header.state_root.is_empty() {
-            let calculated_state_root = self.state.get_root_hash();
-            if block.header.state_root != calculated_state_root {
-                tracing::error!("State root mismatch. Expected: {}, Got: {}", 
-                    block.header.state_root, calculated_state_root);
-                self.state.discard_changes();
+            self.update_finality();
+        } else if Consensus::is_better_block(&block, weight, &current_head_block, head_weight) {
+            // Better fork: reorg onto it
+            if let Err(e) = self.storage.store_block(&block) {
+                tracing::error!("Failed to store block: {}", e);
                 return false;
             }
+            if let Err(e) = self.reorg_to(&block) {
+                tracing::warn!("Reorg to {} failed: {}", block.hash, e);
+                return false;
+            }
+            self.update_finality();
+        } else {
+            // Side fork: keep the block so the fork can still win later
+            if let Err(e) = self.storage.store_block(&block) {
+                tracing::error!("Failed to store block: {}", e);
+                return false;
+            }
+            tracing::info!("Stored side-fork block {} at slot {}", block.hash, block.header.slot);
         }
-
-        // 4. Fork Choice
-        let current_head_block = self.get_head();
         
-        if Consensus::is_better_block(&block, &current_head_block) {
-            self.head = block.hash.clone();
-            if let Err(e) = self.storage.store_head(&self.head) {
-                tracing::error!("Failed to store head: {}", e);
+        // Metrics Update
+        gauge!("block_height", self.get_height() as f64);
+        counter!("transaction_count", block.transactions.len() as u64);
+        histogram!("block_processing_time", start.elapsed());
+
+        true
+    }
+
+    /// Chain weight of a stored block: the number of blocks from genesis up to it
+    /// Falls back to counting ancestors for blocks stored without a weight.
+    pub fn block_weight(&self, hash: &str) -> Result<u64, String> {
+        let mut missing = 0;
+        let mut current = hash.to_string();
+        loop {
+            if let Some(weight) = self.storage.get_block_weight(&current)? {
+                return Ok(weight + missing);
             }
+            let block = self.storage.get_block(&current)?
+                .ok_or_else(|| format!("Block {} not found", current))?;
+            current = block.header.parent_hash;
+            missing += 1;
         }
+    }
 
-        if let Err(e) = self.storage.store_block(&block) {
-            tracing::error!("Failed to store block: {}", e);
-            self.state.discard_changes(); // Rollback if block storage fails
-            return false;
-        }
+    /// Execute a block on top of the current head and make it the new head
+    /// On error nothing is committed, but pending state must be discarded by the caller.
+    fn connect_block(&mut self, block: &Block) -> Result<(), String> {
+        // 1. Validate and execute transactions (ATOMIC)
+        self.execute_block(block)?;
 
-        // 5. Commit State Changes
-        if let Err(e) = self.state.apply_changes() {
-            tracing::error!("Failed to apply state changes: {}", e);
-            return false;
+        // 2. Verify state_root (required for every non-genesis block)
+        let calculated_state_root = self.state.compute_root_hash()?;
+        if block.header.state_root != calculated_state_root {
+            return Err(format!("State root mismatch. Expected: {}, Got: {}",
+                block.header.state_root, calculated_state_root));
         }
 
-        // Index block by height
-        if let Err(e) = self.storage.store_block_by_height(block.header.slot, &block.hash) {
-            tracing::error!("Failed to index block by height: {}", e);
+        // 3. Store the block with what is needed to revert it, commit the state changes
+        // and move the head in one atomic write, so a crash never leaves them out of step
+        let undo = BlockUndo { state: self.state.undo_journal()? };
+        self.storage.begin_batch();
+        if let Err(e) = self.write_connected_block(block, &undo).and_then(|_| self.storage.commit_batch()) {
+            self.storage.discard_batch();
+            self.state.rebuild_trie(); // apply_changes may have moved the trie past storage
+            return Err(e);
         }
+        self.head = block.hash.clone();
+        self.reload_validators();
 
-        // Index transactions and Address History
+        // 4. Index Address History
+        // Best effort and outside the batch: each entry reads the address's history count
         for tx in &block.transactions {
-            // Validator Management Logic (after state applied balance changes)
-            match &tx.data {
-                 crate::transaction::TransactionData::RegisterValidator { stake } => {
-                     // Balance is already deducted by state.apply_transaction
-                     if let Err(e) = self.validators.register_validator(tx.sender.clone(), *stake, block.header.epoch) {
-                         println!("Failed to register validator: {}", e);
-                         // TODO: If this fails, we should technically revert the transaction or handle it.
-                         // For now, if state applied it (deducted balance), but this fails, user loses money?
-                         // Ideally validate_block logic checks this BEFORE execution.
-                         // We are safe because register_validator checks duplicate.
-                         // BUT min stake check is in validate_logic? No, just >0.
-                         // Core logic should be consistent.
-                     }
-                 },
-                 crate::transaction::TransactionData::UnregisterValidator => {
-                     match self.validators.unregister_validator(&tx.sender) {
-                         Ok(stake) => {
-                             // Refund stake
-                             let mut account = self.state.get_account(&tx.sender).unwrap_or(crate::state::Account::new(0));
-                             account.balance += stake;
-                             self.state.pending_changes.insert(tx.sender.clone(), account);
-                         },
-                         Err(e) => println!("Failed to unregister validator: {}", e),
-                     }
-                 },
-                 _ => {}
-            }
-
             let tx_hash = hex::encode(tx.hash());
-            
-            // 1. Index Tx -> Block
-            if let Err(e) = self.storage.store_transaction_index(&tx_hash, &block.hash) {
-                 println!("Failed to index transaction: {}", e);
-            }
-            
-            // 2. Index Address History (Sender)
+
+            // Sender
             let sender_hex = hex::encode(&tx.sender);
             if let Err(e) = self.storage.add_transaction_to_address(&sender_hex, &tx_hash) {
                 println!("Failed to index history for sender: {}", e);
             }
-            
-            // 3. Index Address History (Receiver)
+
+            // Receiver
             let receiver_hex = hex::encode(&tx.receiver);
             if let Err(e) = self.storage.add_transaction_to_address(&receiver_hex, &tx_hash) {
                 tracing::error!("Failed to index history for receiver: {}", e);
             }
         }
-        
-        // Metrics Update
-        gauge!("block_height", block.header.slot as f64);
-        counter!("transaction_count", block.transactions.len() as u64);
-        histogram!("block_processing_time", start.elapsed());
 
-        true
+        Ok(())
+    }
+
+    /// Writes that make an executed block the head, for `connect_block` to batch
+    fn write_connected_block(&mut self, block: &Block, undo: &BlockUndo) -> Result<(), String> {
+        self.storage.store_block(block)?;
+        self.storage.store_undo(&block.hash, undo)?;
+        self.state.apply_changes()?;
+        self.storage.store_head(&block.hash)?;
+
+        // Index block by height and its transactions (canonical blocks only)
+        self.storage.store_block_by_height(block.header.slot, &block.hash)?;
+        for tx in &block.transactions {
+            self.storage.store_transaction_index(&hex::encode(tx.hash()), &block.hash)?;
+        }
+        Ok(())
+    }
+
+    /// Revert the head block using its undo record and step back to its parent
+    /// Every head change is one atomic write, so a crash mid-reorg leaves the node on
+    /// a consistent block of either chain. The trie is left stale; callers rebuild it
+    /// once they are done reverting.
+    fn disconnect_head(&mut self) -> Result<Block, String> {
+        let block = self.get_head();
+        let undo = self.storage.get_undo(&block.hash)?
+            .ok_or(format!("Missing undo record for block {}", block.hash))?;
+
+        self.storage.begin_batch();
+        if let Err(e) = self.write_disconnected_block(&block, &undo).and_then(|_| self.storage.commit_batch()) {
+            self.storage.discard_batch();
+            return Err(e);
+        }
+        self.head = block.header.parent_hash.clone();
+        self.reload_validators();
+        self.reverted_transactions.extend(block.transactions.iter().cloned());
+
+        Ok(block)
+    }
+
+    /// Writes that take the head block off the canonical chain, for `disconnect_head` to batch
+    fn write_disconnected_block(&mut self, block: &Block, undo: &BlockUndo) -> Result<(), String> {
+        self.state.revert(&undo.state)?;
+        self.storage.delete_block_by_height(block.header.slot)?;
+        for tx in &block.transactions {
+            self.storage.delete_transaction_index(&hex::encode(tx.hash()))?;
+        }
+        self.storage.store_head(&block.header.parent_hash)
+    }
+
+    /// Switch the canonical chain to the fork ending in `new_tip`
+    /// Rolls back to the common ancestor and replays the fork; if any fork block
+    /// fails to execute, the fork is marked invalid and the old chain restored.
+    fn reorg_to(&mut self, new_tip: &Block) -> Result<(), String> {
+        // 1. Collect the fork branch back to the canonical chain
+        let mut branch = vec![new_tip.clone()];
+        let mut cursor = new_tip.header.parent_hash.clone();
+        loop {
+            let block = self.get_block(&cursor).ok_or(format!("Missing fork block {}", cursor))?;
+            if self.is_canonical(&block) {
+                break;
+            }
+            cursor = block.header.parent_hash.clone();
+            branch.push(block);
+        }
+        branch.reverse();
+        let ancestor = cursor;
+
+        // Never roll back past the finalized block
+        let ancestor_slot = self.get_block(&ancestor).map(|b| b.header.slot).unwrap_or(0);
+        if ancestor_slot < self.finalized_slot() {
+            for block in &branch {
+                self.invalid_blocks.insert(block.hash.clone());
+            }
+            return Err(format!("Fork from {} conflicts with finalized block {}", ancestor, self.finalized_head));
+        }
+
+        tracing::info!("Reorg: rolling back to {} and applying {} fork blocks", ancestor, branch.len());
+
+        // 2. Roll the canonical chain back to the common ancestor
+        let reverted_before = self.reverted_transactions.len();
+        let mut disconnected = Vec::new();
+        while self.head != ancestor {
+            disconnected.push(self.disconnect_head()?);
+        }
+        self.state.rebuild_trie();
+
+        // 3. Replay the fork, checking leadership now that state is at each block's parent
+        for (i, block) in branch.iter().enumerate() {
+            if let Err(e) = self.check_slot_leader(block).and_then(|_| self.connect_block(block)) {
+                self.state.discard_changes();
+                tracing::warn!("Fork block {} failed to execute: {}", block.hash, e);
+
+                // This block and its descendants can never become canonical
+                for bad in &branch[i..] {
+                    self.invalid_blocks.insert(bad.hash.clone());
+                    let _ = self.storage.delete_block(&bad.hash);
+                }
+
+                // Restore the original chain
+                while self.head != ancestor {
+                    self.disconnect_head()?;
+                }
+                self.state.rebuild_trie();
+                for block in disconnected.iter().rev() {
+                    if let Err(e) = self.connect_block(block) {
+                        self.state.discard_changes();
+                        return Err(format!("Failed to restore block {}: {}", block.hash, e));
+                    }
+                }
+                self.reverted_transactions.truncate(reverted_before);
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Randomness used as the VRF seed input in `epoch` by a block built on the head
+    /// Only holds for the head's descendants: a fork block takes it from its own parent.
+    pub fn epoch_randomness(&self, epoch: u64) -> Result<Vec<u8>, String> {
+        let mix = self.block_randomness(&self.head)?;
+        if epoch < mix.epoch {
+            return Err(format!("Epoch {} is before the head's epoch {}", epoch, mix.epoch));
+        }
+        Ok(mix.advance_to(epoch).randomness)
+    }
+
+    /// Epoch randomness and VRF mix as of a stored block, along its own ancestry
+    /// Falls back to folding in ancestors for blocks stored without one.
+    pub fn block_randomness(&self, hash: &str) -> Result<RandomnessMix, String> {
+        let mut missing = Vec::new();
+        let mut current = hash.to_string();
+        let mut mix = loop {
+            if let Some(mix) = self.storage.get_block_randomness(&current)? {
+                break mix;
+            }
+            let block = self.storage.get_block(&current)?
+                .ok_or_else(|| format!("Block {} not found", current))?;
+            current = block.header.parent_hash.clone();
+            missing.push(block.header);
+        };
+        for header in missing.iter().rev() {
+            mix = mix.child(header);
+        }
+        Ok(mix)
+    }
+
+    /// Check that a block's producer won its slot, by the validator set in committed state
+    /// Only meaningful while the block's parent is the head.
+    fn check_slot_leader(&self, block: &Block) -> Result<(), String> {
+        let validators = self.validators_for_epoch(block.header.slot / SLOTS_PER_EPOCH);
+        if !self.consensus.is_slot_leader(&validators, &block.header.validator_pubkey, &block.header.vrf_output) {
+            return Err(format!("Producer of block {} did not win slot {}", block.hash, block.header.slot));
+        }
+        Ok(())
+    }
+
+    /// Slot of the latest finalized block
+    pub fn finalized_slot(&self) -> u64 {
+        self.get_block(&self.finalized_head).map(|b| b.header.slot).unwrap_or(0)
+    }
+
+    /// Whether a block is final: on the canonical chain at or below the finalized block
+    pub fn is_finalized(&self, block: &Block) -> bool {
+        block.header.slot <= self.finalized_slot() && self.is_canonical(block)
+    }
+
+    /// Whether a block is on the canonical chain
+    fn is_canonical(&self, block: &Block) -> bool {
+        block.header.slot == 0
+            || self.storage.get_block_by_height(block.header.slot).ok().flatten().as_deref() == Some(block.hash.as_str())
+    }
+
+    /// Transactions from blocks reverted by reorgs since the last call
+    pub fn take_reverted_transactions(&mut self) -> Vec<Transaction> {
+        std::mem::take(&mut self.reverted_transactions)
+    }
+
+    /// Equivocation evidence collected since the last call
+    pub fn take_equivocation_evidence(&mut self) -> Vec<(BlockHeader, BlockHeader)> {
+        std::mem::take(&mut self.equivocation_evidence)
+    }
+
+    /// Execute a block's transactions on top of the current state
+    /// The resulting changes are left pending; the caller verifies and commits or discards them.
+    fn execute_block(&mut self, block: &Block) -> Result<(), String> {
+        self.state.discard_changes(); // Ensure clean slate
+        self.state.begin_block(block.header.slot);
+
+        for tx in &block.transactions {
+            self.process_transaction(tx)?;
+        }
+
+        self.reward_producer(&block.header.validator_pubkey, block.header.epoch, &block.transactions);
+        Ok(())
+    }
+
+    /// Assemble a block on top of the current head from candidate transactions
+    /// Candidates that fail to execute or don't fit the block limits are left out.
+    /// tx_root and state_root are filled in from the included transactions;
+    /// committed state is not modified.
+    pub fn build_block(&mut self, mut header: BlockHeader, candidates: Vec<Transaction>) -> Block {
+        self.state.discard_changes();
+        self.state.begin_block(header.slot);
+        let mut included = Vec::new();
+
+        // Size of the block without transactions, once its roots and signature are filled in
+        let limits = self.consensus.limits().clone();
+        let mut sized_header = header.clone();
+        sized_header.state_root = "0".repeat(64);
+        sized_header.tx_root = "0".repeat(64);
+        sized_header.producer_signature = vec![0; 64];
+        let mut block_bytes = Block { header: sized_header, transactions: vec![], hash: "0".repeat(64) }.size();
+
+        for tx in candidates {
+            if included.len() as u64 >= limits.max_block_txs {
+                break;
+            }
+            let tx_bytes = bincode::serialized_size(&tx).unwrap_or(u64::MAX);
+            if block_bytes.saturating_add(tx_bytes) > limits.max_block_bytes {
+                continue;
+            }
+
+            let checkpoint = self.state.checkpoint();
+            match self.process_transaction(&tx) {
+                Ok(()) => {
+                    block_bytes += tx_bytes;
+                    included.push(tx);
+                }
+                Err(e) => {
+                    tracing::debug!("Leaving transaction {} out of block: {}", hex::encode(tx.hash()), e);
+                    self.state.restore(checkpoint);
+                }
+            }
+        }
+
+        self.reward_producer(&header.validator_pubkey, header.epoch, &included);
+        match self.state.compute_root_hash() {
+            Ok(root) => header.state_root = root,
+            Err(e) => tracing::error!("Failed to compute state root: {}", e),
+        }
+        self.state.discard_changes();
+
+        Block::new(header, included)
+    }
+
+    /// Pay the block reward for `epoch` and the collected fees to the block producer
+    /// The producer signs with a schnorrkel key, so the payout goes to the Ed25519
+    /// reward address stored with its validator record.
+    fn reward_producer(&mut self, producer: &[u8], epoch: u64, transactions: &[Transaction]) {
+        let total_fees = transactions.iter().fold(0u64, |acc, tx| acc.saturating_add(tx.fee));
+        let reward = self.genesis.block_reward_at(epoch).saturating_add(total_fees);
+        if reward > 0 {
+            let payee = self.state.get_validator(producer)
+                .map(|validator| validator.reward_address)
+                .unwrap_or_else(|| producer.to_vec());
+            self.state.credit_balance(&payee, reward);
+        }
     }
 
     /// Process a transaction and update state
     pub fn process_transaction(&mut self, tx: &Transaction) -> Result<(), String> {
-        // Validate transaction
-        tx.validate()?;
+        // Validate transaction (multisig senders against their stored signer set)
+        let multisig = self.state.get_multisig(&tx.sender);
+        tx.validate(self.chain_id, self.consensus.limits(), multisig.as_ref())?;
 
         // Execute transaction logic
         self.state.apply_transaction(tx)?;
@@ -221,6 +613,11 @@ impl Chain {
         Ok(())
     }
 
+    /// Network identifier transactions must be signed for
+    pub fn chain_id(&self) -> u32 {
+        self.chain_id
+    }
+
     pub fn get_head(&self) -> Block {
         self.storage.get_block(&self.head).unwrap().expect("Head block not found in storage")
     }
@@ -267,6 +664,12 @@ impl Chain {
         if !self.validators.is_validator(&vote.validator_pubkey) {
             return false;
         }
+        if vote.slot <= self.finalized_slot() {
+            return false; // Already final
+        }
+        if self.get_block(&vote.block_hash).is_some_and(|b| b.header.slot != vote.slot) {
+            return false;
+        }
 
         // 2. Verify Signature
         if let Ok(pubkey) = PublicKey::from_bytes(&vote.validator_pubkey) {
@@ -302,28 +705,59 @@ impl Chain {
         true
     }
 
+    /// Finalize a voted block once more than 2/3 of the active stake has voted for it
+    /// Only canonical blocks above the current finalized block qualify.
     fn check_finality(&mut self, block_hash: &str) {
+        let block = match self.get_block(block_hash) {
+            Some(b) => b,
+            None => return,
+        };
+        if block.header.slot <= self.finalized_slot() || !self.is_canonical(&block) {
+            return;
+        }
+
         if let Some(votes) = self.votes.get(block_hash) {
             let mut total_vote_stake = 0;
             let total_stake = self.validators.get_total_stake();
             
             for vote in votes {
                 if let Some(validator) = self.validators.get_validator(&vote.validator_pubkey) {
-                    total_vote_stake += validator.stake;
+                    if self.validators.is_validator(&vote.validator_pubkey) {
+                        total_vote_stake += validator.stake;
+                    }
                 }
             }
 
             // Threshold: > 2/3 of total stake
-            if total_stake > 0 && total_vote_stake as f64 > (total_stake as f64 * 0.6666) {
-                if self.finalized_head != *block_hash {
-                    tracing::info!("🎉 BLOCK FINALIZED: {} (Stake: {}/{})", block_hash, total_vote_stake, total_stake);
-                    
-                    // Update finalized head
-                    self.finalized_head = block_hash.to_string();
-                }
+            if total_stake > 0 && total_vote_stake * 3 > total_stake * 2 {
+                tracing::info!("🎉 BLOCK FINALIZED: {} (Stake: {}/{})", block_hash, total_vote_stake, total_stake);
+                self.finalize(&block);
             }
         }
     }
+
+    /// Re-check votes after the canonical chain changed, lowest slot first
+    fn update_finality(&mut self) {
+        let mut candidates: Vec<(u64, String)> = self.votes.iter()
+            .filter_map(|(hash, votes)| votes.first().map(|v| (v.slot, hash.clone())))
+            .collect();
+        candidates.sort();
+        for (_, hash) in candidates {
+            self.check_finality(&hash);
+        }
+    }
+
+    /// Finalize a canonical block, and with it all of its ancestors
+    fn finalize(&mut self, block: &Block) {
+        self.finalized_head = block.hash.clone();
+        if let Err(e) = self.storage.store_finalized(&block.hash) {
+            tracing::error!("Failed to persist finalized block: {}", e);
+        }
+
+        // Votes for final blocks are no longer needed
+        let finalized_slot = block.header.slot;
+        self.votes.retain(|_, votes| votes.iter().any(|v| v.slot > finalized_slot));
+    }
 }
 
 #[cfg(test)]
@@ -331,7 +765,24 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
     use crate::block::BlockHeader;
-    use crate::crypto::Crypto;
+    use crate::transaction::{TransactionData, MIN_TRANSACTION_FEE};
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    const CHAIN_ID: u32 = 1;
+
+    fn test_genesis_config() -> GenesisConfig {
+        // No block reward, so empty blocks leave the state root unchanged,
+        // a leader in every slot, and validators registered by each test
+        GenesisConfig {
+            genesis_time: 0,
+            initial_validators: vec![],
+            block_reward: 0,
+            active_slot_coeff_bps: crate::validator::ACTIVE_SLOT_COEFF_SCALE,
+            chain_id: Some(CHAIN_ID),
+            ..crate::config::AppConfig::default_devnet(9000).genesis
+        }
+    }
 
     fn create_genesis() -> Block {
         Block {
@@ -373,38 +824,84 @@ mod tests {
         }
     }
 
+    /// Register a validator directly in committed state, as genesis setup does
+    fn register_test_validator(chain: &mut Chain, pubkey: &[u8]) {
+        chain.state.add_validator(crate::validator::ValidatorInfo::new(pubkey.to_vec(), 1000, 0));
+        chain.state.apply_changes().unwrap();
+        chain.reload_validators();
+    }
+
     #[test]
     fn test_chain_init() {
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path()).unwrap();
         let genesis = create_genesis();
-        let chain = Chain::new(storage, genesis.clone(), 0);
+        let chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
 
         assert_eq!(chain.head, genesis.hash);
         assert_eq!(chain.get_height(), 0);
     }
 
+    #[test]
+    fn test_genesis_state_is_deterministic() {
+        let config = GenesisConfig {
+            initial_validators: vec!["validator_a_seed".to_string(), "validator_b_seed".to_string()],
+            ..test_genesis_config()
+        };
+        let genesis = create_genesis();
+
+        // Nodes running different validator keys start from the same state
+        let dir_a = TempDir::new().unwrap();
+        let dir_b = TempDir::new().unwrap();
+        let node_a = Chain::new(Storage::new(dir_a.path()).unwrap(), genesis.clone(), &config);
+        let node_b = Chain::new(Storage::new(dir_b.path()).unwrap(), genesis.clone(), &config);
+        assert_eq!(node_a.state.get_root_hash(), node_b.state.get_root_hash());
+        assert_eq!(node_a.validators.get_total_stake(), 2 * GENESIS_VALIDATOR_STAKE);
+
+        let validator = Crypto::validator_keypair_from_seed("validator_a_seed").public.to_bytes().to_vec();
+        let wallet = Crypto::wallet_key_from_seed("validator_a_seed").verifying_key().to_bytes().to_vec();
+        assert!(node_a.validators.is_validator(&validator));
+        assert_eq!(node_a.state.get_balance(&wallet), GENESIS_VALIDATOR_BALANCE);
+        drop(node_b);
+
+        // A restart keeps the committed state instead of allocating genesis again
+        let mut node_a = node_a;
+        node_a.state.slash_validator(&validator).unwrap();
+        node_a.state.set_balance(wallet.clone(), 0);
+        node_a.state.apply_changes().unwrap();
+        let root = node_a.state.get_root_hash();
+        drop(node_a);
+
+        let restarted = Chain::new(Storage::new(dir_a.path()).unwrap(), genesis, &config);
+        assert_eq!(restarted.state.get_root_hash(), root);
+        assert!(restarted.state.get_validator(&validator).unwrap().slashed);
+        assert_eq!(restarted.state.get_balance(&wallet), 0);
+    }
+
     #[test]
     fn test_add_block() {
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path()).unwrap();
         let genesis = create_genesis();
-        let mut chain = Chain::new(storage, genesis.clone(), 0);
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
 
         // Register a validator
         let keypair = Crypto::generate_keypair();
         let pubkey = keypair.public.to_bytes().to_vec();
-        chain.validators.register_validator(pubkey.clone(), 1000, 0).unwrap();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
 
         // Create a block
         let mut block = create_next_block(&genesis, 1);
         block.header.validator_pubkey = pubkey.clone();
         
         // Generate VRF
-        let seed = Consensus::compute_vrf_seed(&genesis.header.vrf_output, block.header.slot);
+        let seed = Consensus::compute_vrf_seed(&randomness, block.header.slot);
         let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
         block.header.vrf_output = vrf_preout.to_bytes().to_vec();
         block.header.vrf_proof = vrf_proof.to_bytes().to_vec();
+        block.header.state_root = chain.state.get_root_hash(); // No transactions, state unchanged
+        block.sign(&keypair);
 
         assert!(chain.add_block(block.clone()));
         
@@ -417,7 +914,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path()).unwrap();
         let genesis = create_genesis();
-        let mut chain = Chain::new(storage, genesis.clone(), 0);
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
 
         // Block with non-existent parent
         let mut block_invalid = create_next_block(&genesis, 1);
@@ -431,18 +928,19 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path()).unwrap();
         let genesis = create_genesis();
-        let mut chain = Chain::new(storage, genesis.clone(), 0);
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
 
         // Register a validator
         let keypair = Crypto::generate_keypair();
         let pubkey = keypair.public.to_bytes().to_vec();
-        chain.validators.register_validator(pubkey.clone(), 1000, 0).unwrap();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
 
         // Setup: Fund sender
-        let sender_pair = Crypto::generate_keypair();
-        let sender = sender_pair.public.to_bytes().to_vec();
-        let receiver_pair = Crypto::generate_keypair();
-        let receiver = receiver_pair.public.to_bytes().to_vec();
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let receiver_pair = SigningKey::generate(&mut OsRng);
+        let receiver = receiver_pair.verifying_key().to_bytes().to_vec();
 
         chain.state.set_balance(sender.clone(), 100);
         chain.state.apply_changes().unwrap();
@@ -451,15 +949,17 @@ mod tests {
         let mut block = create_next_block(&genesis, 1);
         block.header.validator_pubkey = pubkey.clone();
         
-        let seed = Consensus::compute_vrf_seed(&genesis.header.vrf_output, block.header.slot);
+        let seed = Consensus::compute_vrf_seed(&randomness, block.header.slot);
         let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
         block.header.vrf_output = vrf_preout.to_bytes().to_vec();
         block.header.vrf_proof = vrf_proof.to_bytes().to_vec();
         
-        let tx1 = Transaction::new(sender.clone(), receiver.clone(), crate::transaction::TransactionData::NativeTransfer { amount: 10 }, 0, &sender_pair); // Valid
-        let tx2 = Transaction::new(sender.clone(), receiver.clone(), crate::transaction::TransactionData::NativeTransfer { amount: 1000 }, 1, &sender_pair); // Invalid (nsf)
+        let tx1 = Transaction::new(sender.clone(), receiver.clone(), crate::transaction::TransactionData::NativeTransfer { amount: 10 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair); // Valid
+        let tx2 = Transaction::new(sender.clone(), receiver.clone(), crate::transaction::TransactionData::NativeTransfer { amount: 1000 }, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair); // Invalid (nsf)
 
         block.transactions = vec![tx1, tx2];
+        block.header.tx_root = Block::calculate_merkle_root(&block.transactions);
+        block.sign(&keypair);
 
         // Should fail
         assert!(!chain.add_block(block));
@@ -467,4 +967,668 @@ mod tests {
         // State should be unchanged (100)
         assert_eq!(chain.state.get_balance(&sender), 100);
     }
+
+    #[test]
+    fn test_fees_paid_to_producer() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+
+        // The validator signs blocks with a schnorrkel key and is paid at an Ed25519 wallet
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        let wallet_pair = SigningKey::generate(&mut OsRng);
+        let wallet = wallet_pair.verifying_key().to_bytes().to_vec();
+        chain.state.add_validator(crate::validator::ValidatorInfo::new(pubkey.clone(), 1000, 0).with_reward_address(wallet.clone()));
+        chain.state.apply_changes().unwrap();
+        chain.reload_validators();
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let receiver = SigningKey::generate(&mut OsRng).verifying_key().to_bytes().to_vec();
+        chain.state.set_balance(sender.clone(), 100);
+        chain.state.apply_changes().unwrap();
+
+        let mut block = create_next_block(&genesis, 1);
+        block.header.validator_pubkey = pubkey.clone();
+        let seed = Consensus::compute_vrf_seed(&randomness, block.header.slot);
+        let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+        block.header.vrf_output = vrf_preout.to_bytes().to_vec();
+        block.header.vrf_proof = vrf_proof.to_bytes().to_vec();
+
+        let tx = Transaction::new(sender.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 10 }, 0, 7, CHAIN_ID, &sender_pair);
+        let mut block = chain.build_block(block.header, vec![tx]);
+        block.sign(&keypair);
+
+        assert!(chain.add_block(block));
+
+        assert_eq!(chain.state.get_balance(&sender), 83);
+        assert_eq!(chain.state.get_balance(&receiver), 10);
+        assert_eq!(chain.state.get_balance(&wallet), 7);
+        assert_eq!(chain.state.get_balance(&pubkey), 0);
+
+        // The reward is spendable with the wallet key
+        let spend = Transaction::new(wallet.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 5 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &wallet_pair);
+        chain.process_transaction(&spend).unwrap();
+        assert_eq!(chain.state.get_balance(&wallet), 1);
+        assert_eq!(chain.state.get_balance(&receiver), 15);
+    }
+
+    #[test]
+    fn test_chain_id_from_genesis() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let config = GenesisConfig { chain_id: None, ..test_genesis_config() };
+        let mut chain = Chain::new(storage, genesis.clone(), &config);
+
+        // Derived from the genesis block unless configured
+        assert_eq!(chain.chain_id(), config.chain_id(&genesis.hash));
+        assert_ne!(config.chain_id(&genesis.hash), config.chain_id("other-genesis"));
+        assert_eq!(test_genesis_config().chain_id(&genesis.hash), CHAIN_ID);
+
+        // Transactions signed for another network are rejected
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        chain.state.set_balance(sender.clone(), 100);
+        chain.state.apply_changes().unwrap();
+        let foreign = Transaction::new(sender.clone(), vec![0xA; 32], TransactionData::NativeTransfer { amount: 1 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        assert!(chain.process_transaction(&foreign).is_err());
+        let local = Transaction::new(sender, vec![0xA; 32], TransactionData::NativeTransfer { amount: 1 }, 0, MIN_TRANSACTION_FEE, chain.chain_id(), &sender_pair);
+        assert!(chain.process_transaction(&local).is_ok());
+    }
+
+    #[test]
+    fn test_block_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut config = test_genesis_config();
+        config.limits.max_block_txs = 2;
+        let mut chain = Chain::new(storage, genesis.clone(), &config);
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        chain.state.set_balance(sender.clone(), 100);
+        chain.state.apply_changes().unwrap();
+
+        let mut header = create_next_block(&genesis, 1).header;
+        header.validator_pubkey = pubkey.clone();
+        let seed = Consensus::compute_vrf_seed(&randomness, header.slot);
+        let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+        header.vrf_output = vrf_preout.to_bytes().to_vec();
+        header.vrf_proof = vrf_proof.to_bytes().to_vec();
+
+        let txs: Vec<_> = (0..3)
+            .map(|nonce| Transaction::new(sender.clone(), vec![0xA; 32], TransactionData::NativeTransfer { amount: 1 }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair))
+            .collect();
+
+        // A block over the transaction limit is rejected
+        let mut oversized = Block::new(header.clone(), txs.clone());
+        oversized.sign(&keypair);
+        assert!(!chain.add_block(oversized));
+
+        // The producer only takes as many transactions as fit
+        let mut block = chain.build_block(header, txs);
+        block.sign(&keypair);
+        assert_eq!(block.transactions.len(), 2);
+        assert!(chain.add_block(block));
+    }
+
+    #[test]
+    fn test_state_root_enforced() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let receiver = SigningKey::generate(&mut OsRng).verifying_key().to_bytes().to_vec();
+        chain.state.set_balance(sender.clone(), 100);
+        chain.state.apply_changes().unwrap();
+        let root_before = chain.state.get_root_hash();
+
+        let make_header = |slot: u64| {
+            let mut header = create_next_block(&genesis, slot).header;
+            header.validator_pubkey = pubkey.clone();
+            let seed = Consensus::compute_vrf_seed(&randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            header
+        };
+
+        let valid = Transaction::new(sender.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 10 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        let overspend = Transaction::new(sender.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 1000 }, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+
+        // The producer leaves the failing transaction out and commits to the resulting root
+        let mut block = chain.build_block(make_header(3), vec![valid.clone(), overspend]);
+        block.sign(&keypair);
+        assert_eq!(block.transactions, vec![valid.clone()]);
+        assert_ne!(block.header.state_root, root_before);
+        assert_eq!(chain.state.get_root_hash(), root_before);
+
+        // Missing or wrong roots are rejected (separate slots to avoid equivocation)
+        for (slot, bad_root) in [(1, "".to_string()), (2, root_before.clone())] {
+            let mut bad_block = chain.build_block(make_header(slot), vec![valid.clone()]);
+            bad_block.header.state_root = bad_root;
+            bad_block.sign(&keypair);
+            assert!(!chain.add_block(bad_block));
+            assert_eq!(chain.state.get_root_hash(), root_before);
+        }
+
+        assert!(chain.add_block(block.clone()));
+        assert_eq!(chain.state.get_root_hash(), block.header.state_root);
+    }
+
+    #[test]
+    fn test_producer_signature_required() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let mut header = create_next_block(&genesis, 1).header;
+        header.validator_pubkey = pubkey.clone();
+        let seed = Consensus::compute_vrf_seed(&randomness, header.slot);
+        let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+        header.vrf_output = vrf_preout.to_bytes().to_vec();
+        header.vrf_proof = vrf_proof.to_bytes().to_vec();
+        let mut block = chain.build_block(header, vec![]);
+
+        // Unsigned
+        assert!(!chain.add_block(block.clone()));
+
+        // Re-wrapping the VRF proof under another key's signature
+        let mut forged = block.clone();
+        forged.sign(&Crypto::generate_keypair());
+        assert!(!chain.add_block(forged));
+
+        block.sign(&keypair);
+        assert!(chain.add_block(block));
+    }
+
+    #[test]
+    fn test_fork_state_and_reorg() {
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let receiver_a = vec![0xA; 32];
+        let receiver_b = vec![0xB; 32];
+        let genesis = create_genesis();
+
+        // Two nodes with the same genesis state: one follows the canonical chain, the other builds the fork
+        let new_chain = |temp_dir: &TempDir| {
+            let storage = Storage::new(temp_dir.path()).unwrap();
+            let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+            register_test_validator(&mut chain, &pubkey);
+            chain.state.set_balance(sender.clone(), 100);
+            chain.state.apply_changes().unwrap();
+            chain
+        };
+        let (temp_dir, fork_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut chain = new_chain(&temp_dir);
+        let mut fork_chain = new_chain(&fork_dir);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let make_header = |parent: &Block, slot: u64| {
+            let mut header = create_next_block(parent, slot).header;
+            header.validator_pubkey = pubkey.clone();
+            let seed = Consensus::compute_vrf_seed(&randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            header
+        };
+        // First header from `slot` on whose VRF output wins (or loses) an equal-weight tie against `rival`
+        let tie_header = |parent: &Block, mut slot: u64, rival: &BlockHeader, wins: bool| loop {
+            let header = make_header(parent, slot);
+            if (header.vrf_output < rival.vrf_output) == wins {
+                return header;
+            }
+            slot += 1;
+        };
+
+        let tx_a = Transaction::new(sender.clone(), receiver_a.clone(), TransactionData::NativeTransfer { amount: 10 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        let tx_b = Transaction::new(sender.clone(), receiver_b.clone(), TransactionData::NativeTransfer { amount: 20 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+
+        // Canonical block 1, and a child of it built before it gets orphaned
+        let mut block_1 = chain.build_block(make_header(&genesis, 1), vec![tx_a.clone()]);
+        block_1.sign(&keypair);
+        assert!(chain.add_block(block_1.clone()));
+        let mut block_2 = chain.build_block(make_header(&block_1, 2), vec![]);
+        block_2.sign(&keypair);
+        assert_eq!(chain.state.get_balance(&receiver_a), 10);
+
+        // Fork: one block that loses an equal-weight tie against block 1, then a child
+        // that wins the tie against block 2, plus a child with a bad state root
+        let mut fork_1 = fork_chain.build_block(tie_header(&genesis, 3, &block_1.header, false), vec![tx_b.clone()]);
+        fork_1.sign(&keypair);
+        assert!(fork_chain.add_block(fork_1.clone()));
+        let bad_slot = fork_1.header.slot + 1;
+        let mut bad_fork = fork_chain.build_block(make_header(&fork_1, bad_slot), vec![]);
+        bad_fork.header.state_root = genesis.hash.clone();
+        bad_fork.sign(&keypair);
+        let mut fork_2 = fork_chain.build_block(tie_header(&fork_1, bad_slot + 1, &block_2.header, true), vec![]);
+        fork_2.sign(&keypair);
+
+        // An equal-weight fork block that loses the tiebreak is stored without touching state,
+        // even though its slot is higher
+        assert!(chain.add_block(fork_1.clone()));
+        assert_eq!(chain.head, block_1.hash);
+        assert_eq!(chain.state.get_balance(&receiver_b), 0);
+
+        // A heavier fork that fails to execute leaves the canonical chain intact
+        assert!(!chain.add_block(bad_fork.clone()));
+        assert_eq!(chain.head, block_1.hash);
+        assert_eq!(chain.state.get_balance(&receiver_a), 10);
+        assert_eq!(chain.state.get_root_hash(), block_1.header.state_root);
+        assert!(chain.take_reverted_transactions().is_empty());
+
+        // A heavier valid fork reverts block 1 and applies the fork
+        assert!(chain.add_block(fork_2.clone()));
+        assert_eq!(chain.head, fork_2.hash);
+        assert_eq!(chain.block_weight(&fork_2.hash).unwrap(), 2);
+        assert_eq!(chain.state.get_balance(&receiver_a), 0);
+        assert_eq!(chain.state.get_balance(&receiver_b), 20);
+        assert_eq!(chain.state.get_balance(&sender), 79);
+        assert_eq!(chain.state.get_root_hash(), fork_2.header.state_root);
+        assert_eq!(chain.storage.get_block_by_height(1).unwrap(), None);
+        assert_eq!(chain.take_reverted_transactions(), vec![tx_a]);
+
+        // A block on the losing fork that only ties is stored without touching state
+        assert!(chain.add_block(block_2.clone()));
+        assert_eq!(chain.head, fork_2.hash);
+        assert_eq!(chain.state.get_balance(&receiver_a), 0);
+        assert!(chain.get_block(&block_2.hash).is_some());
+    }
+
+    #[test]
+    fn test_validator_set_survives_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage.clone(), genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        // A new validator registers its block-signing key through a transaction from its wallet
+        let wallet_pair = SigningKey::generate(&mut OsRng);
+        let wallet = wallet_pair.verifying_key().to_bytes().to_vec();
+        let candidate_pair = Crypto::generate_keypair();
+        let candidate = candidate_pair.public.to_bytes().to_vec();
+        chain.state.set_balance(wallet.clone(), 5000);
+        chain.state.apply_changes().unwrap();
+
+        let mut header = create_next_block(&genesis, 1).header;
+        header.validator_pubkey = pubkey.clone();
+        let seed = Consensus::compute_vrf_seed(&randomness, header.slot);
+        let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+        header.vrf_output = vrf_preout.to_bytes().to_vec();
+        header.vrf_proof = vrf_proof.to_bytes().to_vec();
+
+        let data = TransactionData::RegisterValidator {
+            stake: 2000,
+            validator_pubkey: candidate.clone(),
+            proof_of_possession: Crypto::prove_possession(&candidate_pair, &wallet),
+        };
+        let tx = Transaction::new(wallet.clone(), vec![], data, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &wallet_pair);
+        let mut block = chain.build_block(header, vec![tx]);
+        block.sign(&keypair);
+        assert!(chain.add_block(block.clone()));
+        assert_eq!(chain.validators.get_validator(&candidate).unwrap().stake, 2000);
+        assert_eq!(chain.validators.get_validator(&candidate).unwrap().reward_address, wallet);
+        drop(chain);
+
+        // Restarting from the same storage rebuilds the set
+        let mut chain = Chain::new(storage, genesis, &test_genesis_config());
+        assert_eq!(chain.head, block.hash);
+        assert_eq!(chain.validators.get_validator(&candidate).unwrap().stake, 2000);
+        assert!(chain.validators.is_validator(&pubkey));
+
+        // The new validator only joins at the next epoch boundary
+        assert!(!chain.validators.is_validator(&candidate));
+        assert_eq!(chain.validators.get_total_stake(), 1000);
+        let next_epoch = chain.validators_for_epoch(1);
+        assert!(next_epoch.is_validator(&candidate));
+        assert_eq!(next_epoch.get_total_stake(), 3000);
+        assert_eq!(chain.state.get_root_hash(), block.header.state_root);
+
+        // From then on it produces blocks with the key it registered
+        let mut header = create_next_block(&block, SLOTS_PER_EPOCH).header;
+        header.epoch = 1;
+        header.validator_pubkey = candidate.clone();
+        let seed = Consensus::compute_vrf_seed(&chain.epoch_randomness(1).unwrap(), header.slot);
+        let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&candidate_pair, &seed);
+        header.vrf_output = vrf_preout.to_bytes().to_vec();
+        header.vrf_proof = vrf_proof.to_bytes().to_vec();
+        let mut produced = chain.build_block(header, vec![]);
+        produced.sign(&candidate_pair);
+        assert!(chain.add_block(produced.clone()));
+        assert_eq!(chain.head, produced.hash);
+    }
+
+    #[test]
+    fn test_block_reward_schedule() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let config = GenesisConfig { block_reward: 50, reward_halving_epochs: 1, ..test_genesis_config() };
+        assert_eq!(config.block_reward_at(0), 50);
+        assert_eq!(config.block_reward_at(2), 12);
+        assert_eq!(config.block_reward_at(100), 0);
+        let mut chain = Chain::new(storage, genesis.clone(), &config);
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+
+        let make_block = |chain: &mut Chain, parent: &Block, slot: u64| {
+            let mut header = create_next_block(parent, slot).header;
+            header.epoch = slot / SLOTS_PER_EPOCH;
+            header.validator_pubkey = pubkey.clone();
+            let randomness = chain.epoch_randomness(header.epoch).unwrap();
+            let seed = Consensus::compute_vrf_seed(&randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            let mut block = chain.build_block(header, vec![]);
+            block.sign(&keypair);
+            block
+        };
+
+        // Epoch 0 pays the full reward, epoch 1 half of it
+        let block_1 = make_block(&mut chain, &genesis, 1);
+        assert_ne!(block_1.header.state_root, chain.state.get_root_hash());
+        assert!(chain.add_block(block_1.clone()));
+        assert_eq!(chain.state.get_balance(&pubkey), 50);
+
+        let block_2 = make_block(&mut chain, &block_1, SLOTS_PER_EPOCH);
+        assert!(chain.add_block(block_2));
+        assert_eq!(chain.state.get_balance(&pubkey), 75);
+    }
+
+    #[test]
+    fn test_equivocation_evidence_slashes_on_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let reporter_pair = SigningKey::generate(&mut OsRng);
+        let reporter = reporter_pair.verifying_key().to_bytes().to_vec();
+        chain.state.set_balance(reporter.clone(), 100);
+        chain.state.apply_changes().unwrap();
+
+        let make_header = |parent: &Block, slot: u64| {
+            let mut header = create_next_block(parent, slot).header;
+            header.validator_pubkey = pubkey.clone();
+            let seed = Consensus::compute_vrf_seed(&randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            header
+        };
+
+        let mut block_1 = chain.build_block(make_header(&genesis, 1), vec![]);
+        block_1.sign(&keypair);
+        assert!(chain.add_block(block_1.clone()));
+
+        // Re-signing the same header (e.g. after a restart) is not equivocation
+        let mut resigned = block_1.clone();
+        resigned.sign(&keypair);
+        assert_ne!(resigned.hash, block_1.hash);
+        chain.add_block(resigned);
+        assert!(chain.take_equivocation_evidence().is_empty());
+
+        // A second block for the same slot is rejected and kept as evidence
+        let mut conflicting_header = make_header(&genesis, 1);
+        conflicting_header.timestamp += 1;
+        let mut conflicting = Block::new(conflicting_header, vec![]);
+        conflicting.sign(&keypair);
+        assert!(!chain.add_block(conflicting.clone()));
+        let evidence = chain.take_equivocation_evidence();
+        assert_eq!(evidence, vec![(block_1.header.clone(), conflicting.header.clone())]);
+
+        // The validator is only slashed once the evidence is included in a block
+        assert!(chain.validators.is_validator(&pubkey));
+        let (header_a, header_b) = evidence.into_iter().next().unwrap();
+        let tx = Transaction::new(reporter.clone(), vec![], TransactionData::SubmitEquivocationEvidence { header_a: Box::new(header_a), header_b: Box::new(header_b) }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &reporter_pair);
+        let mut block_2 = chain.build_block(make_header(&block_1, 2), vec![tx.clone()]);
+        assert_eq!(block_2.transactions, vec![tx]);
+        block_2.sign(&keypair);
+        assert!(chain.add_block(block_2));
+
+        let slashed = chain.state.get_validator(&pubkey).unwrap();
+        assert!(slashed.slashed);
+        assert_eq!(slashed.stake, 0);
+        assert!(!chain.validators.is_validator(&pubkey));
+        assert_eq!(chain.validators.get_total_stake(), 0);
+
+        // Reporter gets 10% of the 1000 stake, the rest is burned
+        assert_eq!(chain.state.get_balance(&reporter), 100 - MIN_TRANSACTION_FEE + 100);
+    }
+
+    #[test]
+    fn test_finality_persists_and_blocks_reorgs() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage.clone(), genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+        let randomness = chain.epoch_randomness(0).unwrap();
+
+        let make_block = |chain: &mut Chain, parent: &Block, slot: u64| {
+            let mut header = create_next_block(parent, slot).header;
+            header.validator_pubkey = pubkey.clone();
+            let seed = Consensus::compute_vrf_seed(&randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            let mut block = chain.build_block(header, vec![]);
+            block.sign(&keypair);
+            block
+        };
+        let vote_for = |block: &Block| {
+            let context = schnorrkel::signing_context(b"nocostcoin-vote");
+            Vote {
+                block_hash: block.hash.clone(),
+                slot: block.header.slot,
+                validator_pubkey: pubkey.clone(),
+                signature: keypair.sign(context.bytes(block.hash.as_bytes())).to_bytes().to_vec(),
+            }
+        };
+
+        // A fork is built before the canonical chain is finalized
+        let fork_1 = make_block(&mut chain, &genesis, 5);
+        let fork_2 = make_block(&mut chain, &fork_1, 6);
+        let fork_3 = make_block(&mut chain, &fork_2, 7);
+
+        let block_1 = make_block(&mut chain, &genesis, 1);
+        assert!(chain.add_block(block_1.clone()));
+        let block_2 = make_block(&mut chain, &block_1, 2);
+        assert!(chain.add_block(block_2.clone()));
+
+        // The only validator votes: block 2 and its ancestors are final
+        assert!(chain.add_vote(vote_for(&block_2)));
+        assert_eq!(chain.finalized_head, block_2.hash);
+        assert!(chain.is_finalized(&block_1));
+        assert!(chain.votes.is_empty());
+
+        // A heavier fork from below the finalized block is refused
+        // (its first blocks are at most as heavy and may just be stored)
+        chain.add_block(fork_1);
+        chain.add_block(fork_2);
+        assert!(!chain.add_block(fork_3));
+        assert_eq!(chain.head, block_2.hash);
+
+        // Pending votes and the finalized block survive a restart
+        let block_3 = make_block(&mut chain, &block_2, 3);
+        assert!(chain.add_block(block_3.clone()));
+        drop(chain);
+        storage.store_vote(&vote_for(&block_3)).unwrap();
+
+        let chain = Chain::new(storage, genesis, &test_genesis_config());
+        assert_eq!(chain.finalized_head, block_2.hash);
+        assert_eq!(chain.finalized_slot(), 2);
+        assert_eq!(chain.votes.get(&block_3.hash).map(|v| v.len()), Some(1));
+    }
+
+    #[test]
+    fn test_epoch_randomness_frozen_at_boundary() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        let pubkey = keypair.public.to_bytes().to_vec();
+        register_test_validator(&mut chain, &pubkey);
+
+        let make_block = |chain: &mut Chain, parent: &Block, slot: u64| {
+            let mut header = create_next_block(parent, slot).header;
+            header.epoch = slot / SLOTS_PER_EPOCH;
+            header.validator_pubkey = pubkey.clone();
+            let randomness = chain.block_randomness(&parent.hash).unwrap().advance_to(header.epoch).randomness;
+            let seed = Consensus::compute_vrf_seed(&randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(&keypair, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            let mut block = chain.build_block(header, vec![]);
+            block.sign(&keypair);
+            block
+        };
+
+        let epoch_0 = chain.epoch_randomness(0).unwrap();
+        assert_eq!(epoch_0, Consensus::genesis_randomness(&genesis.hash));
+
+        // Epoch 1 randomness mixes every VRF output of epoch 0
+        let block_1 = make_block(&mut chain, &genesis, 1);
+        assert!(chain.add_block(block_1.clone()));
+        let mix_0 = Consensus::mix_vrf_output(&[], &block_1.header.vrf_output);
+        let epoch_1 = Consensus::next_epoch_randomness(&epoch_0, &mix_0, 1);
+        assert_eq!(chain.epoch_randomness(1).unwrap(), epoch_1);
+
+        // Blocks in epoch 1 don't change its randomness, only the next epoch's
+        let epoch_2_before = chain.epoch_randomness(2).unwrap();
+        let block_2 = make_block(&mut chain, &block_1, SLOTS_PER_EPOCH);
+        assert!(chain.add_block(block_2.clone()));
+        assert_eq!(chain.epoch_randomness(1).unwrap(), epoch_1);
+        assert_ne!(chain.epoch_randomness(2).unwrap(), epoch_2_before);
+
+        // A sibling of block 2 shares its ancestry, so sees the same epoch 1 randomness
+        // (the fork has equal weight, so pick a slot where it wins the VRF tiebreak)
+        let fork = (SLOTS_PER_EPOCH + 1..)
+            .map(|slot| make_block(&mut chain, &block_1, slot))
+            .find(|fork| fork.header.vrf_output < block_2.header.vrf_output)
+            .unwrap();
+        assert!(chain.add_block(fork.clone()));
+        assert_eq!(chain.head, fork.hash);
+        assert_eq!(chain.epoch_randomness(1).unwrap(), epoch_1);
+        let mix = chain.block_randomness(&fork.hash).unwrap();
+        assert_eq!(mix, RandomnessMix { epoch: 1, randomness: epoch_1, value: Consensus::mix_vrf_output(&[], &fork.header.vrf_output) });
+    }
+
+    #[test]
+    fn test_fork_across_epoch_boundary() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let genesis = create_genesis();
+        let mut chain = Chain::new(storage, genesis.clone(), &test_genesis_config());
+
+        let keypair = Crypto::generate_keypair();
+        register_test_validator(&mut chain, &keypair.public.to_bytes());
+        let outsider = Crypto::generate_keypair();
+
+        // Seeds the VRF with the given epoch randomness; empty blocks keep the state root
+        let make_block = |chain: &mut Chain, parent: &Block, slot: u64, producer: &schnorrkel::Keypair, randomness: &[u8]| {
+            let mut header = create_next_block(parent, slot).header;
+            header.epoch = slot / SLOTS_PER_EPOCH;
+            header.validator_pubkey = producer.public.to_bytes().to_vec();
+            let seed = Consensus::compute_vrf_seed(randomness, slot);
+            let (vrf_preout, vrf_proof) = Crypto::vrf_sign(producer, &seed);
+            header.vrf_output = vrf_preout.to_bytes().to_vec();
+            header.vrf_proof = vrf_proof.to_bytes().to_vec();
+            let mut block = chain.build_block(header, vec![]);
+            block.sign(producer);
+            block
+        };
+        let randomness_after = |chain: &Chain, parent: &Block, epoch: u64| {
+            chain.block_randomness(&parent.hash).unwrap().advance_to(epoch).randomness
+        };
+
+        // The chains split in epoch 0: block 1 is canonical, fork 1 loses the tiebreak
+        let epoch_0 = chain.epoch_randomness(0).unwrap();
+        let block_1 = make_block(&mut chain, &genesis, 1, &keypair, &epoch_0);
+        assert!(chain.add_block(block_1.clone()));
+        let fork_1 = (2..)
+            .map(|slot| make_block(&mut chain, &genesis, slot, &keypair, &epoch_0))
+            .find(|fork| fork.header.vrf_output > block_1.header.vrf_output)
+            .unwrap();
+        assert!(chain.add_block(fork_1.clone()));
+        assert_eq!(chain.head, block_1.hash);
+
+        // Each side freezes its own epoch 0 mix into the epoch 1 randomness
+        let canonical_1 = randomness_after(&chain, &block_1, 1);
+        let fork_randomness = randomness_after(&chain, &fork_1, 1);
+        assert_ne!(canonical_1, fork_randomness);
+        let block_2 = make_block(&mut chain, &block_1, SLOTS_PER_EPOCH, &keypair, &canonical_1);
+        assert!(chain.add_block(block_2.clone()));
+
+        // An epoch 1 block on the fork is seeded from the fork's randomness, not the head's
+        let fork_2 = (SLOTS_PER_EPOCH + 1..)
+            .map(|slot| make_block(&mut chain, &fork_1, slot, &keypair, &fork_randomness))
+            .find(|fork| fork.header.vrf_output > block_2.header.vrf_output)
+            .unwrap();
+        let wrong_seed = make_block(&mut chain, &fork_1, fork_2.header.slot, &keypair, &canonical_1);
+        assert!(!chain.add_block(wrong_seed));
+        assert!(chain.add_block(fork_2.clone()));
+        assert_eq!(chain.head, block_2.hash);
+
+        // Fork blocks from unknown producers are not even stored, heavier or not
+        let outsider_side = make_block(&mut chain, &fork_1, fork_2.header.slot + 1, &outsider, &fork_randomness);
+        assert!(!chain.add_block(outsider_side.clone()));
+        assert!(chain.get_block(&outsider_side.hash).is_none());
+        let outsider_block = make_block(&mut chain, &fork_2, fork_2.header.slot + 1, &outsider, &fork_randomness);
+        assert!(!chain.add_block(outsider_block.clone()));
+        assert!(chain.get_block(&outsider_block.hash).is_none());
+        assert_eq!(chain.head, block_2.hash);
+
+        // A heavier fork wins and carries its own epoch 1 randomness
+        let fork_3 = make_block(&mut chain, &fork_2, fork_2.header.slot + 2, &keypair, &fork_randomness);
+        assert!(chain.add_block(fork_3.clone()));
+        assert_eq!(chain.head, fork_3.hash);
+        assert_eq!(chain.epoch_randomness(1).unwrap(), fork_randomness);
+        assert_eq!(chain.epoch_randomness(2).unwrap(), randomness_after(&chain, &fork_3, 2));
+    }
 }
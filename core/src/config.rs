@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 use chrono::Utc;
+use crate::consensus::BlockLimits;
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AppConfig {
@@ -18,11 +20,73 @@ pub struct NetworkConfig {
     pub listen_addr: String,
 }
 
+/// Stake bonded for every initial validator at genesis
+pub const GENESIS_VALIDATOR_STAKE: u64 = 1_000_000;
+/// Spendable balance given to every initial validator's wallet at genesis
+pub const GENESIS_VALIDATOR_BALANCE: u64 = 1_000_000;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GenesisConfig {
     pub genesis_time: i64,
     pub genesis_seed: String,
     pub initial_validators: Vec<String>, // List of seeds for initial validators
+    #[serde(default = "default_unbonding_epochs")]
+    pub unbonding_epochs: u64, // Epochs withdrawn stake stays locked and slashable
+    #[serde(default = "default_channel_challenge_slots")]
+    pub channel_challenge_slots: u64, // Slots a unilateral channel close can be challenged for
+    #[serde(default = "default_block_reward")]
+    pub block_reward: u64, // Newly issued coins paid to each block producer
+    #[serde(default = "default_reward_halving_epochs")]
+    pub reward_halving_epochs: u64, // Block reward halves every this many epochs (0 = never)
+    #[serde(default = "default_active_slot_coeff_bps")]
+    pub active_slot_coeff_bps: u64, // Expected share of slots with a leader, in basis points
+    #[serde(default)]
+    pub limits: BlockLimits, // Block and transaction size limits
+    #[serde(default)]
+    pub chain_id: Option<u32>, // Signed into every transaction; derived from the genesis block if unset
+}
+
+fn default_unbonding_epochs() -> u64 {
+    crate::validator::DEFAULT_UNBONDING_EPOCHS
+}
+
+fn default_channel_challenge_slots() -> u64 {
+    crate::state::DEFAULT_CHANNEL_CHALLENGE_SLOTS
+}
+
+fn default_block_reward() -> u64 {
+    10
+}
+
+fn default_reward_halving_epochs() -> u64 {
+    8760 // ~1 year of 1 hour epochs
+}
+
+fn default_active_slot_coeff_bps() -> u64 {
+    5000 // A block every other slot on average
+}
+
+impl GenesisConfig {
+    /// Block reward for blocks produced in `epoch`
+    pub fn block_reward_at(&self, epoch: u64) -> u64 {
+        if self.reward_halving_epochs == 0 {
+            return self.block_reward;
+        }
+        let halvings = epoch / self.reward_halving_epochs;
+        if halvings >= 64 {
+            return 0;
+        }
+        self.block_reward >> halvings
+    }
+
+    /// Chain ID of the network started from `genesis_hash`
+    /// The configured one if set, otherwise the first 4 bytes of the hashed genesis block hash.
+    pub fn chain_id(&self, genesis_hash: &str) -> u32 {
+        self.chain_id.unwrap_or_else(|| {
+            let digest = Sha256::digest(genesis_hash.as_bytes());
+            u32::from_le_bytes(digest[..4].try_into().expect("digest is 32 bytes"))
+        })
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -53,6 +117,13 @@ impl AppConfig {
                     "nocostcoin_node_9001_seed".to_string(),
                     "nocostcoin_node_9002_seed".to_string(),
                 ],
+                unbonding_epochs: default_unbonding_epochs(),
+                channel_challenge_slots: default_channel_challenge_slots(),
+                block_reward: default_block_reward(),
+                reward_halving_epochs: default_reward_halving_epochs(),
+                active_slot_coeff_bps: default_active_slot_coeff_bps(),
+                limits: BlockLimits::default(),
+                chain_id: None,
             },
             mining: MiningConfig {
                 enabled: true,
@@ -1,20 +1,85 @@
-use crate::block::Block;
+use crate::block::{Block, BlockHeader};
 use sha2::Digest;
 use chrono::Utc;
 use crate::validator::ValidatorSet;
+use serde::{Deserialize, Serialize};
 
 
 pub const SLOT_DURATION_MS: u64 = 2000; // 2 seconds
 pub const SLOTS_PER_EPOCH: u64 = 1800; // 1 hour / 2 seconds = 1800 slots
+pub const MAX_FUTURE_SLOTS: u64 = 2; // Clock drift tolerance for blocks ahead of local time
 
+/// Consensus limits on block and transaction size, set in genesis
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct BlockLimits {
+    pub max_block_bytes: u64, // Serialized size of the whole block
+    pub max_block_txs: u64,
+    pub max_tx_metadata_bytes: u64, // Metadata blob carried by a single transaction
+    pub max_extra_witnesses: u64,
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        Self {
+            max_block_bytes: 2 * 1024 * 1024,
+            max_block_txs: 1000,
+            max_tx_metadata_bytes: 4096,
+            max_extra_witnesses: 16,
+        }
+    }
+}
+
+
+/// Epoch randomness as of one block: its epoch's randomness and the running
+/// mix of the VRF outputs of the epoch's blocks along that block's ancestry
+/// The mix is frozen into the next epoch's randomness once the epoch is over.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RandomnessMix {
+    pub epoch: u64,
+    pub randomness: Vec<u8>, // Randomness of `epoch`, used as the VRF seed input
+    pub value: Vec<u8>,
+}
+
+impl RandomnessMix {
+    /// Mix as of the genesis block
+    pub fn genesis(genesis_hash: &str) -> Self {
+        Self { epoch: 0, randomness: Consensus::genesis_randomness(genesis_hash), value: vec![] }
+    }
+
+    /// Mix seen by a descendant in `epoch`, before any of that epoch's blocks
+    /// Every epoch passed on the way is frozen with the mix it ended with.
+    pub fn advance_to(&self, epoch: u64) -> Self {
+        let mut mix = self.clone();
+        while mix.epoch < epoch {
+            mix.randomness = Consensus::next_epoch_randomness(&mix.randomness, &mix.value, mix.epoch + 1);
+            mix.epoch += 1;
+            mix.value = vec![];
+        }
+        mix
+    }
+
+    /// Mix as of a child block: advanced to its epoch with its VRF output folded in
+    pub fn child(&self, header: &BlockHeader) -> Self {
+        let mut mix = self.advance_to(header.slot / SLOTS_PER_EPOCH);
+        mix.value = Consensus::mix_vrf_output(&mix.value, &header.vrf_output);
+        mix
+    }
+}
 
 pub struct Consensus {
     genesis_time: i64,
+    active_slot_coeff_bps: u64,
+    limits: BlockLimits,
 }
 
 impl Consensus {
-    pub fn new(genesis_time: i64) -> Self {
-        Self { genesis_time }
+    pub fn new(genesis_time: i64, active_slot_coeff_bps: u64, limits: BlockLimits) -> Self {
+        Self { genesis_time, active_slot_coeff_bps, limits }
+    }
+
+    pub fn limits(&self) -> &BlockLimits {
+        &self.limits
     }
 
     pub fn get_current_slot(&self) -> u64 {
@@ -29,30 +94,39 @@ impl Consensus {
         slot / SLOTS_PER_EPOCH
     }
 
-    // Fork Choice Rule: Longest Chain (Highest Slot) with VRF Tiebreaker
-    // 1. Higher slot always wins
-    // 2. For equal slots, lower VRF output wins (more difficult)
-    pub fn is_better_block(new_block: &Block, current_head: &Block) -> bool {
-        // Rule 1: Higher slot wins
-        if new_block.header.slot > current_head.header.slot {
-             return true;
+    // Fork Choice Rule: Heaviest Chain with VRF Tiebreaker
+    // Weight is the number of blocks in the chain. Every candidate descends from the
+    // last finalized block, so this compares the blocks built since that checkpoint.
+    // 1. Higher weight always wins
+    // 2. For equal weight, lower VRF output wins (more difficult)
+    pub fn is_better_block(new_block: &Block, new_weight: u64, current_head: &Block, head_weight: u64) -> bool {
+        // Rule 1: Heavier chain wins
+        if new_weight != head_weight {
+            return new_weight > head_weight;
         }
-        
-        // Rule 2: For equal slots, use VRF output as tiebreaker (lower wins)
-        if new_block.header.slot == current_head.header.slot {
-            return new_block.header.vrf_output < current_head.header.vrf_output;
-        }
-        
-        // Lower slot loses
-        false
+
+        // Rule 2: For equal weight, use VRF output as tiebreaker (lower wins)
+        new_block.header.vrf_output < current_head.header.vrf_output
     }
 
     // get_proposer_rank removed for Secret Leader Election
 
+    /// Whether a VRF output wins the slot for a validator, with this chain's active slot coefficient
+    pub fn is_slot_leader(&self, validators: &ValidatorSet, pubkey: &[u8], vrf_output: &[u8]) -> bool {
+        validators.is_slot_leader(pubkey, vrf_output, self.active_slot_coeff_bps)
+    }
+
 
 
     /// Validate a block against the consensus rules
-    pub fn validate_block(&self, block: &Block, parent: &Block, validators: &ValidatorSet) -> Result<(), String> {
+    /// `epoch_randomness` is the randomness of the block's epoch along its ancestry and
+    /// `validators` the epoch's set as of the parent; without it the caller checks leadership later.
+    pub fn validate_block(&self, block: &Block, parent: &Block, validators: Option<&ValidatorSet>, epoch_randomness: &[u8]) -> Result<(), String> {
+        // 0. Header Integrity
+        if block.hash != Block::calculate_hash(&block.header) {
+            return Err("Block hash does not match header".to_string());
+        }
+
         // 1. Parent Continuity
         if block.header.parent_hash != parent.hash {
             return Err("Parent hash mismatch".to_string());
@@ -63,6 +137,29 @@ impl Consensus {
             return Err(format!("Slot must be greater than parent slot. Block: {}, Parent: {}", block.header.slot, parent.header.slot));
         }
 
+        // 2a. No blocks from the future (beyond clock drift)
+        let current_slot = self.get_current_slot();
+        if block.header.slot > current_slot + MAX_FUTURE_SLOTS {
+            return Err(format!("Block slot {} is too far ahead of current slot {}", block.header.slot, current_slot));
+        }
+
+        // 2b. Epoch must follow from the slot
+        if block.header.epoch != block.header.slot / SLOTS_PER_EPOCH {
+            return Err(format!("Epoch {} does not match slot {}", block.header.epoch, block.header.slot));
+        }
+
+        // 2c. Size Limits
+        if block.transactions.len() as u64 > self.limits.max_block_txs {
+            return Err(format!("Block has {} transactions, limit is {}", block.transactions.len(), self.limits.max_block_txs));
+        }
+        if block.header.extra_witnesses.len() as u64 > self.limits.max_extra_witnesses {
+            return Err(format!("Block has {} extra witnesses, limit is {}", block.header.extra_witnesses.len(), self.limits.max_extra_witnesses));
+        }
+        let block_bytes = block.size();
+        if block_bytes > self.limits.max_block_bytes {
+            return Err(format!("Block is {} bytes, limit is {}", block_bytes, self.limits.max_block_bytes));
+        }
+
         // 3. VRF Eligibility Check (Secret Leader Election)
         // Public Key & VRF Proof Verification
          let pubkey = schnorrkel::PublicKey::from_bytes(&block.header.validator_pubkey)
@@ -72,16 +169,22 @@ impl Consensus {
          let vrf_proof = schnorrkel::vrf::VRFProof::from_bytes(&block.header.vrf_proof)
             .map_err(|_| "Invalid VRF proof".to_string())?;
          
-         // Re-compute seed from parent to ensure they used the right input
-         let seed = Self::compute_vrf_seed(&parent.header.vrf_output, block.header.slot);
+         // Re-compute seed from the epoch randomness to ensure they used the right input
+         let seed = Self::compute_vrf_seed(epoch_randomness, block.header.slot);
          
          if !crate::crypto::Crypto::vrf_verify(&pubkey, &seed, &vrf_preout, &vrf_proof) {
              return Err("Invalid VRF signature/proof".to_string());
          }
 
+        // 3b. Producer Signature (binds the VRF proof to this exact header)
+        let signing_hash = Block::signing_hash(&block.header);
+        if !crate::crypto::Crypto::verify_block_signature(&pubkey, &signing_hash, &block.header.producer_signature) {
+            return Err("Invalid producer signature".to_string());
+        }
+
         // 4. Stake-Weighted Threshold Check (Did they allowably win?)
         // This replaces the Rank check.
-        if !validators.is_slot_leader(&block.header.validator_pubkey, &block.header.vrf_output) {
+        if validators.is_some_and(|v| !self.is_slot_leader(v, &block.header.validator_pubkey, &block.header.vrf_output)) {
             return Err("Validator VRF output did not meets the stake-weighted threshold (Not a leader)".to_string());
         }
 
@@ -94,18 +197,45 @@ impl Consensus {
         Ok(())
     }
     
-    pub fn compute_vrf_seed(parent_vrf_output: &[u8], slot: u64) -> Vec<u8> {
+    /// VRF input for a slot
+    /// Depends only on randomness frozen before the epoch began, so a producer
+    /// cannot grind its own VRF output to bias later slots of the epoch.
+    pub fn compute_vrf_seed(epoch_randomness: &[u8], slot: u64) -> Vec<u8> {
         let mut hasher = sha2::Sha256::new();
-        hasher.update(parent_vrf_output);
+        hasher.update(epoch_randomness);
         hasher.update(slot.to_le_bytes());
         hasher.finalize().to_vec()
     }
+
+    /// Randomness of epoch 0, derived from the genesis block
+    pub fn genesis_randomness(genesis_hash: &str) -> Vec<u8> {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(b"nocostcoin-epoch-randomness");
+        hasher.update(genesis_hash.as_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// Fold a block's VRF output into its epoch's mix
+    pub fn mix_vrf_output(mix: &[u8], vrf_output: &[u8]) -> Vec<u8> {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(mix);
+        hasher.update(vrf_output);
+        hasher.finalize().to_vec()
+    }
+
+    /// Randomness of `epoch` from the previous epoch's randomness and VRF mix
+    pub fn next_epoch_randomness(previous: &[u8], mix: &[u8], epoch: u64) -> Vec<u8> {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(previous);
+        hasher.update(mix);
+        hasher.update(epoch.to_le_bytes());
+        hasher.finalize().to_vec()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::block::BlockHeader;
 
     fn create_dummy_block(slot: u64, vrf_output: Vec<u8>) -> Block {
         Block {
@@ -131,7 +261,7 @@ mod tests {
 
     #[test]
     fn test_epoch_calculation() {
-        let consensus = Consensus::new(0);
+        let consensus = Consensus::new(0, 5000, BlockLimits::default());
         assert_eq!(consensus.get_epoch(0), 0);
         assert_eq!(consensus.get_epoch(1799), 0);
         assert_eq!(consensus.get_epoch(1800), 1);
@@ -139,16 +269,53 @@ mod tests {
     }
 
     #[test]
-    fn test_fork_choice_longest_chain() {
-        let block_a = create_dummy_block(10, vec![0]);
-        let block_b = create_dummy_block(11, vec![0]);
-        
-        // Higher slot wins
-        assert!(Consensus::is_better_block(&block_b, &block_a));
-        assert!(!Consensus::is_better_block(&block_a, &block_b));
+    fn test_fork_choice_heaviest_chain() {
+        let block_a = create_dummy_block(10, vec![1]);
+        let block_b = create_dummy_block(500, vec![0]);
+
+        // More blocks win, however far ahead the other tip's slot is
+        assert!(Consensus::is_better_block(&block_a, 5, &block_b, 4));
+        assert!(!Consensus::is_better_block(&block_b, 4, &block_a, 5));
+
+        // Equal weight: lower VRF output wins
+        assert!(Consensus::is_better_block(&block_b, 5, &block_a, 5));
+        assert!(!Consensus::is_better_block(&block_a, 5, &block_b, 5));
     }
 
+    #[test]
+    fn test_reject_future_slot() {
+        let consensus = Consensus::new(Utc::now().timestamp_millis(), 5000, BlockLimits::default());
+        let parent = create_dummy_block(0, vec![]);
+        let validators = ValidatorSet::new();
+
+        let mut block = create_dummy_block(MAX_FUTURE_SLOTS + 10, vec![]);
+        block.header.epoch = 0;
+        block.header.parent_hash = parent.hash.clone();
+        block.hash = Block::calculate_hash(&block.header);
+        let err = consensus.validate_block(&block, &parent, Some(&validators), &[]).unwrap_err();
+        assert!(err.contains("too far ahead"), "{}", err);
+
+        // Within the drift tolerance the block gets as far as the VRF checks
+        block.header.slot = 1;
+        block.hash = Block::calculate_hash(&block.header);
+        let err = consensus.validate_block(&block, &parent, Some(&validators), &[]).unwrap_err();
+        assert!(!err.contains("too far ahead"), "{}", err);
+    }
 
+    #[test]
+    fn test_vrf_seed_ignores_parent_output() {
+        let randomness = Consensus::genesis_randomness("genesis");
 
+        // The seed only depends on the epoch randomness and the slot
+        assert_eq!(Consensus::compute_vrf_seed(&randomness, 5), Consensus::compute_vrf_seed(&randomness, 5));
+        assert_ne!(Consensus::compute_vrf_seed(&randomness, 5), Consensus::compute_vrf_seed(&randomness, 6));
 
+        // Each VRF output folded into the mix changes the next epoch's randomness
+        let mix_a = Consensus::mix_vrf_output(&[], &[1; 32]);
+        let mix_b = Consensus::mix_vrf_output(&mix_a, &[2; 32]);
+        assert_ne!(
+            Consensus::next_epoch_randomness(&randomness, &mix_a, 1),
+            Consensus::next_epoch_randomness(&randomness, &mix_b, 1)
+        );
+    }
 }
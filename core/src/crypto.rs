@@ -1,4 +1,5 @@
-use schnorrkel::{Keypair, PublicKey, vrf::{VRFPreOut, VRFProof}};
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey, PublicKey, vrf::{VRFPreOut, VRFProof}};
+use ed25519_dalek::SigningKey;
 use rand::rngs::OsRng;
 
 pub struct Crypto;
@@ -8,6 +9,27 @@ impl Crypto {
         Keypair::generate_with(OsRng)
     }
 
+    /// Deterministic validator key from a seed string (testnets and genesis validators)
+    pub fn validator_keypair_from_seed(seed_str: &str) -> Keypair {
+        let mini_secret = MiniSecretKey::from_bytes(&Self::seed_bytes(seed_str))
+            .expect("32 bytes is a valid mini secret");
+        mini_secret.expand(ExpansionMode::Ed25519).to_keypair()
+    }
+
+    /// Deterministic Ed25519 wallet key from the same seed string as the validator key
+    pub fn wallet_key_from_seed(seed_str: &str) -> SigningKey {
+        SigningKey::from_bytes(&Self::seed_bytes(seed_str))
+    }
+
+    // Seed strings are used as raw key material, zero padded or cut to 32 bytes
+    fn seed_bytes(seed_str: &str) -> [u8; 32] {
+        let mut seed = [0u8; 32];
+        for (i, &b) in seed_str.as_bytes().iter().enumerate().take(32) {
+            seed[i] = b;
+        }
+        seed
+    }
+
     // VRF Sign: Returns (Output, Proof)
     // Input is usually the seed (e.g., previous block VRF output or epoch randomness)
     pub fn vrf_sign(keypair: &Keypair, input: &[u8]) -> (VRFPreOut, VRFProof) {
@@ -28,4 +50,35 @@ impl Crypto {
             .vrf_verify(context.bytes(input), output, proof)
             .is_ok()
     }
+
+    // Block Sign: producer signature over the block signing hash
+    pub fn sign_block(keypair: &Keypair, message: &[u8]) -> Vec<u8> {
+        let context = schnorrkel::signing_context(b"nocostcoin-block");
+        keypair.sign(context.bytes(message)).to_bytes().to_vec()
+    }
+
+    // Proof of possession: the validator key signs the account registering it,
+    // so nobody can bond a key they don't hold or reuse another account's proof
+    pub fn prove_possession(keypair: &Keypair, owner: &[u8]) -> Vec<u8> {
+        let context = schnorrkel::signing_context(b"nocostcoin-validator-pop");
+        keypair.sign(context.bytes(owner)).to_bytes().to_vec()
+    }
+
+    // Proof of possession Verify
+    pub fn verify_possession(public_key: &PublicKey, owner: &[u8], proof: &[u8]) -> bool {
+        let context = schnorrkel::signing_context(b"nocostcoin-validator-pop");
+        match schnorrkel::Signature::from_bytes(proof) {
+            Ok(sig) => public_key.verify(context.bytes(owner), &sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // Block Verify
+    pub fn verify_block_signature(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+        let context = schnorrkel::signing_context(b"nocostcoin-block");
+        match schnorrkel::Signature::from_bytes(signature) {
+            Ok(sig) => public_key.verify(context.bytes(message), &sig).is_ok(),
+            Err(_) => false,
+        }
+    }
 }
@@ -8,6 +8,7 @@ pub mod validator;
 pub mod storage;
 pub mod network;
 pub mod mempool;
+pub mod micropayment;
 pub mod sync;
 pub mod wallet;
 pub mod vote;
@@ -1,39 +1,136 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::cmp::Reverse;
 use crate::transaction::Transaction;
 use crate::state::State;
+use crate::consensus::BlockLimits;
+
+/// Maximum number of future-nonce transactions held per sender
+pub const MAX_QUEUED_PER_SENDER: usize = 64;
+
+/// Per-sender transaction queues, keyed by nonce
+#[derive(Debug, Clone, Default)]
+struct SenderQueue {
+    pending: BTreeMap<u64, Transaction>, // Contiguous from the account nonce, ready for a block
+    queued: BTreeMap<u64, Transaction>,  // Waiting for a nonce gap to be filled
+}
+
+impl SenderQueue {
+    /// Nonce the sender's next transaction should use
+    fn next_nonce(&self, state_nonce: u64) -> u64 {
+        self.pending.keys().next_back().map(|n| n + 1).unwrap_or(state_nonce)
+    }
+
+    fn get(&self, nonce: u64) -> Option<&Transaction> {
+        self.pending.get(&nonce).or_else(|| self.queued.get(&nonce))
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len() + self.queued.len()
+    }
+
+    /// Add a transaction whose nonce is at least the account nonce
+    /// Returns whether it was added as a new entry rather than replacing one.
+    fn insert(&mut self, tx: Transaction, state_nonce: u64, is_full: bool) -> Result<bool, String> {
+        // Replace-by-fee for an already known nonce
+        if let Some(existing) = self.get(tx.nonce) {
+            if existing.hash() == tx.hash() {
+                return Err("Transaction already in mempool".to_string());
+            }
+            if tx.fee <= existing.fee {
+                return Err(format!("Replacement transaction underpriced. Existing fee: {}, New fee: {}", existing.fee, tx.fee));
+            }
+            let slot = self.pending.get_mut(&tx.nonce)
+                .or_else(|| self.queued.get_mut(&tx.nonce))
+                .expect("nonce present in sender queue");
+            *slot = tx;
+            return Ok(false);
+        }
+
+        if is_full {
+            return Err("Mempool is full".to_string());
+        }
+
+        if tx.nonce == self.next_nonce(state_nonce) {
+            self.pending.insert(tx.nonce, tx);
+            // The new transaction may close a gap
+            self.reorganize(state_nonce);
+        } else {
+            if self.queued.len() >= MAX_QUEUED_PER_SENDER {
+                return Err(format!("Too many queued transactions for sender (max {})", MAX_QUEUED_PER_SENDER));
+            }
+            self.queued.insert(tx.nonce, tx);
+        }
+        Ok(true)
+    }
+
+    /// Drop transactions below the account nonce and re-split the rest
+    /// into the contiguous pending run and the queued remainder
+    /// Returns the number of dropped transactions.
+    fn reorganize(&mut self, state_nonce: u64) -> usize {
+        let mut all = std::mem::take(&mut self.pending);
+        all.append(&mut self.queued);
+
+        let current = all.split_off(&state_nonce);
+        let dropped = all.len();
+        let mut expected = state_nonce;
+        for (nonce, tx) in current {
+            if nonce == expected {
+                self.pending.insert(nonce, tx);
+                expected += 1;
+            } else {
+                self.queued.insert(nonce, tx);
+            }
+        }
+        dropped
+    }
+}
 
 /// Transaction Mempool to store unconfirmed transactions
 #[derive(Debug, Clone)]
 pub struct Mempool {
-    pub transactions: HashMap<Vec<u8>, Transaction>, // Hash -> Transaction
+    senders: HashMap<Vec<u8>, SenderQueue>, // Sender -> nonce-ordered queues
+    size: usize,
     capacity: usize,
+    chain_id: u32, // Only transactions signed for this network are accepted
+    limits: BlockLimits, // Transactions must fit these to ever be included
+    current_slot: u64, // Latest slot seen by evict_expired
 }
 
 impl Mempool {
     /// Create a new Mempool with a given capacity
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, chain_id: u32, limits: BlockLimits) -> Self {
         Self {
-            transactions: HashMap::new(),
+            senders: HashMap::new(),
+            size: 0,
             capacity,
+            chain_id,
+            limits,
+            current_slot: 0,
         }
     }
 
     /// Add a transaction to the mempool
+    ///
+    /// A transaction reusing a nonce already in the pool replaces the
+    /// existing one only if it pays a strictly higher fee.
     pub fn add_transaction(&mut self, tx: Transaction, state: &State) -> Result<(), String> {
-        // 1. Check capacity
-        if self.transactions.len() >= self.capacity {
-            return Err("Mempool is full".to_string());
+        // 1. Basic Validation (Signature)
+        tx.validate(self.chain_id, &self.limits, state.get_multisig(&tx.sender).as_ref())?;
+        if tx.is_expired(self.current_slot) {
+            return Err(format!("Transaction expired after slot {}", tx.valid_until_slot.unwrap_or_default()));
         }
 
-        // 2. Basic Validation (Signature)
-        tx.validate()?;
+        // 2. Check Balance (based on transaction type, fee always applies)
+        let balance = state.get_balance(&tx.sender);
+        if balance < tx.fee {
+            return Err(format!("Insufficient balance for fee. Available: {}, Required: {}", balance, tx.fee));
+        }
 
-        // 3. Check Balance (based on transaction type)
         match &tx.data {
             crate::transaction::TransactionData::NativeTransfer { amount } => {
-                let balance = state.get_balance(&tx.sender);
-                if balance < *amount {
-                    return Err(format!("Insufficient balance. Available: {}, Required: {}", balance, amount));
+                let required = amount.saturating_add(tx.fee);
+                if balance < required {
+                    return Err(format!("Insufficient balance. Available: {}, Required: {}", balance, required));
                 }
             },
             crate::transaction::TransactionData::TransferAsset { asset_id, amount } => {
@@ -44,94 +141,316 @@ impl Mempool {
                       return Err(format!("Insufficient asset balance. Available: {}, Required: {}", balance, amount));
                  }
             },
+            crate::transaction::TransactionData::ApproveAsset { asset_id, .. } => {
+                state.get_asset(asset_id).ok_or("Asset does not exist")?;
+            },
+            crate::transaction::TransactionData::TransferAssetFrom { asset_id, owner, amount } => {
+                let allowance = state.get_asset_allowance(owner, asset_id, &tx.sender);
+                if allowance < *amount {
+                    return Err(format!("Amount exceeds asset allowance. Remaining: {}, Requested: {}", allowance, amount));
+                }
+                let owner_account = state.get_account(owner).ok_or("Owner account not found")?;
+                let balance = owner_account.assets.get(asset_id).cloned().unwrap_or(0);
+                if balance < *amount {
+                    return Err(format!("Insufficient asset balance. Available: {}, Required: {}", balance, amount));
+                }
+            },
+            crate::transaction::TransactionData::BurnAsset { asset_id, amount } => {
+                let account = state.get_account(&tx.sender).ok_or("Sender account not found")?;
+                let balance = account.assets.get(asset_id).cloned().unwrap_or(0);
+                if balance < *amount {
+                    return Err(format!("Insufficient asset balance. Available: {}, Required: {}", balance, amount));
+                }
+            },
+            crate::transaction::TransactionData::MintAsset { asset_id, .. }
+            | crate::transaction::TransactionData::SetAssetAdmin { asset_id, .. }
+            | crate::transaction::TransactionData::FreezeAsset { asset_id }
+            | crate::transaction::TransactionData::UnfreezeAsset { asset_id } => {
+                let asset = state.get_asset(asset_id).ok_or("Asset does not exist")?;
+                if asset.admin != tx.sender {
+                    return Err("Only the asset admin can do this".to_string());
+                }
+            },
             crate::transaction::TransactionData::CreateAsset { .. } => {
                 // Creation might have a fee in the future
             },
+            crate::transaction::TransactionData::CreateOrder { offer: crate::transaction::OrderAsset::Native { amount }, .. } => {
+                let required = amount.saturating_add(tx.fee);
+                if balance < required {
+                    return Err(format!("Insufficient balance for order escrow. Available: {}, Required: {}", balance, required));
+                }
+            },
+            crate::transaction::TransactionData::FillOrder { order_id } => {
+                let order = state.get_order(order_id).ok_or("Order does not exist")?;
+                if order.is_closed {
+                    return Err("Order already closed".to_string());
+                }
+                // The taker pays the ask straight to the maker
+                match &order.ask {
+                    crate::transaction::OrderAsset::Native { amount } => {
+                        let required = amount.saturating_add(tx.fee);
+                        if balance < required {
+                            return Err(format!("Insufficient balance for order ask. Available: {}, Required: {}", balance, required));
+                        }
+                    }
+                    crate::transaction::OrderAsset::Asset { asset_id, amount } => {
+                        let account = state.get_account(&tx.sender).ok_or("Sender account not found")?;
+                        let asset_balance = account.assets.get(asset_id).cloned().unwrap_or(0);
+                        if asset_balance < *amount {
+                            return Err(format!("Insufficient asset balance. Available: {}, Required: {}", asset_balance, amount));
+                        }
+                    }
+                    crate::transaction::OrderAsset::NFT { collection_id, item_id } => {
+                        state.nft_for_mover(&tx.sender, collection_id, *item_id)?;
+                    }
+                }
+            },
+            crate::transaction::TransactionData::CancelOrder { order_id } => {
+                let order = state.get_order(order_id).ok_or("Order does not exist")?;
+                if order.is_closed {
+                    return Err("Order already closed".to_string());
+                }
+            },
             crate::transaction::TransactionData::OpenChannel { amount, .. } => {
-                let balance = state.get_balance(&tx.sender);
-                if balance < *amount {
-                    return Err(format!("Insufficient balance for channel deposit. Available: {}, Required: {}", balance, amount));
+                let required = amount.saturating_add(tx.fee);
+                if balance < required {
+                    return Err(format!("Insufficient balance for channel deposit. Available: {}, Required: {}", balance, required));
                 }
             },
-            crate::transaction::TransactionData::CloseChannel { channel_id, .. } => {
-                 if !state.channels.contains_key(channel_id) {
+            crate::transaction::TransactionData::CloseChannel { channel_id, .. }
+            | crate::transaction::TransactionData::StartChannelClose { channel_id, .. }
+            | crate::transaction::TransactionData::ChallengeChannelClose { channel_id, .. }
+            | crate::transaction::TransactionData::SettleChannel { channel_id } => {
+                 if state.get_channel(channel_id).is_none() {
                      return Err("Channel does not exist".to_string());
                  }
             },
              crate::transaction::TransactionData::DelegateSpend { .. } => {
                  // Nothing to check against state for setting up delegate
             },
+            crate::transaction::TransactionData::DelegatedTransfer { owner, amount } => {
+                let delegation = state.get_delegation(owner, &tx.sender).ok_or("No active delegation from owner")?;
+                if delegation.allowance < *amount {
+                    return Err(format!("Amount exceeds delegated allowance. Remaining: {}, Requested: {}", delegation.allowance, amount));
+                }
+                let owner_balance = state.get_balance(owner);
+                if owner_balance < *amount {
+                    return Err(format!("Insufficient owner balance. Available: {}, Required: {}", owner_balance, amount));
+                }
+            },
+            crate::transaction::TransactionData::BurnNFT { collection_id, item_id } => {
+                // Approved addresses and operators may burn as well as the owner
+                state.nft_for_mover(&tx.sender, collection_id, *item_id)?;
+            },
+            crate::transaction::TransactionData::CreateMultisig { signers, threshold } => {
+                let address = crate::transaction::Transaction::multisig_address(signers, *threshold);
+                if state.get_multisig(&address).is_some() {
+                    return Err("Multisig account already exists".to_string());
+                }
+            },
+            crate::transaction::TransactionData::SubmitEquivocationEvidence { header_a, .. } => {
+                if state.get_validator(&header_a.validator_pubkey).is_none() {
+                    return Err("Offender is not a validator".to_string());
+                }
+            },
             _ => {
                 // Other types logic
             }
         }
 
-        // 4. Check Nonce
-        // For now, we just check if nonce is >= state nonce.
-        // A more advanced implementation would track pending nonces.
+        // 3. Check Nonce
         let state_nonce = state.get_nonce(&tx.sender);
         if tx.nonce < state_nonce {
             return Err(format!("Invalid nonce. State nonce: {}, Tx nonce: {}", state_nonce, tx.nonce));
         }
 
-        // 5. Add to pool
-        // We use the signature as a unique ID for now, or we could hash the tx.
-        // Since Transaction doesn't have a hash field, we can use the signature bytes.
-        self.transactions.insert(tx.signature.clone(), tx);
-        
-        Ok(())
+        let sender = tx.sender.clone();
+        let queue = self.senders.entry(sender.clone()).or_default();
+        self.size -= queue.reorganize(state_nonce);
+
+        // 4. Replace-by-fee for a known nonce, otherwise add within capacity
+        let is_full = self.size >= self.capacity;
+        match queue.insert(tx, state_nonce, is_full) {
+            Ok(added) => {
+                if added {
+                    self.size += 1;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if queue.len() == 0 {
+                    self.senders.remove(&sender);
+                }
+                Err(e)
+            }
+        }
     }
 
     /// Get transactions to include in a block
-    /// Returns a list of transactions ordered by nonce for each sender
+    /// Highest fee first, while keeping each sender's transactions in nonce order.
+    /// Only pending (gap-free) transactions are returned.
     pub fn get_transactions_for_block(&self, limit: usize) -> Vec<Transaction> {
-        let mut txs: Vec<Transaction> = self.transactions.values().cloned().collect();
-        
-        // Simple ordering: just take the first 'limit' transactions
-        // In a real system, we'd order by fee, then nonce.
-        // Here we just sort by nonce to ensure we don't include out-of-order txs if possible
-        txs.sort_by_key(|tx| tx.nonce);
-        
-        txs.into_iter().take(limit).collect()
+        let mut by_sender: HashMap<&[u8], _> = self.senders
+            .iter()
+            .map(|(sender, queue)| (sender.as_slice(), queue.pending.values().peekable()))
+            .collect();
+
+        // Only the next transaction of every sender is a candidate.
+        // Ties are broken by sender so selection is deterministic.
+        let mut heap = BinaryHeap::new();
+        for (sender, txs) in by_sender.iter_mut() {
+            if let Some(tx) = txs.peek() {
+                heap.push((tx.fee, Reverse(*sender)));
+            }
+        }
+
+        let mut selected = Vec::new();
+        while selected.len() < limit {
+            let Some((_, Reverse(sender))) = heap.pop() else { break };
+            let txs = by_sender.get_mut(sender).expect("sender queued in heap");
+            if let Some(tx) = txs.next() {
+                selected.push(tx.clone());
+            }
+            if let Some(next) = txs.peek() {
+                heap.push((next.fee, Reverse(sender)));
+            }
+        }
+
+        selected
+    }
+
+    /// Next nonce a sender should use, accounting for its pending transactions
+    pub fn get_pending_nonce(&self, sender: &[u8], state: &State) -> u64 {
+        let state_nonce = state.get_nonce(sender);
+        self.senders
+            .get(sender)
+            .map(|queue| {
+                // Ignore pending entries already made stale by a new block
+                let mut expected = state_nonce;
+                while queue.pending.contains_key(&expected) {
+                    expected += 1;
+                }
+                expected
+            })
+            .unwrap_or(state_nonce)
     }
 
     /// Remove transactions that have been included in a block
     pub fn remove_transactions(&mut self, txs: &[Transaction]) {
         for tx in txs {
-            self.transactions.remove(&tx.signature);
+            if let Some(queue) = self.senders.get_mut(&tx.sender) {
+                let removed = queue.pending.remove(&tx.nonce).or_else(|| queue.queued.remove(&tx.nonce));
+                if removed.is_some() {
+                    self.size -= 1;
+                }
+                if queue.len() == 0 {
+                    self.senders.remove(&tx.sender);
+                }
+            }
+        }
+    }
+
+    /// Re-align every sender's queues with the latest account nonces:
+    /// drop transactions that can no longer be included and promote
+    /// queued transactions whose nonce gap has been closed
+    pub fn sync_with_state(&mut self, state: &State) {
+        for (sender, queue) in self.senders.iter_mut() {
+            queue.reorganize(state.get_nonce(sender));
         }
+        self.senders.retain(|_, queue| queue.len() > 0);
+        self.size = self.senders.values().map(|queue| queue.len()).sum();
+    }
+
+    /// Drop transactions that can no longer be included from `slot` on
+    /// Later transactions of the same senders are moved back to the queue until
+    /// the nonce gap is filled. Returns the number of evicted transactions.
+    pub fn evict_expired(&mut self, slot: u64, state: &State) -> usize {
+        self.current_slot = slot;
+        let before = self.size;
+        for (sender, queue) in self.senders.iter_mut() {
+            let live = |_: &u64, tx: &mut Transaction| !tx.is_expired(slot);
+            let count = queue.len();
+            queue.pending.retain(live);
+            queue.queued.retain(live);
+            if queue.len() != count {
+                queue.reorganize(state.get_nonce(sender));
+            }
+        }
+        self.senders.retain(|_, queue| queue.len() > 0);
+        self.size = self.senders.values().map(|queue| queue.len()).sum();
+        before - self.size
+    }
+
+    /// Look up a transaction by its hash
+    pub fn get_transaction(&self, hash: &[u8]) -> Option<Transaction> {
+        self.senders
+            .values()
+            .flat_map(|queue| queue.pending.values().chain(queue.queued.values()))
+            .find(|tx| tx.hash() == hash)
+            .cloned()
+    }
+
+    /// Get all transactions sent by an address, in nonce order
+    pub fn get_by_sender(&self, sender: &[u8]) -> Vec<Transaction> {
+        self.senders
+            .get(sender)
+            .map(|queue| queue.pending.values().chain(queue.queued.values()).cloned().collect())
+            .unwrap_or_default()
     }
 
     /// Get current size of mempool
     pub fn len(&self) -> usize {
-        self.transactions.len()
+        self.size
+    }
+
+    /// Check if the mempool holds no transactions
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Number of transactions ready for inclusion
+    pub fn pending_len(&self) -> usize {
+        self.senders.values().map(|queue| queue.pending.len()).sum()
+    }
+
+    /// Number of transactions waiting on a nonce gap
+    pub fn queued_len(&self) -> usize {
+        self.senders.values().map(|queue| queue.queued.len()).sum()
     }
 
     /// Get all transactions in the mempool
     pub fn get_all(&self) -> Vec<Transaction> {
-        self.transactions.values().cloned().collect()
+        self.senders
+            .values()
+            .flat_map(|queue| queue.pending.values().chain(queue.queued.values()))
+            .cloned()
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::Crypto;
+    use crate::transaction::{TransactionData, MIN_TRANSACTION_FEE};
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
     use crate::storage::Storage;
     use tempfile::TempDir;
 
+    const CHAIN_ID: u32 = 1;
+
     #[test]
     fn test_add_transaction() {
-        let mut mempool = Mempool::new(10);
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path()).unwrap();
         let mut state = State::new(storage);
         
-        let sender_keypair = Crypto::generate_keypair();
-        let receiver_keypair = Crypto::generate_keypair();
+        let sender_keypair = SigningKey::generate(&mut OsRng);
+        let receiver_keypair = SigningKey::generate(&mut OsRng);
         
-        let sender_addr = sender_keypair.public.to_bytes().to_vec();
-        let receiver_addr = receiver_keypair.public.to_bytes().to_vec();
+        let sender_addr = sender_keypair.verifying_key().to_bytes().to_vec();
+        let receiver_addr = receiver_keypair.verifying_key().to_bytes().to_vec();
         
         // Fund sender
         state.set_balance(sender_addr.clone(), 100);
@@ -142,10 +461,259 @@ mod tests {
             receiver_addr.clone(),
             crate::transaction::TransactionData::NativeTransfer { amount: 50 },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &sender_keypair
         );
         
         assert!(mempool.add_transaction(tx.clone(), &state).is_ok());
         assert_eq!(mempool.len(), 1);
     }
+
+    #[test]
+    fn test_block_selection_prefers_higher_fee() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let cheap_pair = SigningKey::generate(&mut OsRng);
+        let cheap = cheap_pair.verifying_key().to_bytes().to_vec();
+        let rich_pair = SigningKey::generate(&mut OsRng);
+        let rich = rich_pair.verifying_key().to_bytes().to_vec();
+        let receiver = vec![7, 7, 7];
+
+        state.set_balance(cheap.clone(), 1000);
+        state.set_balance(rich.clone(), 1000);
+
+        let cheap_tx = Transaction::new(cheap.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 10 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &cheap_pair);
+        let rich_tx_0 = Transaction::new(rich.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 10 }, 0, 5, CHAIN_ID, &rich_pair);
+        // Higher fee but must still come after the sender's nonce 0
+        let rich_tx_1 = Transaction::new(rich.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 10 }, 1, 50, CHAIN_ID, &rich_pair);
+
+        mempool.add_transaction(cheap_tx.clone(), &state).unwrap();
+        mempool.add_transaction(rich_tx_0.clone(), &state).unwrap();
+        mempool.add_transaction(rich_tx_1.clone(), &state).unwrap();
+
+        let selected = mempool.get_transactions_for_block(10);
+        assert_eq!(selected, vec![rich_tx_0.clone(), rich_tx_1, cheap_tx]);
+
+        let selected = mempool.get_transactions_for_block(1);
+        assert_eq!(selected, vec![rich_tx_0]);
+    }
+
+    #[test]
+    fn test_reject_insufficient_balance_for_fee() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 100);
+
+        // Amount alone fits, amount + fee does not
+        let tx = Transaction::new(sender, vec![7, 7, 7], TransactionData::NativeTransfer { amount: 100 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        assert!(mempool.add_transaction(tx, &state).is_err());
+    }
+
+    #[test]
+    fn test_gapped_nonce_is_queued_until_filled() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+
+        let tx_0 = Transaction::new(sender.clone(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        let tx_2 = Transaction::new(sender.clone(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, 2, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        let tx_1 = Transaction::new(sender.clone(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+
+        mempool.add_transaction(tx_0.clone(), &state).unwrap();
+        mempool.add_transaction(tx_2.clone(), &state).unwrap();
+        assert_eq!((mempool.pending_len(), mempool.queued_len()), (1, 1));
+        assert_eq!(mempool.get_transactions_for_block(10), vec![tx_0.clone()]);
+        assert_eq!(mempool.get_pending_nonce(&sender, &state), 1);
+
+        // Filling the gap promotes the queued transaction
+        mempool.add_transaction(tx_1.clone(), &state).unwrap();
+        assert_eq!((mempool.pending_len(), mempool.queued_len()), (3, 0));
+        assert_eq!(mempool.get_transactions_for_block(10), vec![tx_0, tx_1, tx_2]);
+        assert_eq!(mempool.get_pending_nonce(&sender, &state), 3);
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+
+        let original = Transaction::new(sender.clone(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, 0, 5, CHAIN_ID, &sender_pair);
+        let same_fee = Transaction::new(sender.clone(), vec![8, 8, 8], TransactionData::NativeTransfer { amount: 10 }, 0, 5, CHAIN_ID, &sender_pair);
+        let higher_fee = Transaction::new(sender.clone(), vec![8, 8, 8], TransactionData::NativeTransfer { amount: 10 }, 0, 6, CHAIN_ID, &sender_pair);
+
+        mempool.add_transaction(original.clone(), &state).unwrap();
+        assert!(mempool.add_transaction(original, &state).is_err());
+        assert!(mempool.add_transaction(same_fee, &state).is_err());
+        mempool.add_transaction(higher_fee.clone(), &state).unwrap();
+
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.get_all(), vec![higher_fee]);
+    }
+
+    #[test]
+    fn test_sync_with_state_drops_stale_nonces() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+
+        let mut txs = Vec::new();
+        for nonce in [0, 1, 3] {
+            let tx = Transaction::new(sender.clone(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+            mempool.add_transaction(tx.clone(), &state).unwrap();
+            txs.push(tx);
+        }
+
+        // Nonces 0 and 1 get included in a block
+        state.apply_transaction(&txs[0]).unwrap();
+        state.apply_transaction(&txs[1]).unwrap();
+
+        mempool.sync_with_state(&state);
+        assert_eq!(mempool.len(), 1);
+        assert_eq!((mempool.pending_len(), mempool.queued_len()), (0, 1));
+        assert_eq!(mempool.get_pending_nonce(&sender, &state), 2);
+    }
+
+    #[test]
+    fn test_stale_entries_free_capacity() {
+        let mut mempool = Mempool::new(2, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let other_pair = SigningKey::generate(&mut OsRng);
+        let other = other_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+        state.set_balance(other.clone(), 1000);
+
+        let transfer = |keypair: &SigningKey, nonce| Transaction::new(keypair.verifying_key().to_bytes().to_vec(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair);
+        let txs = [transfer(&sender_pair, 0), transfer(&sender_pair, 1)];
+        for tx in &txs {
+            mempool.add_transaction(tx.clone(), &state).unwrap();
+        }
+
+        // A rejected sender does not leave an empty queue behind
+        assert_eq!(mempool.add_transaction(transfer(&other_pair, 0), &state).err().unwrap(), "Mempool is full");
+        assert!(!mempool.senders.contains_key(&other));
+
+        // Both transactions get included before the mempool is synced;
+        // the sender's next transaction drops them and takes their room
+        state.apply_transaction(&txs[0]).unwrap();
+        state.apply_transaction(&txs[1]).unwrap();
+        mempool.add_transaction(transfer(&sender_pair, 2), &state).unwrap();
+        assert_eq!(mempool.len(), 1);
+        mempool.add_transaction(transfer(&other_pair, 0), &state).unwrap();
+        assert_eq!(mempool.len(), 2);
+    }
+
+    #[test]
+    fn test_expired_transactions_evicted() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let mut state = State::new(storage);
+
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+
+        let transfer = |nonce, valid_until_slot| {
+            let mut tx = Transaction::new(sender.clone(), vec![7, 7, 7], TransactionData::NativeTransfer { amount: 10 }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+            tx.valid_until_slot = valid_until_slot;
+            tx.sign(&sender_pair);
+            tx
+        };
+        mempool.add_transaction(transfer(0, Some(5)), &state).unwrap();
+        mempool.add_transaction(transfer(1, None), &state).unwrap();
+
+        // Nonce 0 expires: nonce 1 waits for a replacement
+        assert_eq!(mempool.evict_expired(5, &state), 0);
+        assert_eq!(mempool.evict_expired(6, &state), 1);
+        assert_eq!((mempool.pending_len(), mempool.queued_len()), (0, 1));
+
+        // Expired transactions are not admitted
+        assert!(mempool.add_transaction(transfer(0, Some(5)), &state).is_err());
+        mempool.add_transaction(transfer(0, Some(6)), &state).unwrap();
+        assert_eq!((mempool.pending_len(), mempool.queued_len()), (2, 0));
+    }
+
+    #[test]
+    fn test_operator_nft_burn_accepted() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = State::new(Storage::new(temp_dir.path()).unwrap());
+        let owner_pair = SigningKey::generate(&mut OsRng);
+        let owner = owner_pair.verifying_key().to_bytes().to_vec();
+        let operator_pair = SigningKey::generate(&mut OsRng);
+        let operator = operator_pair.verifying_key().to_bytes().to_vec();
+        let stranger_pair = SigningKey::generate(&mut OsRng);
+        let stranger = stranger_pair.verifying_key().to_bytes().to_vec();
+        for address in [&owner, &operator, &stranger] {
+            state.set_balance(address.clone(), 100);
+        }
+
+        let tx = |keypair: &SigningKey, data, nonce| Transaction::new(keypair.verifying_key().to_bytes().to_vec(), vec![], data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair);
+        let create_tx = tx(&owner_pair, TransactionData::CreateCollection { name: "Art".into(), symbol: "ART".into(), metadata: vec![], max_items: None }, 0);
+        state.apply_transaction(&create_tx).unwrap();
+        let collection_id = create_tx.calculate_asset_id().unwrap();
+        state.apply_transaction(&tx(&owner_pair, TransactionData::MintNFT { collection_id: collection_id.clone(), item_id: 1, item_metadata: vec![], recipient: owner.clone() }, 1)).unwrap();
+        state.apply_transaction(&tx(&owner_pair, TransactionData::ApproveNFT { collection_id: collection_id.clone(), item_id: None, operator, approved: true }, 2)).unwrap();
+        state.apply_changes().unwrap();
+
+        let burn = TransactionData::BurnNFT { collection_id, item_id: 1 };
+        assert_eq!(mempool.add_transaction(tx(&stranger_pair, burn.clone(), 0), &state).err().unwrap(), "Sender does not own this NFT");
+        assert!(mempool.add_transaction(tx(&operator_pair, burn, 0), &state).is_ok());
+    }
+
+    #[test]
+    fn test_fill_order_requires_affordable_ask() {
+        let mut mempool = Mempool::new(10, CHAIN_ID, BlockLimits::default());
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = State::new(Storage::new(temp_dir.path()).unwrap());
+        let maker_pair = SigningKey::generate(&mut OsRng);
+        let taker_pair = SigningKey::generate(&mut OsRng);
+        let poor_pair = SigningKey::generate(&mut OsRng);
+        state.set_balance(maker_pair.verifying_key().to_bytes().to_vec(), 100);
+        state.set_balance(taker_pair.verifying_key().to_bytes().to_vec(), 100);
+        state.set_balance(poor_pair.verifying_key().to_bytes().to_vec(), 10);
+
+        let tx = |keypair: &SigningKey, data, nonce| Transaction::new(keypair.verifying_key().to_bytes().to_vec(), vec![], data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair);
+        let create_asset = tx(&maker_pair, TransactionData::CreateAsset { name: "Gold".into(), symbol: "GLD".into(), supply: 100, decimals: 0, metadata: vec![], max_supply: None }, 0);
+        state.apply_transaction(&create_asset).unwrap();
+        let offer = crate::transaction::OrderAsset::Asset { asset_id: create_asset.calculate_asset_id().unwrap(), amount: 30 };
+        let ask = crate::transaction::OrderAsset::Native { amount: 50 };
+        let create_order = tx(&maker_pair, TransactionData::CreateOrder { offer, ask, taker: None, expiry: 100 }, 1);
+        state.apply_transaction(&create_order).unwrap();
+        state.apply_changes().unwrap();
+
+        let fill = TransactionData::FillOrder { order_id: create_order.calculate_asset_id().unwrap() };
+        assert_eq!(mempool.add_transaction(tx(&poor_pair, fill.clone(), 0), &state).err().unwrap(), "Insufficient balance for order ask. Available: 10, Required: 51");
+        assert!(mempool.add_transaction(tx(&taker_pair, fill, 0), &state).is_ok());
+    }
 }
@@ -0,0 +1,265 @@
+use crate::state::PaymentChannel;
+use crate::storage::Storage;
+use crate::transaction::BalanceProof;
+use crate::wallet::Wallet;
+
+/// Streams payments through open payment channels off-chain
+///
+/// The opener (partner A) pays by issuing a new state signed by itself; the recipient
+/// (partner B) checks it, counter-signs and keeps it. Every state has a higher sequence
+/// number and moves funds towards B only. Only co-signed states are stored, and the latest
+/// one is what either partner settles on-chain: with `CloseChannel` once a final state is
+/// agreed, or alone with `StartChannelClose` if the other partner stops responding.
+#[derive(Clone)]
+pub struct MicropaymentManager {
+    storage: Storage,
+}
+
+impl MicropaymentManager {
+    pub fn new(storage: Storage) -> Self {
+        Self { storage }
+    }
+
+    /// Latest state stored for a channel, if any payment went through it
+    pub fn latest_state(&self, channel_id: &[u8]) -> Result<Option<BalanceProof>, String> {
+        self.storage.get_channel_state(channel_id)
+    }
+
+    /// Payer side: issue the next state, moving `amount` more to the recipient
+    /// The returned state carries only the payer's signature and is stored once `confirm`
+    /// gets it back counter-signed.
+    pub fn pay(&self, wallet: &Wallet, channel: &PaymentChannel, amount: u64, is_final: bool, current_slot: u64) -> Result<BalanceProof, String> {
+        if wallet.get_address_bytes() != channel.partner_a {
+            return Err("Only the channel opener can pay through it".to_string());
+        }
+        Self::check_open(channel, current_slot)?;
+
+        let latest = self.current_state(channel)?;
+        if latest.balance_a < amount {
+            return Err(format!("Insufficient channel balance. Available: {}, Required: {}", latest.balance_a, amount));
+        }
+
+        let mut proof = BalanceProof::new(
+            channel.id.clone(),
+            latest.sequence + 1,
+            latest.balance_a - amount,
+            latest.balance_b + amount,
+            is_final,
+        );
+        proof.sign_a(&wallet.keypair);
+        Ok(proof)
+    }
+
+    /// Recipient side: validate a state issued by the payer, counter-sign and store it
+    pub fn receive(&self, wallet: &Wallet, channel: &PaymentChannel, mut proof: BalanceProof, current_slot: u64) -> Result<BalanceProof, String> {
+        if wallet.get_address_bytes() != channel.partner_b {
+            return Err("Only the channel recipient can accept payments".to_string());
+        }
+        Self::check_open(channel, current_slot)?;
+        Self::check_state(channel, &proof)?;
+        proof.verify_a(&channel.partner_a)?;
+
+        let latest = self.current_state(channel)?;
+        if proof.sequence <= latest.sequence {
+            return Err(format!("Channel state sequence {} is not newer than {}", proof.sequence, latest.sequence));
+        }
+        if proof.balance_b < latest.balance_b {
+            return Err(format!("Channel state lowers the recipient balance from {} to {}", latest.balance_b, proof.balance_b));
+        }
+
+        proof.sign_b(&wallet.keypair);
+        self.storage.store_channel_state(&proof)?;
+        Ok(proof)
+    }
+
+    /// Payer side: store the counter-signed copy of a state issued by `pay`
+    pub fn confirm(&self, channel: &PaymentChannel, proof: BalanceProof) -> Result<(), String> {
+        Self::check_state(channel, &proof)?;
+        // Partner A's signature is ours, so the state was issued by `pay`
+        proof.verify(&channel.partner_a, &channel.partner_b)?;
+
+        let latest = self.current_state(channel)?;
+        if proof.sequence <= latest.sequence {
+            return Err(format!("Channel state sequence {} is not newer than {}", proof.sequence, latest.sequence));
+        }
+
+        self.storage.store_channel_state(&proof)
+    }
+
+    /// Final co-signed state to settle the channel with `CloseChannel`
+    pub fn closing_state(&self, channel: &PaymentChannel) -> Result<BalanceProof, String> {
+        let proof = self.latest_state(&channel.id)?.ok_or("No payments made through this channel")?;
+        if !proof.is_final {
+            return Err("Latest channel state is not final".to_string());
+        }
+        proof.verify(&channel.partner_a, &channel.partner_b)?;
+        Ok(proof)
+    }
+
+    /// State to start a unilateral close with: the latest co-signed one, or none to refund
+    /// the opener, which is also the only close left once the channel has expired
+    pub fn unilateral_close_state(&self, channel: &PaymentChannel, current_slot: u64) -> Result<Option<BalanceProof>, String> {
+        if current_slot > channel.expiry {
+            return Ok(None);
+        }
+        let proof = self.latest_state(&channel.id)?;
+        if let Some(proof) = &proof {
+            proof.verify(&channel.partner_a, &channel.partner_b)?;
+        }
+        Ok(proof)
+    }
+
+    /// Co-signed state newer than the one a pending unilateral close would pay out
+    pub fn challenge_state(&self, channel: &PaymentChannel) -> Result<BalanceProof, String> {
+        let closing = channel.closing.as_ref().ok_or("Channel is not closing")?;
+        let proof = self.latest_state(&channel.id)?.ok_or("No payments made through this channel")?;
+        if proof.sequence <= closing.sequence {
+            return Err(format!("No channel state newer than the pending close (sequence {})", closing.sequence));
+        }
+        proof.verify(&channel.partner_a, &channel.partner_b)?;
+        Ok(proof)
+    }
+
+    /// Latest stored state, or the opening one with the whole deposit on the payer's side
+    fn current_state(&self, channel: &PaymentChannel) -> Result<BalanceProof, String> {
+        let latest = self.latest_state(&channel.id)?
+            .unwrap_or_else(|| BalanceProof::new(channel.id.clone(), 0, channel.total_deposit, 0, false));
+
+        if latest.is_final {
+            return Err("Channel state is already final".to_string());
+        }
+        Ok(latest)
+    }
+
+    fn check_open(channel: &PaymentChannel, current_slot: u64) -> Result<(), String> {
+        if channel.is_closed {
+            return Err("Channel already closed".to_string());
+        }
+        if channel.closing.is_some() {
+            return Err("Channel is closing".to_string());
+        }
+        // Past its expiry the channel only refunds the opener, so later payments are worthless
+        if current_slot > channel.expiry {
+            return Err(format!("Channel expired at slot {}", channel.expiry));
+        }
+        Ok(())
+    }
+
+    fn check_state(channel: &PaymentChannel, proof: &BalanceProof) -> Result<(), String> {
+        if proof.channel_id != channel.id {
+            return Err("Balance proof is for a different channel".to_string());
+        }
+        if proof.balance_a.checked_add(proof.balance_b) != Some(channel.total_deposit) {
+            return Err("Balance mismatch: total does not match deposit".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ChannelClosing;
+    use tempfile::TempDir;
+
+    fn create_channel(payer: &Wallet, payee: &Wallet) -> PaymentChannel {
+        PaymentChannel {
+            id: vec![7; 32],
+            partner_a: payer.get_address_bytes(),
+            partner_b: payee.get_address_bytes(),
+            total_deposit: 100,
+            expiry: 1000,
+            is_closed: false,
+            closing: None,
+        }
+    }
+
+    #[test]
+    fn test_stream_payments() {
+        let payer_dir = TempDir::new().unwrap();
+        let payee_dir = TempDir::new().unwrap();
+        let payer_side = MicropaymentManager::new(Storage::new(payer_dir.path()).unwrap());
+        let payee_side = MicropaymentManager::new(Storage::new(payee_dir.path()).unwrap());
+        let payer = Wallet::new();
+        let payee = Wallet::new();
+        let channel = create_channel(&payer, &payee);
+
+        for _ in 0..3 {
+            let offered = payer_side.pay(&payer, &channel, 10, false, 0).unwrap();
+            let accepted = payee_side.receive(&payee, &channel, offered, 0).unwrap();
+            payer_side.confirm(&channel, accepted).unwrap();
+        }
+        let latest = payee_side.latest_state(&channel.id).unwrap().unwrap();
+        assert_eq!((latest.sequence, latest.balance_a, latest.balance_b), (3, 70, 30));
+
+        // Replaying an older state is rejected
+        let mut stale = BalanceProof::new(channel.id.clone(), 2, 80, 20, false);
+        stale.sign_a(&payer.keypair);
+        assert_eq!(payee_side.receive(&payee, &channel, stale, 0).err().unwrap(), "Channel state sequence 2 is not newer than 3");
+
+        // The payer cannot overspend the deposit or pay through someone else's channel
+        assert_eq!(payer_side.pay(&payer, &channel, 71, false, 0).err().unwrap(), "Insufficient channel balance. Available: 70, Required: 71");
+        assert!(payer_side.pay(&payee, &channel, 1, false, 0).is_err());
+
+        // Final state settles the channel
+        assert!(payee_side.closing_state(&channel).is_err());
+        let offered = payer_side.pay(&payer, &channel, 5, true, 0).unwrap();
+        payee_side.receive(&payee, &channel, offered, 0).unwrap();
+        let proof = payee_side.closing_state(&channel).unwrap();
+        assert_eq!((proof.balance_a, proof.balance_b), (65, 35));
+        assert!(payee_side.receive(&payee, &channel, proof, 0).is_err());
+    }
+
+    #[test]
+    fn test_receive_rejects_forged_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let payee_side = MicropaymentManager::new(Storage::new(temp_dir.path()).unwrap());
+        let payer = Wallet::new();
+        let payee = Wallet::new();
+        let channel = create_channel(&payer, &payee);
+
+        // Not signed by the payer
+        let mut forged = BalanceProof::new(channel.id.clone(), 1, 0, 100, false);
+        forged.sign_a(&payee.keypair);
+        assert_eq!(payee_side.receive(&payee, &channel, forged, 0).err().unwrap(), "Invalid partner A signature on balance proof");
+
+        // Does not add up to the deposit
+        let mut inflated = BalanceProof::new(channel.id.clone(), 1, 100, 100, false);
+        inflated.sign_a(&payer.keypair);
+        assert_eq!(payee_side.receive(&payee, &channel, inflated, 0).err().unwrap(), "Balance mismatch: total does not match deposit");
+        assert!(payee_side.latest_state(&channel.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unconfirmed_and_expired_states() {
+        let payer_dir = TempDir::new().unwrap();
+        let payee_dir = TempDir::new().unwrap();
+        let payer_side = MicropaymentManager::new(Storage::new(payer_dir.path()).unwrap());
+        let payee_side = MicropaymentManager::new(Storage::new(payee_dir.path()).unwrap());
+        let payer = Wallet::new();
+        let payee = Wallet::new();
+        let mut channel = create_channel(&payer, &payee);
+
+        // A state the recipient never counter-signed is not kept, so it cannot be settled
+        let offered = payer_side.pay(&payer, &channel, 10, false, 0).unwrap();
+        assert!(payer_side.latest_state(&channel.id).unwrap().is_none());
+        assert_eq!(payer_side.unilateral_close_state(&channel, 0).unwrap(), None);
+        assert!(payer_side.confirm(&channel, offered.clone()).is_err());
+
+        let accepted = payee_side.receive(&payee, &channel, offered, 0).unwrap();
+        payer_side.confirm(&channel, accepted.clone()).unwrap();
+        assert_eq!(payer_side.unilateral_close_state(&channel, 0).unwrap(), Some(accepted.clone()));
+
+        // Past expiry nothing more can be paid, and closing only refunds the opener
+        assert_eq!(payer_side.pay(&payer, &channel, 10, false, 1001).err().unwrap(), "Channel expired at slot 1000");
+        let late = payer_side.pay(&payer, &channel, 10, false, 1000).unwrap();
+        assert_eq!(payee_side.receive(&payee, &channel, late, 1001).err().unwrap(), "Channel expired at slot 1000");
+        assert_eq!(payer_side.unilateral_close_state(&channel, 1001).unwrap(), None);
+
+        // A close on the opening state is answered with the latest co-signed one
+        channel.closing = Some(ChannelClosing { sequence: 0, balance_a: 100, balance_b: 0, challenge_end_slot: 900 });
+        assert_eq!(payee_side.challenge_state(&channel).unwrap(), accepted);
+        channel.closing = Some(ChannelClosing { sequence: 1, balance_a: 90, balance_b: 10, challenge_end_slot: 900 });
+        assert!(payee_side.challenge_state(&channel).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::config::AppConfig;
+use crate::api::ChannelCloseStep;
 use crate::network::{NetworkConfig, NetworkNode, SyncMessage};
 use crate::crypto::Crypto;
 use crate::wallet::Wallet;
@@ -7,13 +8,14 @@ use schnorrkel::Keypair as SchnorrkelKeypair;
 use crate::block::{Block, BlockHeader};
 use crate::chain::Chain;
 use crate::mempool::Mempool;
+use crate::micropayment::MicropaymentManager;
 use crate::sync::{SyncManager, SyncEvent};
 use chrono::Utc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use crate::vote::Vote;
-use tracing::{info, error, warn};
+use tracing::{info, warn};
 
 pub struct Node {
     config: AppConfig,
@@ -66,29 +68,6 @@ impl Node {
             .await
             .expect("Failed to create network node");
 
-        // Helper to generate deterministic keypair from a seed string (Schnorrkel)
-        fn generate_validator_keypair(seed_str: &str) -> schnorrkel::Keypair {
-            let mut seed = [0u8; 32];
-            let bytes = seed_str.as_bytes();
-            for (i, &b) in bytes.iter().enumerate().take(32) {
-                seed[i] = b;
-            }
-            let mini_secret = schnorrkel::MiniSecretKey::from_bytes(&seed)
-                .expect("Failed to create secret from seed");
-            let secret = mini_secret.expand(schnorrkel::ExpansionMode::Ed25519);
-            secret.to_keypair()
-        }
-
-        // Helper to generate deterministic Ed25519 key (Wallet)
-        fn generate_wallet_key(seed_str: &str) -> SigningKey {
-            let mut seed = [0u8; 32];
-            let bytes = seed_str.as_bytes();
-            for (i, &b) in bytes.iter().enumerate().take(32) {
-                seed[i] = b;
-            }
-            SigningKey::from_bytes(&seed)
-        }
-
         // Determine base path
         let base_path = self.config.data_dir.clone().unwrap_or_else(|| std::path::PathBuf::from("."));
 
@@ -98,7 +77,7 @@ impl Node {
         
         let validator_keypair = if let Some(seed) = &self.config.mining.validator_seed {
             info!("Using deterministic validator key from config");
-            generate_validator_keypair(seed)
+            Crypto::validator_keypair_from_seed(seed)
         } else {
             // Load or create validator key file
             if val_path.exists() {
@@ -119,7 +98,7 @@ impl Node {
         
         let wallet_keypair = if let Some(seed) = &self.config.mining.validator_seed {
             info!("Using deterministic wallet key from seed");
-            let kp = generate_wallet_key(seed);
+            let kp = Crypto::wallet_key_from_seed(seed);
             
             // Save just for checks
             let w = Wallet::from_keypair(kp.clone());
@@ -144,7 +123,7 @@ impl Node {
 
         // Create Deterministic Genesis Block
         let genesis_seed_str = &self.config.genesis.genesis_seed;
-        let genesis_kp = generate_validator_keypair(genesis_seed_str);
+        let genesis_kp = Crypto::validator_keypair_from_seed(genesis_seed_str);
         
         let genesis_vrf_output = vec![0u8; 32];
         let genesis_vrf_proof = vec![0u8; 64];
@@ -174,43 +153,8 @@ impl Node {
         let db_path = base_path.join(db_filename);
         let storage = crate::storage::Storage::new(db_path.to_str().unwrap()).expect("Failed to create storage");
 
-        // Initialize Chain with Storage
-        let mut chain = Chain::new(storage, genesis_block.clone(), genesis_time);
-
-        // PRE-REGISTER Configured Validators
-        info!("Pre-registering initial validators...");
-        for seed in &self.config.genesis.initial_validators {
-            let node_kp = generate_validator_keypair(seed);
-            let node_pubkey = node_kp.public.to_bytes().to_vec();
-            
-            if chain.state.get_balance(&node_pubkey) == 0 {
-                chain.state.set_balance(node_pubkey.clone(), 1_000_000);
-                let _ = chain.validators.register_validator(node_pubkey, 1_000_000, 0);
-            }
-        }
-        
-        // Register self if we have balance (or give balance if genesis logic allows)
-        // For Devnet simplicity: ensure self has balance if we are using a configured seed
-        // (The loop above likely covered us if we are in the initial list)
-        // If we are NOT in the initial list, we might need funding (Faucet).
-        let my_address = wallet_keypair.verifying_key().to_bytes().to_vec();
-        if self.config.mining.validator_seed.is_some() {
-             // If we rely on seed, we expect to be in genesis. 
-             // Double check balance.
-             if chain.state.get_balance(&my_address) == 0 {
-                  // Fallback for standalone dev mode: fund self
-                  info!("Funding self (standalone mode)...");
-                  chain.state.set_balance(my_address.clone(), 1_000_000);
-                  let _ = chain.validators.register_validator(my_address.clone(), 1_000_000, 0);
-             }
-        }
-        
-        // CRITICAL: Persist initial balances to disk
-        if let Err(e) = chain.state.apply_changes() {
-            error!("CRITICAL ERROR: Failed to persist initial balances: {}", e);
-        } else {
-            info!("✓ Successfully persisted initial balances to disk");
-        }
+        // Initialize Chain with Storage (commits the genesis state on first start)
+        let mut chain = Chain::new(storage, genesis_block.clone(), &self.config.genesis);
 
         info!("Chain initialized with genesis: {}", genesis_block.hash);
 
@@ -254,13 +198,16 @@ impl Node {
             }
         });
 
+        let block_limits = self.config.genesis.limits.clone();
+
         // Spawn blockchain simulation task
         let blockchain_handle = tokio::spawn(async move {
             let mut validator_keypair: SchnorrkelKeypair = validator_keypair;
             let mut wallet_keypair: SigningKey = wallet_keypair;
             let mut my_address = wallet_keypair.verifying_key().to_bytes().to_vec();
             let mut last_slot = 0;
-            let mut mempool = Mempool::new(1000);
+            let mut mempool = Mempool::new(1000, chain.chain_id(), block_limits);
+            let micropayments = MicropaymentManager::new(chain.storage.clone());
             let mut sync_manager = SyncManager::new(sync_event_tx);
             let mut simulation_enabled = false;
 
@@ -349,7 +296,7 @@ impl Node {
                             println!("--- Node Info ---");
                             println!("Height: {}", chain.get_height());
                             println!("Head: {}", chain.head);
-                            println!("Mempool: {} txs", mempool.len());
+                            println!("Mempool: {} txs ({} pending, {} queued)", mempool.len(), mempool.pending_len(), mempool.queued_len());
                             println!("Sync State: {:?}", sync_manager.get_state());
                             println!("Address: {}", hex::encode(my_address.clone()));
                             println!("Balance: {}", chain.state.get_balance(&my_address));
@@ -375,17 +322,15 @@ impl Node {
                                  }
                             };
                             
-                            let nonce = chain.state.get_nonce(&my_address);
-                             let pending_nonce_offset = mempool.get_transactions_for_block(1000)
-                                .iter()
-                                .filter(|tx| tx.sender == my_address)
-                                .count() as u64;
+                            let nonce = mempool.get_pending_nonce(&my_address, &chain.state);
                                 
                             let tx = crate::transaction::Transaction::new(
                                 my_address.clone(),
                                 recv_bytes,
                                 crate::transaction::TransactionData::NativeTransfer { amount },
-                                nonce + pending_nonce_offset,
+                                nonce,
+                                crate::transaction::MIN_TRANSACTION_FEE,
+                                chain.chain_id(),
                                 &wallet_keypair
                             );
                             if let Ok(_) = mempool.add_transaction(tx.clone(), &chain.state) {
@@ -422,23 +367,26 @@ impl Node {
                 if !sync_manager.is_syncing() && current_slot > last_slot {
                     println!("Processing Slot: {}", current_slot);
                     last_slot = current_slot;
+
+                    // 0. Drop transactions that can no longer be included
+                    let evicted = mempool.evict_expired(current_slot, &chain.state);
+                    if evicted > 0 {
+                        println!("Evicted {} expired transactions from mempool", evicted);
+                    }
                     
                     // 1. Generate a random transaction
                     if simulation_enabled && rand::random::<f64>() < 0.5 {
                         let receiver = Crypto::generate_keypair().public.to_bytes().to_vec();
                         let amount = 10;
-                        let nonce = chain.state.get_nonce(&my_address);
-                        
-                        let pending_nonce_offset = mempool.get_transactions_for_block(1000)
-                            .iter()
-                            .filter(|tx| tx.sender == my_address)
-                            .count() as u64;
+                        let nonce = mempool.get_pending_nonce(&my_address, &chain.state);
                             
                         let tx = crate::transaction::Transaction::new(
                             my_address.clone(),
                             receiver,
                             crate::transaction::TransactionData::NativeTransfer { amount },
-                            nonce + pending_nonce_offset,
+                            nonce,
+                            crate::transaction::MIN_TRANSACTION_FEE,
+                            chain.chain_id(),
                             &wallet_keypair
                         );
                         
@@ -451,13 +399,21 @@ impl Node {
 
                     // 2. Secret Leader Election Check
                     let parent = chain.get_head();
-                    let seed = crate::consensus::Consensus::compute_vrf_seed(&parent.header.vrf_output, current_slot);
+                    let randomness = match chain.epoch_randomness(chain.consensus.get_epoch(current_slot)) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            println!("Cannot produce for slot {}: {}", current_slot, e);
+                            continue;
+                        }
+                    };
+                    let seed = crate::consensus::Consensus::compute_vrf_seed(&randomness, current_slot);
                     let (vrf_out, vrf_proof) = Crypto::vrf_sign(&validator_keypair, &seed);
                     let vrf_output_bytes = vrf_out.to_bytes().to_vec();
                     let vrf_proof_bytes = vrf_proof.to_bytes().to_vec();
                     let my_pubkey_bytes = validator_keypair.public.to_bytes();
 
-                    if chain.validators.is_slot_leader(&my_pubkey_bytes, &vrf_output_bytes) {
+                    let slot_validators = chain.validators_for_epoch(chain.consensus.get_epoch(current_slot));
+                    if chain.consensus.is_slot_leader(&slot_validators, &my_pubkey_bytes, &vrf_output_bytes) {
                          println!("🎰 Won Secret Leader Election for slot {}", current_slot);
                     } else {
                         // Not a leader
@@ -466,7 +422,7 @@ impl Node {
 
                     // 3. Produce block
                     println!("⛏️  Mining: Check mempool (size: {})", mempool.len());
-                    let transactions = mempool.get_transactions_for_block(100);
+                    let transactions = mempool.get_transactions_for_block(chain.consensus.limits().max_block_txs as usize);
                     println!("⛏️  Mining: Selected {} txs for block", transactions.len());
                     
                     let new_header = BlockHeader {
@@ -476,8 +432,8 @@ impl Node {
                         vrf_output: vrf_output_bytes,
                         vrf_proof: vrf_proof_bytes,
                         validator_pubkey: validator_keypair.public.to_bytes().to_vec(),
-                        producer_signature: vec![],
-                        state_root: "".to_string(),
+                        producer_signature: vec![], // Set by Block::sign
+                        state_root: "".to_string(), // Filled in by build_block
                         tx_root: "".to_string(),
                         extra_witnesses: vec![],
                         timestamp: Utc::now().timestamp_millis(),
@@ -496,14 +452,19 @@ impl Node {
                         }
                     }
                     
-                    let new_block = Block::new(new_header, transactions.clone());
+                    // Execute the candidates to fill in state_root; failing ones are left out
+                    let mut new_block = chain.build_block(new_header, transactions);
+                    new_block.sign(&validator_keypair);
                     
                     if chain.add_block(new_block.clone()) {
                         println!("Produced and added block for slot {}: {} with {} txs", 
                             current_slot, new_block.hash, new_block.transactions.len());
                         
-                        mempool.remove_transactions(&transactions);
-                        println!("🧹 Mining: Removed {} txs from mempool. New size: {}", transactions.len(), mempool.len());
+                        // Only the included transactions leave the pool; candidates that failed
+                        // to execute or did not fit stay for a later block
+                        mempool.remove_transactions(&new_block.transactions);
+                        mempool.sync_with_state(&chain.state);
+                        println!("🧹 Mining: Removed {} txs from mempool. New size: {}", new_block.transactions.len(), mempool.len());
                         
                         network_client.broadcast_block(new_block.clone());
                         println!("Broadcasted block");
@@ -533,7 +494,15 @@ impl Node {
                     if chain.add_block(block.clone()) {
                         println!("✓ Accepted block from network for slot {}", block.header.slot);
                         println!("Added received block to chain");
-                        mempool.remove_transactions(&block.transactions);
+
+                        // Transactions from blocks dropped by a reorg go back into the pool
+                        for tx in chain.take_reverted_transactions() {
+                            let _ = mempool.add_transaction(tx, &chain.state);
+                        }
+                        if chain.head == block.hash {
+                            mempool.remove_transactions(&block.transactions);
+                        }
+                        mempool.sync_with_state(&chain.state);
                         println!("🧹 Network: Removed {} txs from mempool (incoming block). New size: {}", block.transactions.len(), mempool.len());
                         
                         // VOTE for this block
@@ -554,7 +523,27 @@ impl Node {
                             chain.add_vote(vote);
                         }
                     } else {
-                        // Block rejected
+                        // Block rejected; report any equivocation it revealed
+                        for (header_a, header_b) in chain.take_equivocation_evidence() {
+                            let offender = hex::encode(&header_a.validator_pubkey);
+                            let nonce = mempool.get_pending_nonce(&my_address, &chain.state);
+                            let tx = crate::transaction::Transaction::new(
+                                my_address.clone(),
+                                vec![],
+                                crate::transaction::TransactionData::SubmitEquivocationEvidence { header_a: Box::new(header_a), header_b: Box::new(header_b) },
+                                nonce,
+                                crate::transaction::MIN_TRANSACTION_FEE,
+                                chain.chain_id(),
+                                &wallet_keypair
+                            );
+                            match mempool.add_transaction(tx.clone(), &chain.state) {
+                                Ok(()) => {
+                                    println!("⚖️  Submitting equivocation evidence against {}", offender);
+                                    network_client.broadcast_transaction(tx);
+                                }
+                                Err(e) => println!("Failed to submit equivocation evidence: {}", e),
+                            }
+                        }
                     }
                 }
                 
@@ -578,6 +567,7 @@ impl Node {
                                  peer_count: sync_manager.get_peers().len(),
                                  balance: chain.state.get_balance(&my_address),
                                  address: hex::encode(my_address.clone()),
+                                 chain_id: chain.chain_id(),
                              };
                              let _ = respond_to.send(stats);
                         }
@@ -607,21 +597,22 @@ impl Node {
                                 }
                             };
                             
-                            let nonce = chain.state.get_nonce(&my_address);
-                            
                             // Account for pending transactions in mempool
-                            let pending_nonce_offset = mempool.get_transactions_for_block(1000)
-                                .iter()
-                                .filter(|tx| tx.sender == my_address)
-                                .count() as u64;
+                            let nonce = mempool.get_pending_nonce(&my_address, &chain.state);
                             
-                            let tx = crate::transaction::Transaction::new(
+                            let mut tx = crate::transaction::Transaction::new(
                                 my_address.clone(),
                                 receiver_bytes,
                                 crate::transaction::TransactionData::NativeTransfer { amount: req.amount },
-                                nonce + pending_nonce_offset,
+                                nonce,
+                                req.fee.unwrap_or(crate::transaction::MIN_TRANSACTION_FEE),
+                                chain.chain_id(),
                                 &wallet_keypair
                             );
+                            if req.valid_until_slot.is_some() {
+                                tx.valid_until_slot = req.valid_until_slot;
+                                tx.sign(&wallet_keypair);
+                            }
                             
                             let hash = hex::encode(tx.hash());
 
@@ -710,7 +701,7 @@ impl Node {
                             let response = validator.map(|v| crate::api::ValidatorStatusResponse {
                                 pubkey: hex::encode(&check_address),
                                 stake: v.stake,
-                                is_active: v.stake > 0, // Simplified check
+                                is_active: chain.validators.is_validator(&check_address),
                                 last_voted_slot: 0, // TODO: Track last voted slot in Validator struct or locally here
                             });
                             let _ = respond_to.send(response);
@@ -773,13 +764,14 @@ impl Node {
                             
                             // Send tokens
                             let amount = 1000; // Faucet amount
+                            let fee = crate::transaction::MIN_TRANSACTION_FEE;
                             let current_balance = chain.state.get_balance(&my_address);
                             
                             println!("💰 Faucet: Node balance: {} tokens, requested: {} tokens", current_balance, amount);
                             
-                            // Check if node has sufficient balance
-                            if current_balance < amount {
-                                println!("❌ Faucet: Insufficient node balance ({} < {})", current_balance, amount);
+                            // Check if node has sufficient balance (amount + fee)
+                            if current_balance < amount + fee {
+                                println!("❌ Faucet: Insufficient node balance ({} < {})", current_balance, amount + fee);
                                 let _ = respond_to.send(Err(format!(
                                     "Faucet temporarily unavailable: Node balance too low ({} tokens). Please try another node or wait for the node to receive more tokens.",
                                     current_balance
@@ -787,22 +779,17 @@ impl Node {
                                 continue;
                             }
                             
-                            let nonce = chain.state.get_nonce(&my_address);
-                            
                             // Account for pending transactions in mempool
-                            let pending_nonce_offset = mempool.get_transactions_for_block(1000)
-                                .iter()
-                                .filter(|tx| tx.sender == my_address)
-                                .count() as u64;
-                            
-                            let final_nonce = nonce + pending_nonce_offset;
-                            println!("🔢 Faucet: Using nonce {} (state: {}, pending offset: {})", final_nonce, nonce, pending_nonce_offset);
+                            let final_nonce = mempool.get_pending_nonce(&my_address, &chain.state);
+                            println!("🔢 Faucet: Using nonce {} (state: {})", final_nonce, chain.state.get_nonce(&my_address));
                             
                             let tx = crate::transaction::Transaction::new(
                                 my_address.clone(),
                                 receiver.clone(),
                                 crate::transaction::TransactionData::NativeTransfer { amount },
                                 final_nonce,
+                                fee,
+                                chain.chain_id(),
                                 &wallet_keypair
                             );
                             
@@ -880,14 +867,12 @@ impl Node {
                         }
                         crate::api::ApiCommand::GetTransaction(hash, respond_to) => {
                             // 1. Check Mempool
-                            // Loop through mempool logic is strict but doable
-                            // In real impl, mempool would map hash -> tx
-                            let mempool_tx = mempool.transactions.values().find(|tx| hex::encode(tx.hash()) == hash);
+                            let mempool_tx = hex::decode(&hash).ok().and_then(|h| mempool.get_transaction(&h));
                             
                             if let Some(tx) = mempool_tx {
                                 let response = crate::api::TransactionResponse {
                                     hash: hash.clone(),
-                                    transaction: tx.clone(),
+                                    transaction: tx,
                                     block_hash: String::new(), // Not in a block
                                     status: "pending".to_string(),
                                 };
@@ -921,20 +906,23 @@ impl Node {
 
                             // 1. Check Pending (Mempool) - Only for Sender
                             // Receiver pending is harder unless we index mempool by receiver too, but usually sender cares most about pending.
-                            for tx in mempool.transactions.values() {
-                                if hex::encode(&tx.sender) == address {
-                                    history.push(crate::api::TransactionResponse {
-                                        hash: hex::encode(tx.hash()),
-                                        transaction: tx.clone(),
-                                        block_hash: String::new(),
-                                        status: "pending".to_string(),
-                                    });
-                                }
+                            let sender_bytes = hex::decode(&address).unwrap_or_default();
+                            for tx in mempool.get_by_sender(&sender_bytes) {
+                                history.push(crate::api::TransactionResponse {
+                                    hash: hex::encode(tx.hash()),
+                                    transaction: tx,
+                                    block_hash: String::new(),
+                                    status: "pending".to_string(),
+                                });
                             }
                             
                             // 2. Check Confirmed (Storage)
                             if let Ok(tx_hashes) = chain.storage.get_address_history(&address, limit) {
                                 for hash in tx_hashes {
+                                    // A transaction re-included after a reorg is indexed twice
+                                    if history.iter().any(|h| h.hash == hash) {
+                                        continue;
+                                    }
                                     if let Ok(Some(block_hash)) = chain.storage.get_transaction_block(&hash) {
                                         if let Some(block) = chain.get_block(&block_hash) {
                                             if let Some(tx) = block.transactions.iter().find(|t| hex::encode(t.hash()) == hash) {
@@ -955,14 +943,110 @@ impl Node {
                         crate::api::ApiCommand::GetValidators(respond_to) => {
                              let validators = chain.validators.get_all_validators().into_iter().map(|v| {
                                  crate::api::ValidatorStatusResponse {
-                                     pubkey: hex::encode(v.pubkey),
+                                     pubkey: hex::encode(&v.pubkey),
                                      stake: v.stake,
-                                     is_active: !v.slashed,
+                                     is_active: chain.validators.is_validator(&v.pubkey),
                                      last_voted_slot: 0, // Placeholder
                                  }
                              }).collect();
                              let _ = respond_to.send(validators);
                         }
+                        crate::api::ApiCommand::ChannelPay(req, respond_to) => {
+                            let result = hex::decode(&req.channel_id)
+                                .map_err(|_| "Invalid channel ID hex".to_string())
+                                .and_then(|id| chain.state.get_channel(&id).ok_or("Channel not found".to_string()))
+                                .and_then(|channel| {
+                                    let wallet = Wallet::from_keypair(wallet_keypair.clone());
+                                    micropayments.pay(&wallet, &channel, req.amount, req.is_final, chain.consensus.get_current_slot())
+                                });
+                            let _ = respond_to.send(result);
+                        }
+                        crate::api::ApiCommand::ChannelReceive(proof, respond_to) => {
+                            let result = chain.state.get_channel(&proof.channel_id)
+                                .ok_or("Channel not found".to_string())
+                                .and_then(|channel| {
+                                    let wallet = Wallet::from_keypair(wallet_keypair.clone());
+                                    micropayments.receive(&wallet, &channel, proof, chain.consensus.get_current_slot())
+                                });
+                            let _ = respond_to.send(result);
+                        }
+                        crate::api::ApiCommand::ChannelConfirm(proof, respond_to) => {
+                            let result = chain.state.get_channel(&proof.channel_id)
+                                .ok_or("Channel not found".to_string())
+                                .and_then(|channel| micropayments.confirm(&channel, proof.clone()))
+                                .map(|_| proof);
+                            let _ = respond_to.send(result);
+                        }
+                        crate::api::ApiCommand::GetChannelState(channel_id, respond_to) => {
+                            let state = hex::decode(&channel_id).ok()
+                                .and_then(|id| micropayments.latest_state(&id).ok().flatten());
+                            let _ = respond_to.send(state);
+                        }
+                        crate::api::ApiCommand::CloseChannel(step, req, respond_to) => {
+                            let current_slot = chain.consensus.get_current_slot();
+                            let data = hex::decode(&req.channel_id)
+                                .map_err(|_| "Invalid channel ID hex".to_string())
+                                .and_then(|id| chain.state.get_channel(&id).ok_or("Channel not found".to_string()))
+                                .and_then(|channel| {
+                                    let channel_id = channel.id.clone();
+                                    Ok(match step {
+                                        ChannelCloseStep::Cooperative => crate::transaction::TransactionData::CloseChannel {
+                                            channel_id,
+                                            balance_proof: micropayments.closing_state(&channel)?,
+                                        },
+                                        ChannelCloseStep::Start => crate::transaction::TransactionData::StartChannelClose {
+                                            channel_id,
+                                            balance_proof: micropayments.unilateral_close_state(&channel, current_slot)?,
+                                        },
+                                        ChannelCloseStep::Challenge => crate::transaction::TransactionData::ChallengeChannelClose {
+                                            channel_id,
+                                            balance_proof: micropayments.challenge_state(&channel)?,
+                                        },
+                                        ChannelCloseStep::Settle => crate::transaction::TransactionData::SettleChannel { channel_id },
+                                    })
+                                });
+                            let data = match data {
+                                Ok(data) => data,
+                                Err(e) => {
+                                    let _ = respond_to.send(Err(e));
+                                    continue;
+                                }
+                            };
+
+                            let nonce = mempool.get_pending_nonce(&my_address, &chain.state);
+                            let tx = crate::transaction::Transaction::new(
+                                my_address.clone(),
+                                vec![],
+                                data,
+                                nonce,
+                                req.fee.unwrap_or(crate::transaction::MIN_TRANSACTION_FEE),
+                                chain.chain_id(),
+                                &wallet_keypair,
+                            );
+
+                            match mempool.add_transaction(tx.clone(), &chain.state) {
+                                Ok(_) => {
+                                    network_client.broadcast_transaction(tx.clone());
+                                    let _ = respond_to.send(Ok(hex::encode(tx.hash())));
+                                }
+                                Err(e) => {
+                                    let _ = respond_to.send(Err(e));
+                                }
+                            }
+                        }
+                        crate::api::ApiCommand::GetNFT(collection_id, item_id, respond_to) => {
+                            let nft = hex::decode(&collection_id).ok()
+                                .and_then(|id| chain.state.get_nft(&id, item_id));
+                            let response = nft.map(|item| crate::api::NFTResponse {
+                                collection_id: hex::encode(&item.collection_id),
+                                item_id: item.id,
+                                owner: hex::encode(&item.owner),
+                                approved: item.approved.as_ref().map(hex::encode),
+                                metadata: hex::encode(&item.metadata),
+                                metadata_frozen: item.metadata_frozen,
+                            });
+                            let _ = respond_to.send(response);
+                        }
                         crate::api::ApiCommand::RegisterValidator(stake, respond_to) => {
                              // Create and sign a transaction to register as validator
                              // 1. Get address info
@@ -970,9 +1054,9 @@ impl Node {
                              let sender_pubkey_bytes = wallet_keypair.verifying_key().to_bytes().to_vec();
                              
                              let balance = chain.state.get_balance(&sender_pubkey_bytes);
-                             let nonce = chain.state.get_nonce(&sender_pubkey_bytes);
+                             let nonce = mempool.get_pending_nonce(&sender_pubkey_bytes, &chain.state);
                              
-                             if balance < stake {
+                             if balance < stake + crate::transaction::MIN_TRANSACTION_FEE {
                                  let _ = respond_to.send(Err("Insufficient balance".to_string()));
                                  continue;
                              }
@@ -981,8 +1065,15 @@ impl Node {
                              let tx = crate::transaction::Transaction::new(
                                  sender_pubkey_bytes.clone(),
                                  vec![], // No receiver for registration
-                                 crate::transaction::TransactionData::RegisterValidator { stake },
+                                 crate::transaction::TransactionData::RegisterValidator {
+                                     stake,
+                                     // Bond this node's block-signing key; rewards go to the wallet
+                                     validator_pubkey: validator_keypair.public.to_bytes().to_vec(),
+                                     proof_of_possession: Crypto::prove_possession(&validator_keypair, &sender_pubkey_bytes),
+                                 },
                                  nonce,
+                                 crate::transaction::MIN_TRANSACTION_FEE,
+                                 chain.chain_id(),
                                  &wallet_keypair,
                              );
                              
@@ -1,18 +1,30 @@
 use serde::{Deserialize, Serialize};
 use crate::storage::Storage;
 use crate::trie::MerklePatriciaTrie;
-use crate::transaction::{Transaction, TransactionData};
-use std::collections::HashMap;
+use crate::transaction::{BalanceProof, OrderAsset, Transaction, TransactionData};
+use crate::validator::{ValidatorInfo, DEFAULT_UNBONDING_EPOCHS, EQUIVOCATION_REPORTER_REWARD_PERCENT, MIN_STAKE};
+use crate::consensus::SLOTS_PER_EPOCH;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+// Ordered maps keep the serialized form (and therefore the state root) deterministic
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Account {
     pub balance: u64,
     pub nonce: u64,
-    pub assets: HashMap<Vec<u8>, u64>, // AssetID -> Balance
-    pub nfts: HashMap<Vec<u8>, Vec<u64>>, // CollectionID -> Wrapped Item IDs
+    pub assets: BTreeMap<Vec<u8>, u64>, // AssetID -> Balance
+    pub nfts: BTreeMap<Vec<u8>, Vec<u64>>, // CollectionID -> Wrapped Item IDs
     
     // Delegate -> Remaining Allowance
-    pub delegated_allowance: HashMap<Vec<u8>, u64>,
+    pub delegated_allowance: BTreeMap<Vec<u8>, Delegation>,
+    pub asset_allowances: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, u64>>, // AssetID -> Spender -> Allowance
+    pub nft_operators: BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>, // CollectionID -> Operators for all of this account's items
+}
+
+/// Spending allowance granted to a delegate
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delegation {
+    pub allowance: u64,
+    pub expiry: u64, // Last slot the allowance can be used in
 }
 
 impl Account {
@@ -20,14 +32,16 @@ impl Account {
         Self { 
             balance, 
             nonce: 0,
-            assets: HashMap::new(),
-            nfts: HashMap::new(),
-            delegated_allowance: HashMap::new(),
+            assets: BTreeMap::new(),
+            nfts: BTreeMap::new(),
+            delegated_allowance: BTreeMap::new(),
+            asset_allowances: BTreeMap::new(),
+            nft_operators: BTreeMap::new(),
         }
     }
 }
 
-// Global Registry structs (could also be stored in a separate trie, but simplifying for now)
+// Global Registry structs (stored under their own key spaces in Storage and the state trie)
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Asset {
     pub id: Vec<u8>,
@@ -37,6 +51,9 @@ pub struct Asset {
     pub total_supply: u64,
     pub decimals: u8,
     pub metadata: Vec<u8>,
+    pub admin: Vec<u8>, // Can mint, freeze and hand over control; the issuer at creation
+    pub max_supply: Option<u64>, // Minting can never push total_supply above this
+    pub is_frozen: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -46,14 +63,20 @@ pub struct Collection {
     pub name: String,
     pub symbol: String,
     pub metadata: Vec<u8>,
-    pub items: HashMap<u64, NFTItem>, // ItemID -> Item Data
+    pub max_items: Option<u64>, // Cap on items ever minted
+    pub minted: u64, // Items minted so far, burned ones included
 }
 
+// Items are a registry of their own, keyed by collection and item ID
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NFTItem {
     pub id: u64,
+    pub collection_id: Vec<u8>,
     pub owner: Vec<u8>,
     pub metadata: Vec<u8>,
+    pub approved: Option<Vec<u8>>, // May transfer or burn this item; cleared on transfer
+    pub metadata_frozen: bool,
+    pub is_burned: bool, // Burned items are kept so their ID cannot be minted again
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -62,11 +85,89 @@ pub struct PaymentChannel {
     pub partner_a: Vec<u8>, // Opener
     pub partner_b: Vec<u8>, // Recipient
     pub total_deposit: u64,
-    pub expiry: u64, // Slot number
+    pub expiry: u64, // Last slot partner B can be paid out; afterwards the deposit only refunds to A
+    pub is_closed: bool,
+    pub closing: Option<ChannelClosing>, // Unilateral close waiting out its challenge window
+}
+
+/// State submitted by a unilateral close, paid out at `challenge_end_slot` unless superseded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChannelClosing {
+    pub sequence: u64,
+    pub balance_a: u64,
+    pub balance_b: u64,
+    pub challenge_end_slot: u64,
+}
+
+/// Swap order escrowing the maker's offer until it is filled or cancelled
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Order {
+    pub id: Vec<u8>,
+    pub maker: Vec<u8>,
+    pub offer: OrderAsset, // Held by the order while it is open
+    pub ask: OrderAsset, // Paid by the taker straight to the maker
+    pub taker: Option<Vec<u8>>, // Only this account may fill the order if set
+    pub expiry: u64, // Last slot the order can be filled in
     pub is_closed: bool,
+    pub filled_by: Option<Vec<u8>>, // Taker, once filled; unset if cancelled
+}
+
+/// m-of-n account; transactions from `address` need cosignatures from `threshold` of `signers`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MultisigAccount {
+    pub address: Vec<u8>, // Derived from the signer set and threshold
+    pub signers: Vec<Vec<u8>>, // Sorted
+    pub threshold: u32,
+}
+
+/// Slots the counterparty has to answer a unilateral close with a newer state
+pub const DEFAULT_CHANNEL_CHALLENGE_SLOTS: u64 = 900; // ~30 minutes of 2 second slots
+
+// Trie key prefixes for the global registries (accounts are keyed by raw address)
+const ASSET_TRIE_PREFIX: &[u8] = b"asset:";
+const COLLECTION_TRIE_PREFIX: &[u8] = b"collection:";
+const NFT_TRIE_PREFIX: &[u8] = b"nft:";
+const CHANNEL_TRIE_PREFIX: &[u8] = b"channel:";
+const ORDER_TRIE_PREFIX: &[u8] = b"order:";
+const MULTISIG_TRIE_PREFIX: &[u8] = b"multisig:";
+const VALIDATOR_TRIE_PREFIX: &[u8] = b"validator:";
+
+/// Raw trie key/value pair
+type TrieEntry = (Vec<u8>, Vec<u8>);
+
+/// Collection ID and item ID of an NFT
+type NFTKey = (Vec<u8>, u64);
+
+fn nft_trie_key(collection_id: &[u8], item_id: u64) -> Vec<u8> {
+    [NFT_TRIE_PREFIX, collection_id, &item_id.to_be_bytes()].concat()
 }
 
+/// Committed values overwritten by a block, used to roll the block back during a reorg
+/// `None` means the entry did not exist before the block.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UndoJournal {
+    accounts: Vec<(Vec<u8>, Option<Account>)>,
+    assets: Vec<(Vec<u8>, Option<Asset>)>,
+    collections: Vec<(Vec<u8>, Option<Collection>)>,
+    nfts: Vec<(NFTKey, Option<NFTItem>)>,
+    channels: Vec<(Vec<u8>, Option<PaymentChannel>)>,
+    orders: Vec<(Vec<u8>, Option<Order>)>,
+    multisigs: Vec<(Vec<u8>, Option<MultisigAccount>)>,
+    validators: Vec<(Vec<u8>, Option<ValidatorInfo>)>,
+}
 
+/// Snapshot of uncommitted changes, used to roll back a single transaction
+#[derive(Clone)]
+pub struct StateCheckpoint {
+    accounts: HashMap<Vec<u8>, Account>,
+    assets: HashMap<Vec<u8>, Asset>,
+    collections: HashMap<Vec<u8>, Collection>,
+    nfts: HashMap<NFTKey, NFTItem>,
+    channels: HashMap<Vec<u8>, PaymentChannel>,
+    orders: HashMap<Vec<u8>, Order>,
+    multisigs: HashMap<Vec<u8>, MultisigAccount>,
+    validators: HashMap<Vec<u8>, ValidatorInfo>,
+}
 
 #[derive(Clone)]
 pub struct State {
@@ -74,14 +175,46 @@ pub struct State {
     pub pending_changes: HashMap<Vec<u8>, Account>,
     trie: MerklePatriciaTrie,
     
-    // In-memory cache for assets/collections (in a real system, these would also be in Storage/Trie)
-    pub assets: HashMap<Vec<u8>, Asset>,
-    pub collections: HashMap<Vec<u8>, Collection>,
-    pub channels: HashMap<Vec<u8>, PaymentChannel>,
+    // Uncommitted registry writes, flushed together with accounts in apply_changes
+    pending_assets: HashMap<Vec<u8>, Asset>,
+    pending_collections: HashMap<Vec<u8>, Collection>,
+    pending_nfts: HashMap<NFTKey, NFTItem>,
+    pending_channels: HashMap<Vec<u8>, PaymentChannel>,
+    pending_orders: HashMap<Vec<u8>, Order>,
+    pending_multisigs: HashMap<Vec<u8>, MultisigAccount>,
+    pending_validators: HashMap<Vec<u8>, ValidatorInfo>,
+
+    // Slot and epoch of the block being executed (expiries, validator registrations)
+    block_slot: u64,
+    block_epoch: u64,
+    unbonding_epochs: u64,
+    channel_challenge_slots: u64,
 }
 
 impl State {
     pub fn new(storage: Storage) -> Self {
+        let trie = Self::build_trie(&storage);
+        
+        Self { 
+            storage,
+            pending_changes: HashMap::new(),
+            trie,
+            pending_assets: HashMap::new(),
+            pending_collections: HashMap::new(),
+            pending_nfts: HashMap::new(),
+            pending_channels: HashMap::new(),
+            pending_orders: HashMap::new(),
+            pending_multisigs: HashMap::new(),
+            pending_validators: HashMap::new(),
+            block_slot: 0,
+            block_epoch: 0,
+            unbonding_epochs: DEFAULT_UNBONDING_EPOCHS,
+            channel_challenge_slots: DEFAULT_CHANNEL_CHALLENGE_SLOTS,
+        }
+    }
+
+    /// Build the state trie from everything committed to storage
+    fn build_trie(storage: &Storage) -> MerklePatriciaTrie {
         let mut trie = MerklePatriciaTrie::new();
         
         // Rebuild trie from existing accounts
@@ -92,15 +225,64 @@ impl State {
                 }
             }
         }
-        
-        Self { 
-            storage,
-            pending_changes: HashMap::new(),
-            trie,
-            assets: HashMap::new(),
-            collections: HashMap::new(),
-            channels: HashMap::new(),
+
+        // ...and from the global registries
+        if let Ok(assets) = storage.get_all_assets() {
+            for asset in assets {
+                if let Ok(data) = bincode::serialize(&asset) {
+                    trie.insert([ASSET_TRIE_PREFIX, asset.id.as_slice()].concat(), data);
+                }
+            }
+        }
+        if let Ok(collections) = storage.get_all_collections() {
+            for collection in collections {
+                if let Ok(data) = bincode::serialize(&collection) {
+                    trie.insert([COLLECTION_TRIE_PREFIX, collection.id.as_slice()].concat(), data);
+                }
+            }
+        }
+        if let Ok(items) = storage.get_all_nfts() {
+            for item in items {
+                if let Ok(data) = bincode::serialize(&item) {
+                    trie.insert(nft_trie_key(&item.collection_id, item.id), data);
+                }
+            }
+        }
+        if let Ok(channels) = storage.get_all_channels() {
+            for channel in channels {
+                if let Ok(data) = bincode::serialize(&channel) {
+                    trie.insert([CHANNEL_TRIE_PREFIX, channel.id.as_slice()].concat(), data);
+                }
+            }
+        }
+        if let Ok(orders) = storage.get_all_orders() {
+            for order in orders {
+                if let Ok(data) = bincode::serialize(&order) {
+                    trie.insert([ORDER_TRIE_PREFIX, order.id.as_slice()].concat(), data);
+                }
+            }
         }
+        if let Ok(multisigs) = storage.get_all_multisigs() {
+            for multisig in multisigs {
+                if let Ok(data) = bincode::serialize(&multisig) {
+                    trie.insert([MULTISIG_TRIE_PREFIX, multisig.address.as_slice()].concat(), data);
+                }
+            }
+        }
+        if let Ok(validators) = storage.get_all_validators() {
+            for validator in validators {
+                if let Ok(data) = bincode::serialize(&validator) {
+                    trie.insert([VALIDATOR_TRIE_PREFIX, validator.pubkey.as_slice()].concat(), data);
+                }
+            }
+        }
+
+        trie
+    }
+
+    /// Rebuild the state trie from storage (after reverting committed changes)
+    pub fn rebuild_trie(&mut self) {
+        self.trie = Self::build_trie(&self.storage);
     }
 
     pub fn get_account(&self, address: &[u8]) -> Option<Account> {
@@ -110,6 +292,123 @@ impl State {
         self.storage.get_account(address).unwrap_or(None)
     }
 
+    pub fn get_asset(&self, asset_id: &[u8]) -> Option<Asset> {
+        if let Some(asset) = self.pending_assets.get(asset_id) {
+            return Some(asset.clone());
+        }
+        self.storage.get_asset(asset_id).unwrap_or(None)
+    }
+
+    pub fn get_collection(&self, collection_id: &[u8]) -> Option<Collection> {
+        if let Some(collection) = self.pending_collections.get(collection_id) {
+            return Some(collection.clone());
+        }
+        self.storage.get_collection(collection_id).unwrap_or(None)
+    }
+
+    /// An NFT item, burned ones included
+    fn get_nft_record(&self, collection_id: &[u8], item_id: u64) -> Option<NFTItem> {
+        if let Some(item) = self.pending_nfts.get(&(collection_id.to_vec(), item_id)) {
+            return Some(item.clone());
+        }
+        self.storage.get_nft(collection_id, item_id).unwrap_or(None)
+    }
+
+    /// An NFT item that has not been burned; answers who owns it without loading the collection
+    pub fn get_nft(&self, collection_id: &[u8], item_id: u64) -> Option<NFTItem> {
+        self.get_nft_record(collection_id, item_id).filter(|item| !item.is_burned)
+    }
+
+    pub fn get_channel(&self, channel_id: &[u8]) -> Option<PaymentChannel> {
+        if let Some(channel) = self.pending_channels.get(channel_id) {
+            return Some(channel.clone());
+        }
+        self.storage.get_channel(channel_id).unwrap_or(None)
+    }
+
+    /// Swap order, kept once filled or cancelled
+    pub fn get_order(&self, order_id: &[u8]) -> Option<Order> {
+        if let Some(order) = self.pending_orders.get(order_id) {
+            return Some(order.clone());
+        }
+        self.storage.get_order(order_id).unwrap_or(None)
+    }
+
+    /// Signer set of a multisig account; `None` for single-key accounts
+    pub fn get_multisig(&self, address: &[u8]) -> Option<MultisigAccount> {
+        if let Some(multisig) = self.pending_multisigs.get(address) {
+            return Some(multisig.clone());
+        }
+        self.storage.get_multisig(address).unwrap_or(None)
+    }
+
+    /// Validator record, including unregistered (zero stake) entries
+    pub fn get_validator(&self, pubkey: &[u8]) -> Option<ValidatorInfo> {
+        if let Some(validator) = self.pending_validators.get(pubkey) {
+            return Some(validator.clone());
+        }
+        self.storage.get_validator(pubkey).unwrap_or(None)
+    }
+
+    /// All validator records, with uncommitted changes applied
+    pub fn get_all_validators(&self) -> Vec<ValidatorInfo> {
+        let mut validators: BTreeMap<Vec<u8>, ValidatorInfo> = self.storage.get_all_validators()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| (v.pubkey.clone(), v))
+            .collect();
+        for (pubkey, validator) in &self.pending_validators {
+            validators.insert(pubkey.clone(), validator.clone());
+        }
+        validators.into_values().collect()
+    }
+
+    /// Add a validator record directly (genesis setup)
+    pub fn add_validator(&mut self, validator: ValidatorInfo) {
+        self.pending_validators.insert(validator.pubkey.clone(), validator);
+    }
+
+    /// Set how many epochs withdrawn stake stays locked after a validator exits
+    pub fn set_unbonding_epochs(&mut self, epochs: u64) {
+        self.unbonding_epochs = epochs;
+    }
+
+    /// Set how many slots a unilateral channel close can be challenged for
+    pub fn set_channel_challenge_slots(&mut self, slots: u64) {
+        self.channel_challenge_slots = slots;
+    }
+
+    /// Prepare to execute a block at `slot`
+    /// Validators whose unbonding period is over get their stake back at their reward address.
+    pub fn begin_block(&mut self, slot: u64) {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        self.block_slot = slot;
+        self.block_epoch = epoch;
+
+        for mut validator in self.get_all_validators() {
+            if validator.is_unbonding() && validator.withdrawable_epoch <= epoch {
+                self.credit_balance(&validator.reward_address, validator.stake);
+                validator.stake = 0;
+                self.pending_validators.insert(validator.pubkey.clone(), validator);
+            }
+        }
+    }
+
+    /// Slash a validator, burning its bonded or unbonding stake
+    /// Returns the amount slashed.
+    pub fn slash_validator(&mut self, pubkey: &[u8]) -> Result<u64, String> {
+        let mut validator = self.get_validator(pubkey).ok_or("Validator not found")?;
+        if validator.slashed {
+            return Err("Validator already slashed".to_string());
+        }
+
+        let amount = validator.stake;
+        validator.slashed = true;
+        validator.stake = 0;
+        self.pending_validators.insert(pubkey.to_vec(), validator);
+        Ok(amount)
+    }
+
     pub fn get_balance(&self, address: &[u8]) -> u64 {
         self.get_account(address)
             .map(|acc| acc.balance)
@@ -130,25 +429,56 @@ impl State {
 
     // Generic apply transaction
     pub fn apply_transaction(&mut self, tx: &Transaction) -> Result<(), String> {
+        // 0. Check Expiry
+        if tx.is_expired(self.block_slot) {
+            return Err(format!("Transaction expired after slot {}", tx.valid_until_slot.unwrap_or_default()));
+        }
+
         // 1. Check Nonce
         let nonce = self.get_nonce(&tx.sender);
         if tx.nonce != nonce {
              return Err(format!("Invalid nonce. Expected {}, got {}", nonce, tx.nonce));
         }
 
-        // 2. Process Data
+        // 2. Charge Fee (credited to the block producer by Chain)
+        self.charge_fee(&tx.sender, tx.fee)?;
+
+        // 3. Process Data
         match &tx.data {
             TransactionData::NativeTransfer { amount } => {
                 self.transfer_native(&tx.sender, &tx.receiver, *amount)?;
             },
-            TransactionData::CreateAsset { name, symbol, supply, decimals, metadata } => {
-                self.create_asset(&tx.sender, tx, name, symbol, *supply, *decimals, metadata)?;
+            TransactionData::CreateAsset { name, symbol, supply, decimals, metadata, max_supply } => {
+                self.create_asset(&tx.sender, tx, name, symbol, *supply, *decimals, metadata, *max_supply)?;
             },
             TransactionData::TransferAsset { asset_id, amount } => {
                 self.transfer_asset(&tx.sender, &tx.receiver, asset_id, *amount)?;
             },
-            TransactionData::CreateCollection { name, symbol, metadata } => {
-                self.create_collection(&tx.sender, tx, name, symbol, metadata)?;
+            TransactionData::ApproveAsset { asset_id, spender, amount } => {
+                self.approve_asset(&tx.sender, asset_id, spender, *amount)?;
+            },
+            TransactionData::TransferAssetFrom { asset_id, owner, amount } => {
+                self.transfer_asset_from(owner, &tx.sender, &tx.receiver, asset_id, *amount)?;
+            },
+            TransactionData::MintAsset { asset_id, amount } => {
+                self.mint_asset(&tx.sender, &tx.receiver, asset_id, *amount)?;
+            },
+            TransactionData::BurnAsset { asset_id, amount } => {
+                self.burn_asset(&tx.sender, asset_id, *amount)?;
+            },
+            TransactionData::SetAssetAdmin { asset_id, new_admin } => {
+                let mut asset = self.asset_for_admin(&tx.sender, asset_id)?;
+                asset.admin = new_admin.clone();
+                self.pending_assets.insert(asset_id.clone(), asset);
+            },
+            TransactionData::FreezeAsset { asset_id } => {
+                self.set_asset_frozen(&tx.sender, asset_id, true)?;
+            },
+            TransactionData::UnfreezeAsset { asset_id } => {
+                self.set_asset_frozen(&tx.sender, asset_id, false)?;
+            },
+            TransactionData::CreateCollection { name, symbol, metadata, max_items } => {
+                self.create_collection(&tx.sender, tx, name, symbol, metadata, *max_items)?;
             },
             TransactionData::MintNFT { collection_id, item_id, item_metadata, recipient } => {
                 self.mint_nft(&tx.sender, collection_id, *item_id, item_metadata, recipient)?;
@@ -156,39 +486,90 @@ impl State {
             TransactionData::TransferNFT { collection_id, item_id } => {
                 self.transfer_nft(&tx.sender, &tx.receiver, collection_id, *item_id)?;
             },
+            TransactionData::ApproveNFT { collection_id, item_id, operator, approved } => {
+                self.approve_nft(&tx.sender, collection_id, *item_id, operator, *approved)?;
+            },
+            TransactionData::BurnNFT { collection_id, item_id } => {
+                self.burn_nft(&tx.sender, collection_id, *item_id)?;
+            },
+            TransactionData::UpdateNFTMetadata { collection_id, item_id, metadata, freeze } => {
+                self.update_nft_metadata(&tx.sender, collection_id, *item_id, metadata, *freeze)?;
+            },
+            TransactionData::CreateOrder { offer, ask, taker, expiry } => {
+                self.create_order(&tx.sender, tx, offer, ask, taker.as_deref(), *expiry)?;
+            },
+            TransactionData::FillOrder { order_id } => {
+                self.fill_order(&tx.sender, order_id)?;
+            },
+            TransactionData::CancelOrder { order_id } => {
+                self.cancel_order(&tx.sender, order_id)?;
+            },
             TransactionData::OpenChannel { partner, amount, duration } => {
                 self.open_channel(&tx.sender, partner, *amount, *duration, tx)?;
             },
-            TransactionData::CloseChannel { channel_id, balance_proof: _, final_balance_a, final_balance_b } => {
-                 self.close_channel(&tx.sender, channel_id, *final_balance_a, *final_balance_b)?;
+            TransactionData::CloseChannel { channel_id, balance_proof } => {
+                self.close_channel(&tx.sender, channel_id, balance_proof)?;
             },
-            TransactionData::DelegateSpend { delegate, allowance, expiry: _ } => {
-                self.delegate_spend(&tx.sender, delegate, *allowance)?;
+            TransactionData::StartChannelClose { channel_id, balance_proof } => {
+                self.start_channel_close(&tx.sender, channel_id, balance_proof.as_ref())?;
             },
-            TransactionData::RegisterValidator { stake } => {
-                // Deduct stake from balance
-                let mut account = self.get_account(&tx.sender).ok_or("Account not found")?;
-                if account.balance < *stake {
-                    return Err("Insufficient balance for stake".to_string());
-                }
-                account.balance -= stake;
-                self.pending_changes.insert(tx.sender.clone(), account);
-            },
-            TransactionData::UnregisterValidator => {
-                // Refund is handled by Chain/ValidatorSet interaction since State doesn't know staked amount.
-                // Here we just validate account existence
-                if self.get_account(&tx.sender).is_none() {
-                    return Err("Account not found".to_string());
-                }
+            TransactionData::ChallengeChannelClose { channel_id, balance_proof } => {
+                self.challenge_channel_close(&tx.sender, channel_id, balance_proof)?;
+            },
+            TransactionData::SettleChannel { channel_id } => {
+                self.settle_channel(&tx.sender, channel_id)?;
+            },
+            TransactionData::DelegateSpend { delegate, allowance, expiry } => {
+                self.delegate_spend(&tx.sender, delegate, *allowance, *expiry)?;
+            },
+            TransactionData::DelegatedTransfer { owner, amount } => {
+                self.delegated_transfer(owner, &tx.sender, &tx.receiver, *amount)?;
+            },
+            TransactionData::RevokeDelegation { delegate } => {
+                self.revoke_delegation(&tx.sender, delegate)?;
+            },
+            TransactionData::CreateMultisig { signers, threshold } => {
+                self.create_multisig(signers, *threshold)?;
+            },
+            TransactionData::RegisterValidator { stake, validator_pubkey, .. } => {
+                // The proof of possession is checked in Transaction::validate_logic
+                self.register_validator(&tx.sender, validator_pubkey, *stake)?;
+            },
+            TransactionData::UnregisterValidator { validator_pubkey } => {
+                self.unregister_validator(&tx.sender, validator_pubkey)?;
+            },
+            TransactionData::SubmitEquivocationEvidence { header_a, .. } => {
+                // Headers are checked in Transaction::validate_logic
+                self.punish_equivocation(&tx.sender, &header_a.validator_pubkey)?;
             },
         }
 
-        // 3. Increment Nonce
+        // 4. Increment Nonce
         self.increment_nonce(&tx.sender);
 
         Ok(())
     }
 
+    fn charge_fee(&mut self, payer: &[u8], fee: u64) -> Result<(), String> {
+        let mut account = self.get_account(payer).ok_or("Sender account not found")?;
+
+        if account.balance < fee {
+            return Err("Insufficient balance for fee".to_string());
+        }
+
+        account.balance -= fee;
+        self.pending_changes.insert(payer.to_vec(), account);
+        Ok(())
+    }
+
+    /// Add native balance to an account (fee payouts, stake refunds)
+    /// Saturates rather than wrapping, as payouts have no caller to return an error to.
+    pub fn credit_balance(&mut self, address: &[u8], amount: u64) {
+        let mut account = self.get_account(address).unwrap_or(Account::new(0));
+        account.balance = account.balance.saturating_add(amount);
+        self.pending_changes.insert(address.to_vec(), account);
+    }
+
     fn transfer_native(&mut self, from: &[u8], to: &[u8], amount: u64) -> Result<(), String> {
         // Reject self-transfers - sending to yourself is not allowed
         if from == to {
@@ -211,11 +592,11 @@ impl State {
         Ok(())
     }
 
-    fn create_asset(&mut self, issuer: &[u8], tx: &Transaction, name: &str, symbol: &str, supply: u64, decimals: u8, metadata: &[u8]) -> Result<(), String> {
+    fn create_asset(&mut self, issuer: &[u8], tx: &Transaction, name: &str, symbol: &str, supply: u64, decimals: u8, metadata: &[u8], max_supply: Option<u64>) -> Result<(), String> {
         // Calculate ID
         let asset_id = tx.calculate_asset_id().ok_or("Failed to calculate asset ID")?;
 
-        if self.assets.contains_key(&asset_id) {
+        if self.get_asset(&asset_id).is_some() {
             return Err("Asset already exists".to_string());
         }
 
@@ -227,6 +608,9 @@ impl State {
             total_supply: supply,
             decimals,
             metadata: metadata.to_vec(),
+            admin: issuer.to_vec(),
+            max_supply,
+            is_frozen: false,
         };
 
         // Give initial supply to issuer
@@ -234,14 +618,15 @@ impl State {
         issuer_account.assets.insert(asset_id.clone(), supply);
         
         self.pending_changes.insert(issuer.to_vec(), issuer_account);
-        self.assets.insert(asset_id, asset);
+        self.pending_assets.insert(asset_id, asset);
 
         Ok(())
     }
 
     fn transfer_asset(&mut self, from: &[u8], to: &[u8], asset_id: &[u8], amount: u64) -> Result<(), String> {
-        if !self.assets.contains_key(asset_id) {
-            return Err("Asset does not exist".to_string());
+        let asset = self.get_asset(asset_id).ok_or("Asset does not exist")?;
+        if asset.is_frozen {
+            return Err("Asset is frozen".to_string());
         }
 
         let mut from_account = self.get_account(from).ok_or("Sender account not found")?;
@@ -262,10 +647,130 @@ impl State {
         Ok(())
     }
 
-    fn create_collection(&mut self, issuer: &[u8], tx: &Transaction, name: &str, symbol: &str, metadata: &[u8]) -> Result<(), String> {
+    /// Let `spender` move up to `amount` of the owner's asset (0 revokes)
+    fn approve_asset(&mut self, owner: &[u8], asset_id: &[u8], spender: &[u8], amount: u64) -> Result<(), String> {
+        if self.get_asset(asset_id).is_none() {
+            return Err("Asset does not exist".to_string());
+        }
+
+        let mut owner_account = self.get_account(owner).ok_or("Owner account not found")?;
+        let allowances = owner_account.asset_allowances.entry(asset_id.to_vec()).or_default();
+        if amount == 0 {
+            allowances.remove(spender);
+        } else {
+            allowances.insert(spender.to_vec(), amount);
+        }
+        if allowances.is_empty() {
+            owner_account.asset_allowances.remove(asset_id);
+        }
+
+        self.pending_changes.insert(owner.to_vec(), owner_account);
+        Ok(())
+    }
+
+    /// Move `amount` of the owner's asset to `to` on the authority of `spender`, using up its allowance
+    fn transfer_asset_from(&mut self, owner: &[u8], spender: &[u8], to: &[u8], asset_id: &[u8], amount: u64) -> Result<(), String> {
+        let allowance = self.get_asset_allowance(owner, asset_id, spender);
+        if allowance < amount {
+            return Err(format!("Amount exceeds asset allowance. Remaining: {}, Requested: {}", allowance, amount));
+        }
+
+        self.transfer_asset(owner, to, asset_id, amount)?;
+
+        let mut owner_account = self.get_account(owner).ok_or("Owner account not found")?;
+        if let Some(allowances) = owner_account.asset_allowances.get_mut(asset_id) {
+            Self::decrement_allowance(allowances, spender, allowance - amount, |remaining| remaining);
+            if allowances.is_empty() {
+                owner_account.asset_allowances.remove(asset_id);
+            }
+        }
+        self.pending_changes.insert(owner.to_vec(), owner_account);
+        Ok(())
+    }
+
+    /// Store what is left of `spender`'s allowance after a spend
+    /// Spent allowances are removed rather than kept at zero.
+    fn decrement_allowance<V>(allowances: &mut BTreeMap<Vec<u8>, V>, spender: &[u8], remaining: u64, entry: impl FnOnce(u64) -> V) {
+        if remaining == 0 {
+            allowances.remove(spender);
+        } else {
+            allowances.insert(spender.to_vec(), entry(remaining));
+        }
+    }
+
+    /// Amount of `asset_id` that `spender` may still move out of the owner's account
+    pub fn get_asset_allowance(&self, owner: &[u8], asset_id: &[u8], spender: &[u8]) -> u64 {
+        self.get_account(owner)
+            .and_then(|account| account.asset_allowances.get(asset_id)?.get(spender).copied())
+            .unwrap_or(0)
+    }
+
+    /// Load an asset the sender administers
+    fn asset_for_admin(&self, sender: &[u8], asset_id: &[u8]) -> Result<Asset, String> {
+        let asset = self.get_asset(asset_id).ok_or("Asset does not exist")?;
+        if asset.admin != sender {
+            return Err("Only the asset admin can do this".to_string());
+        }
+        Ok(asset)
+    }
+
+    fn mint_asset(&mut self, admin: &[u8], to: &[u8], asset_id: &[u8], amount: u64) -> Result<(), String> {
+        let mut asset = self.asset_for_admin(admin, asset_id)?;
+        if asset.is_frozen {
+            return Err("Asset is frozen".to_string());
+        }
+
+        let total_supply = asset.total_supply.checked_add(amount).ok_or("Total supply overflow")?;
+        if let Some(max_supply) = asset.max_supply {
+            if total_supply > max_supply {
+                return Err(format!("Mint would exceed max supply of {}", max_supply));
+            }
+        }
+        asset.total_supply = total_supply;
+
+        let mut to_account = self.get_account(to).unwrap_or(Account::new(0));
+        let to_balance = to_account.assets.get(asset_id).cloned().unwrap_or(0);
+        to_account.assets.insert(asset_id.to_vec(), to_balance + amount);
+        self.pending_changes.insert(to.to_vec(), to_account);
+        self.pending_assets.insert(asset_id.to_vec(), asset);
+
+        Ok(())
+    }
+
+    fn burn_asset(&mut self, holder: &[u8], asset_id: &[u8], amount: u64) -> Result<(), String> {
+        let mut asset = self.get_asset(asset_id).ok_or("Asset does not exist")?;
+        if asset.is_frozen {
+            return Err("Asset is frozen".to_string());
+        }
+
+        let mut account = self.get_account(holder).ok_or("Sender account not found")?;
+        let balance = account.assets.get(asset_id).cloned().unwrap_or(0);
+        if balance < amount {
+            return Err("Insufficient asset balance".to_string());
+        }
+
+        account.assets.insert(asset_id.to_vec(), balance - amount);
+        asset.total_supply -= amount;
+        self.pending_changes.insert(holder.to_vec(), account);
+        self.pending_assets.insert(asset_id.to_vec(), asset);
+
+        Ok(())
+    }
+
+    fn set_asset_frozen(&mut self, admin: &[u8], asset_id: &[u8], frozen: bool) -> Result<(), String> {
+        let mut asset = self.asset_for_admin(admin, asset_id)?;
+        if asset.is_frozen == frozen {
+            return Err(if frozen { "Asset is already frozen" } else { "Asset is not frozen" }.to_string());
+        }
+        asset.is_frozen = frozen;
+        self.pending_assets.insert(asset_id.to_vec(), asset);
+        Ok(())
+    }
+
+    fn create_collection(&mut self, issuer: &[u8], tx: &Transaction, name: &str, symbol: &str, metadata: &[u8], max_items: Option<u64>) -> Result<(), String> {
         let collection_id = tx.calculate_asset_id().ok_or("Failed to calc ID")?;
 
-        if self.collections.contains_key(&collection_id) {
+        if self.get_collection(&collection_id).is_some() {
             return Err("Collection already exists".to_string());
         }
 
@@ -275,31 +780,44 @@ impl State {
             name: name.to_string(),
             symbol: symbol.to_string(),
             metadata: metadata.to_vec(),
-            items: HashMap::new(),
+            max_items,
+            minted: 0,
         };
 
-        self.collections.insert(collection_id, collection);
+        self.pending_collections.insert(collection_id, collection);
         Ok(())
     }
 
     fn mint_nft(&mut self, sender: &[u8], collection_id: &[u8], item_id: u64, item_metadata: &[u8], recipient: &[u8]) -> Result<(), String> {
-        let collection = self.collections.get_mut(collection_id).ok_or("Collection not found")?;
+        let mut collection = self.get_collection(collection_id).ok_or("Collection not found")?;
         
         if collection.issuer != sender {
             return Err("Only issuer can mint".to_string());
         }
 
-        if collection.items.contains_key(&item_id) {
+        if self.get_nft_record(collection_id, item_id).is_some() {
             return Err("Item ID already exists".to_string());
         }
 
+        if let Some(max_items) = collection.max_items {
+            if collection.minted >= max_items {
+                return Err(format!("Collection is capped at {} items", max_items));
+            }
+        }
+
         let item = NFTItem {
             id: item_id,
+            collection_id: collection_id.to_vec(),
             owner: recipient.to_vec(),
             metadata: item_metadata.to_vec(),
+            approved: None,
+            metadata_frozen: false,
+            is_burned: false,
         };
 
-        collection.items.insert(item_id, item);
+        collection.minted += 1;
+        self.pending_collections.insert(collection_id.to_vec(), collection);
+        self.pending_nfts.insert((collection_id.to_vec(), item_id), item);
 
         // Add to recipient's account
         let mut recipient_account = self.get_account(recipient).unwrap_or(Account::new(0));
@@ -311,23 +829,41 @@ impl State {
         Ok(())
     }
 
-    fn transfer_nft(&mut self, from: &[u8], to: &[u8], collection_id: &[u8], item_id: u64) -> Result<(), String> {
-        let collection = self.collections.get_mut(collection_id).ok_or("Collection not found")?;
-        let item = collection.items.get_mut(&item_id).ok_or("Item not found")?;
+    /// Load an item `sender` may move: its owner, its approved address, or an operator of the owner
+    pub fn nft_for_mover(&self, sender: &[u8], collection_id: &[u8], item_id: u64) -> Result<NFTItem, String> {
+        let item = self.get_nft(collection_id, item_id).ok_or("Item not found")?;
 
-        if item.owner != from {
+        let authorized = item.owner == sender
+            || item.approved.as_deref() == Some(sender)
+            || self.is_nft_operator(&item.owner, collection_id, sender);
+        if !authorized {
             return Err("Sender does not own this NFT".to_string());
         }
+        Ok(item)
+    }
+
+    /// Whether `operator` may move all of the owner's items in the collection
+    pub fn is_nft_operator(&self, owner: &[u8], collection_id: &[u8], operator: &[u8]) -> bool {
+        self.get_account(owner)
+            .and_then(|account| account.nft_operators.get(collection_id).map(|operators| operators.contains(operator)))
+            .unwrap_or(false)
+    }
+
+    fn transfer_nft(&mut self, sender: &[u8], to: &[u8], collection_id: &[u8], item_id: u64) -> Result<(), String> {
+        let mut item = self.nft_for_mover(sender, collection_id, item_id)?;
+        let from = item.owner.clone();
 
-        // Update item owner
+        // Update item owner; approvals do not carry over to the new owner
         item.owner = to.to_vec();
+        item.approved = None;
+        self.pending_nfts.insert((collection_id.to_vec(), item_id), item);
 
         // Remove from sender
-        let mut from_account = self.get_account(from).ok_or("Sender account not found")?;
+        let mut from_account = self.get_account(&from).ok_or("Sender account not found")?;
         if let Some(nfts) = from_account.nfts.get_mut(collection_id) {
             nfts.retain(|&x| x != item_id);
         }
-        self.pending_changes.insert(from.to_vec(), from_account);
+        self.pending_changes.insert(from, from_account);
 
         // Add to receiver
         let mut to_account = self.get_account(to).unwrap_or(Account::new(0));
@@ -338,107 +874,733 @@ impl State {
         Ok(())
     }
 
-    fn open_channel(&mut self, sender: &[u8], partner: &[u8], amount: u64, duration: u64, tx: &Transaction) -> Result<(), String> {
-        let mut sender_account = self.get_account(sender).ok_or("Sender account not found")?;
-        
-        if sender_account.balance < amount {
-            return Err("Insufficient balance for channel deposit".to_string());
+    /// Approve or revoke `operator` for one item, or for all of the owner's items in the collection
+    fn approve_nft(&mut self, owner: &[u8], collection_id: &[u8], item_id: Option<u64>, operator: &[u8], approved: bool) -> Result<(), String> {
+        match item_id {
+            Some(item_id) => {
+                let mut item = self.get_nft(collection_id, item_id).ok_or("Item not found")?;
+                if item.owner != owner {
+                    return Err("Sender does not own this NFT".to_string());
+                }
+                if approved {
+                    item.approved = Some(operator.to_vec());
+                } else if item.approved.as_deref() == Some(operator) {
+                    item.approved = None;
+                }
+                self.pending_nfts.insert((collection_id.to_vec(), item_id), item);
+            }
+            None => {
+                if self.get_collection(collection_id).is_none() {
+                    return Err("Collection not found".to_string());
+                }
+                let mut owner_account = self.get_account(owner).ok_or("Owner account not found")?;
+                let operators = owner_account.nft_operators.entry(collection_id.to_vec()).or_default();
+                if approved {
+                    operators.insert(operator.to_vec());
+                } else {
+                    operators.remove(operator);
+                }
+                if operators.is_empty() {
+                    owner_account.nft_operators.remove(collection_id);
+                }
+                self.pending_changes.insert(owner.to_vec(), owner_account);
+            }
         }
+        Ok(())
+    }
 
-        // Deduct deposit
-        sender_account.balance -= amount;
-        self.pending_changes.insert(sender.to_vec(), sender_account);
+    fn burn_nft(&mut self, sender: &[u8], collection_id: &[u8], item_id: u64) -> Result<(), String> {
+        let mut item = self.nft_for_mover(sender, collection_id, item_id)?;
 
-        let channel_id = tx.calculate_asset_id().ok_or("Failed to calc Channel ID")?;
-        
-        if self.channels.contains_key(&channel_id) {
-            return Err("Channel ID collision".to_string());
+        let mut owner_account = self.get_account(&item.owner).ok_or("Owner account not found")?;
+        if let Some(nfts) = owner_account.nfts.get_mut(collection_id) {
+            nfts.retain(|&x| x != item_id);
         }
+        self.pending_changes.insert(item.owner.clone(), owner_account);
 
-        let channel = PaymentChannel {
-            id: channel_id.clone(),
-            partner_a: sender.to_vec(),
-            partner_b: partner.to_vec(),
-            total_deposit: amount,
-            expiry: duration, 
-            is_closed: false,
-        };
+        item.is_burned = true;
+        item.approved = None;
+        self.pending_nfts.insert((collection_id.to_vec(), item_id), item);
+        Ok(())
+    }
 
-        self.channels.insert(channel_id, channel);
-        Ok(())
-    }
-
-    fn close_channel(&mut self, sender: &[u8], channel_id: &[u8], final_balance_a: u64, final_balance_b: u64) -> Result<(), String> {
-        // Scope to hold the mutable borrow of channel
-        let (partner_a, partner_b, total_deposit) = {
-             let channel = self.channels.get_mut(channel_id).ok_or("Channel not found")?;
-             
-             if channel.is_closed {
-                 return Err("Channel already closed".to_string());
-             }
-             
-             // Authorization check: either partner can close (if they provide proof)
-             if sender != channel.partner_a && sender != channel.partner_b {
-                  return Err("Not authorized to close channel".to_string());
-             }
-             
-             channel.is_closed = true;
-             
-             (channel.partner_a.clone(), channel.partner_b.clone(), channel.total_deposit)
-        }; // Borrow ends here
-
-        // Validate balances match deposit
-        if final_balance_a + final_balance_b != total_deposit {
-            return Err("Balance mismatch: total does not match deposit".to_string());
+    fn update_nft_metadata(&mut self, sender: &[u8], collection_id: &[u8], item_id: u64, metadata: &[u8], freeze: bool) -> Result<(), String> {
+        let collection = self.get_collection(collection_id).ok_or("Collection not found")?;
+        if collection.issuer != sender {
+            return Err("Only issuer can update metadata".to_string());
         }
 
-        // Payout A
-        let mut account_a = self.get_account(&partner_a).unwrap_or(Account::new(0));
-        account_a.balance += final_balance_a;
-        self.pending_changes.insert(partner_a, account_a);
-
-        // Payout B
-        let mut account_b = self.get_account(&partner_b).unwrap_or(Account::new(0));
-        account_b.balance += final_balance_b;
-        self.pending_changes.insert(partner_b, account_b);
+        let mut item = self.get_nft(collection_id, item_id).ok_or("Item not found")?;
+        if item.metadata_frozen {
+            return Err("Item metadata is frozen".to_string());
+        }
 
+        item.metadata = metadata.to_vec();
+        item.metadata_frozen = freeze;
+        self.pending_nfts.insert((collection_id.to_vec(), item_id), item);
         Ok(())
     }
 
-    fn delegate_spend(&mut self, owner: &[u8], delegate: &[u8], allowance: u64) -> Result<(), String> {
-        let mut owner_account = self.get_account(owner).unwrap_or(Account::new(0)); 
-        
-        // Set allowance
-        owner_account.delegated_allowance.insert(delegate.to_vec(), allowance);
-        
-        self.pending_changes.insert(owner.to_vec(), owner_account);
-        Ok(())
-    }
+    fn create_order(&mut self, maker: &[u8], tx: &Transaction, offer: &OrderAsset, ask: &OrderAsset, taker: Option<&[u8]>, expiry: u64) -> Result<(), String> {
+        if expiry < self.block_slot {
+            return Err(format!("Order expiry {} is in the past", expiry));
+        }
 
+        let order_id = tx.calculate_asset_id().ok_or("Failed to calc Order ID")?;
+        if self.get_order(&order_id).is_some() {
+            return Err("Order ID collision".to_string());
+        }
 
+        self.escrow_order_asset(maker, &order_id, offer)?;
 
-    pub fn increment_nonce(&mut self, address: &[u8]) {
-        let mut account = self.get_account(address).unwrap_or(Account::new(0));
-        account.nonce += 1;
-        self.pending_changes.insert(address.to_vec(), account);
+        let order = Order {
+            id: order_id.clone(),
+            maker: maker.to_vec(),
+            offer: offer.clone(),
+            ask: ask.clone(),
+            taker: taker.map(|taker| taker.to_vec()),
+            expiry,
+            is_closed: false,
+            filled_by: None,
+        };
+        self.pending_orders.insert(order_id, order);
+        Ok(())
     }
 
-    pub fn apply_changes(&mut self) -> Result<(), String> {
-        for (address, account) in &self.pending_changes {
-            self.storage.store_account(address, account)
-                .map_err(|e| format!("Failed to store account: {}", e))?;
-            
-            // Update trie with account data
-            let account_data = bincode::serialize(account)
-                .map_err(|e| format!("Failed to serialize account: {}", e))?;
-            self.trie.insert(address.clone(), account_data);
+    /// Load an order that can still be filled or cancelled
+    fn open_order(&self, order_id: &[u8]) -> Result<Order, String> {
+        let order = self.get_order(order_id).ok_or("Order not found")?;
+        if order.is_closed {
+            return Err("Order already closed".to_string());
         }
-        self.pending_changes.clear();
-        Ok(())
+        Ok(order)
     }
 
-    pub fn discard_changes(&mut self) {
+    /// Pay the ask to the maker and the escrowed offer to the taker, in one step
+    fn fill_order(&mut self, taker: &[u8], order_id: &[u8]) -> Result<(), String> {
+        let mut order = self.open_order(order_id)?;
+
+        if order.maker == taker {
+            return Err("Cannot fill own order".to_string());
+        }
+        if order.taker.as_ref().is_some_and(|reserved| reserved != taker) {
+            return Err("Order is reserved for another taker".to_string());
+        }
+        if self.block_slot > order.expiry {
+            return Err(format!("Order expired at slot {}", order.expiry));
+        }
+
+        match &order.ask {
+            OrderAsset::Native { amount } => self.transfer_native(taker, &order.maker, *amount)?,
+            OrderAsset::Asset { asset_id, amount } => self.transfer_asset(taker, &order.maker, asset_id, *amount)?,
+            OrderAsset::NFT { collection_id, item_id } => self.transfer_nft(taker, &order.maker, collection_id, *item_id)?,
+        }
+        self.release_order_asset(order_id, taker, &order.offer)?;
+
+        order.is_closed = true;
+        order.filled_by = Some(taker.to_vec());
+        self.pending_orders.insert(order_id.to_vec(), order);
+        Ok(())
+    }
+
+    /// Maker only; the escrowed offer goes back to the maker
+    fn cancel_order(&mut self, sender: &[u8], order_id: &[u8]) -> Result<(), String> {
+        let mut order = self.open_order(order_id)?;
+        if order.maker != sender {
+            return Err("Only the maker can cancel an order".to_string());
+        }
+
+        self.release_order_asset(order_id, sender, &order.offer)?;
+
+        order.is_closed = true;
+        self.pending_orders.insert(order_id.to_vec(), order);
+        Ok(())
+    }
+
+    /// Move the maker's offer into the order; escrowed NFTs are owned by the order ID
+    fn escrow_order_asset(&mut self, maker: &[u8], order_id: &[u8], offer: &OrderAsset) -> Result<(), String> {
+        let mut maker_account = self.get_account(maker).ok_or("Sender account not found")?;
+
+        match offer {
+            OrderAsset::Native { amount } => {
+                if maker_account.balance < *amount {
+                    return Err("Insufficient native balance".to_string());
+                }
+                maker_account.balance -= amount;
+            }
+            OrderAsset::Asset { asset_id, amount } => {
+                let asset = self.get_asset(asset_id).ok_or("Asset does not exist")?;
+                if asset.is_frozen {
+                    return Err("Asset is frozen".to_string());
+                }
+                let balance = maker_account.assets.get(asset_id).cloned().unwrap_or(0);
+                if balance < *amount {
+                    return Err("Insufficient asset balance".to_string());
+                }
+                maker_account.assets.insert(asset_id.clone(), balance - amount);
+            }
+            OrderAsset::NFT { collection_id, item_id } => {
+                let mut item = self.get_nft(collection_id, *item_id).ok_or("Item not found")?;
+                if item.owner != maker {
+                    return Err("Sender does not own this NFT".to_string());
+                }
+                if let Some(nfts) = maker_account.nfts.get_mut(collection_id) {
+                    nfts.retain(|&x| x != *item_id);
+                }
+                item.owner = order_id.to_vec();
+                item.approved = None;
+                self.pending_nfts.insert((collection_id.clone(), *item_id), item);
+            }
+        }
+
+        self.pending_changes.insert(maker.to_vec(), maker_account);
+        Ok(())
+    }
+
+    /// Pay an escrowed offer out of the order
+    fn release_order_asset(&mut self, order_id: &[u8], to: &[u8], offer: &OrderAsset) -> Result<(), String> {
+        let mut to_account = self.get_account(to).unwrap_or(Account::new(0));
+
+        match offer {
+            OrderAsset::Native { amount } => {
+                to_account.balance += amount;
+            }
+            OrderAsset::Asset { asset_id, amount } => {
+                let asset = self.get_asset(asset_id).ok_or("Asset does not exist")?;
+                if asset.is_frozen {
+                    return Err("Asset is frozen".to_string());
+                }
+                let balance = to_account.assets.get(asset_id).cloned().unwrap_or(0);
+                to_account.assets.insert(asset_id.clone(), balance + amount);
+            }
+            OrderAsset::NFT { collection_id, item_id } => {
+                let mut item = self.get_nft(collection_id, *item_id).ok_or("Item not found")?;
+                if item.owner != order_id {
+                    return Err("Order does not hold this NFT".to_string());
+                }
+                item.owner = to.to_vec();
+                self.pending_nfts.insert((collection_id.clone(), *item_id), item);
+                to_account.nfts.entry(collection_id.clone()).or_default().push(*item_id);
+            }
+        }
+
+        self.pending_changes.insert(to.to_vec(), to_account);
+        Ok(())
+    }
+
+    /// Register the signer set of a multisig account; funds may already sit at its address
+    fn create_multisig(&mut self, signers: &[Vec<u8>], threshold: u32) -> Result<(), String> {
+        let address = Transaction::multisig_address(signers, threshold);
+        if self.get_multisig(&address).is_some() {
+            return Err("Multisig account already exists".to_string());
+        }
+
+        let mut signers = signers.to_vec();
+        signers.sort();
+        self.pending_multisigs.insert(address.clone(), MultisigAccount { address, signers, threshold });
+        Ok(())
+    }
+
+    fn open_channel(&mut self, sender: &[u8], partner: &[u8], amount: u64, duration: u64, tx: &Transaction) -> Result<(), String> {
+        let mut sender_account = self.get_account(sender).ok_or("Sender account not found")?;
+        
+        if sender_account.balance < amount {
+            return Err("Insufficient balance for channel deposit".to_string());
+        }
+
+        // Deduct deposit
+        sender_account.balance -= amount;
+        self.pending_changes.insert(sender.to_vec(), sender_account);
+
+        let channel_id = tx.calculate_asset_id().ok_or("Failed to calc Channel ID")?;
+        
+        if self.get_channel(&channel_id).is_some() {
+            return Err("Channel ID collision".to_string());
+        }
+
+        let channel = PaymentChannel {
+            id: channel_id.clone(),
+            partner_a: sender.to_vec(),
+            partner_b: partner.to_vec(),
+            total_deposit: amount,
+            expiry: self.block_slot.saturating_add(duration),
+            is_closed: false,
+            closing: None,
+        };
+
+        self.pending_channels.insert(channel_id, channel);
+        Ok(())
+    }
+
+    /// Load an open channel the sender is a partner of
+    fn open_channel_for(&self, sender: &[u8], channel_id: &[u8]) -> Result<PaymentChannel, String> {
+        let channel = self.get_channel(channel_id).ok_or("Channel not found")?;
+
+        if channel.is_closed {
+            return Err("Channel already closed".to_string());
+        }
+
+        if sender != channel.partner_a && sender != channel.partner_b {
+            return Err("Not authorized to close channel".to_string());
+        }
+
+        Ok(channel)
+    }
+
+    /// Check a balance proof is co-signed by both partners and splits the whole deposit
+    fn verify_balance_proof(channel: &PaymentChannel, proof: &BalanceProof) -> Result<(), String> {
+        if proof.channel_id != channel.id {
+            return Err("Balance proof is for a different channel".to_string());
+        }
+        proof.verify(&channel.partner_a, &channel.partner_b)?;
+        if proof.balance_a.checked_add(proof.balance_b) != Some(channel.total_deposit) {
+            return Err("Balance mismatch: total does not match deposit".to_string());
+        }
+        Ok(())
+    }
+
+    /// Once expired, the opener can only take the deposit back
+    fn check_channel_expiry(&self, channel: &PaymentChannel, balance_a: u64) -> Result<(), String> {
+        if self.block_slot > channel.expiry && balance_a != channel.total_deposit {
+            return Err(format!("Channel expired at slot {}: the deposit can only be refunded to the opener", channel.expiry));
+        }
+        Ok(())
+    }
+
+    /// Mark the channel closed and pay both partners
+    fn payout_channel(&mut self, mut channel: PaymentChannel, balance_a: u64, balance_b: u64) {
+        let mut account_a = self.get_account(&channel.partner_a).unwrap_or(Account::new(0));
+        account_a.balance += balance_a;
+        self.pending_changes.insert(channel.partner_a.clone(), account_a);
+
+        let mut account_b = self.get_account(&channel.partner_b).unwrap_or(Account::new(0));
+        account_b.balance += balance_b;
+        self.pending_changes.insert(channel.partner_b.clone(), account_b);
+
+        channel.is_closed = true;
+        channel.closing = None;
+        self.pending_channels.insert(channel.id.clone(), channel);
+    }
+
+    /// Cooperative close: both partners signed a final state, paid out immediately
+    fn close_channel(&mut self, sender: &[u8], channel_id: &[u8], proof: &BalanceProof) -> Result<(), String> {
+        let channel = self.open_channel_for(sender, channel_id)?;
+
+        if !proof.is_final {
+            return Err("Cooperative close requires a final balance proof".to_string());
+        }
+        Self::verify_balance_proof(&channel, proof)?;
+        self.check_channel_expiry(&channel, proof.balance_a)?;
+
+        self.payout_channel(channel, proof.balance_a, proof.balance_b);
+        Ok(())
+    }
+
+    /// Unilateral close: submit the latest co-signed state, or the opening state (all to A)
+    /// if none was ever signed, and open the challenge window
+    fn start_channel_close(&mut self, sender: &[u8], channel_id: &[u8], proof: Option<&BalanceProof>) -> Result<(), String> {
+        let mut channel = self.open_channel_for(sender, channel_id)?;
+
+        if channel.closing.is_some() {
+            return Err("Channel is already closing".to_string());
+        }
+
+        let (sequence, balance_a, balance_b) = match proof {
+            Some(proof) => {
+                Self::verify_balance_proof(&channel, proof)?;
+                (proof.sequence, proof.balance_a, proof.balance_b)
+            }
+            None => (0, channel.total_deposit, 0),
+        };
+        self.check_channel_expiry(&channel, balance_a)?;
+
+        channel.closing = Some(ChannelClosing {
+            sequence,
+            balance_a,
+            balance_b,
+            challenge_end_slot: self.block_slot.saturating_add(self.channel_challenge_slots),
+        });
+        self.pending_channels.insert(channel_id.to_vec(), channel);
+        Ok(())
+    }
+
+    /// Replace the state of a pending unilateral close with a newer co-signed one
+    fn challenge_channel_close(&mut self, sender: &[u8], channel_id: &[u8], proof: &BalanceProof) -> Result<(), String> {
+        let mut channel = self.open_channel_for(sender, channel_id)?;
+        let mut closing = channel.closing.clone().ok_or("Channel is not closing")?;
+
+        if self.block_slot > closing.challenge_end_slot {
+            return Err(format!("Challenge window ended at slot {}", closing.challenge_end_slot));
+        }
+        if proof.sequence <= closing.sequence {
+            return Err(format!("Balance proof sequence {} is not newer than {}", proof.sequence, closing.sequence));
+        }
+        Self::verify_balance_proof(&channel, proof)?;
+
+        closing.sequence = proof.sequence;
+        closing.balance_a = proof.balance_a;
+        closing.balance_b = proof.balance_b;
+        channel.closing = Some(closing);
+        self.pending_channels.insert(channel_id.to_vec(), channel);
+        Ok(())
+    }
+
+    /// Pay out a unilateral close once its challenge window is over
+    fn settle_channel(&mut self, sender: &[u8], channel_id: &[u8]) -> Result<(), String> {
+        let channel = self.open_channel_for(sender, channel_id)?;
+        let closing = channel.closing.clone().ok_or("Channel is not closing")?;
+
+        if self.block_slot <= closing.challenge_end_slot {
+            return Err(format!("Channel is in its challenge window until slot {}", closing.challenge_end_slot));
+        }
+
+        self.payout_channel(channel, closing.balance_a, closing.balance_b);
+        Ok(())
+    }
+
+    /// Bond `owner`'s stake to a schnorrkel validator key, paying its rewards to `owner`
+    fn register_validator(&mut self, owner: &[u8], pubkey: &[u8], stake: u64) -> Result<(), String> {
+        if stake < MIN_STAKE {
+            return Err(format!("Stake must be at least {}", MIN_STAKE));
+        }
+
+        // Withdrawn validators keep a zero-stake record and may register again
+        if let Some(existing) = self.get_validator(pubkey) {
+            if existing.slashed {
+                return Err("Validator has been slashed".to_string());
+            }
+            if existing.is_unbonding() {
+                return Err("Validator is unbonding".to_string());
+            }
+            if existing.stake > 0 {
+                return Err("Validator already registered".to_string());
+            }
+        }
+
+        // Deduct stake from balance
+        let mut account = self.get_account(owner).ok_or("Account not found")?;
+        if account.balance < stake {
+            return Err("Insufficient balance for stake".to_string());
+        }
+        account.balance -= stake;
+        self.pending_changes.insert(owner.to_vec(), account);
+
+        // Joins the active set at the next epoch boundary
+        let validator = ValidatorInfo::new(pubkey.to_vec(), stake, self.block_epoch + 1)
+            .with_reward_address(owner.to_vec());
+        self.pending_validators.insert(pubkey.to_vec(), validator);
+        Ok(())
+    }
+
+    fn unregister_validator(&mut self, owner: &[u8], pubkey: &[u8]) -> Result<(), String> {
+        let mut validator = self.get_validator(pubkey)
+            .filter(|v| v.stake > 0 && !v.slashed)
+            .ok_or("Validator not found")?;
+        if validator.reward_address != owner {
+            return Err("Only the registering account can unregister the validator".to_string());
+        }
+        if validator.exit_epoch.is_some() {
+            return Err("Validator is unbonding".to_string());
+        }
+
+        // Leaves the active set at the next epoch boundary; the stake stays
+        // bonded (and slashable) until the unbonding period is over
+        let exit_epoch = self.block_epoch + 1;
+        validator.exit_epoch = Some(exit_epoch);
+        validator.withdrawable_epoch = exit_epoch + self.unbonding_epochs;
+        self.pending_validators.insert(pubkey.to_vec(), validator);
+        Ok(())
+    }
+
+    fn punish_equivocation(&mut self, reporter: &[u8], offender: &[u8]) -> Result<(), String> {
+        let validator = self.get_validator(offender).ok_or("Offender is not a validator")?;
+
+        // Evidence for an offender that is already slashed is accepted but has no effect,
+        // so several nodes reporting the same equivocation don't strand each other's nonces
+        if validator.slashed {
+            return Ok(());
+        }
+
+        let slashed = self.slash_validator(offender)?;
+        let reward = (slashed as u128 * EQUIVOCATION_REPORTER_REWARD_PERCENT as u128 / 100) as u64;
+        if reward > 0 {
+            self.credit_balance(reporter, reward);
+        }
+        // The remainder is burned
+        Ok(())
+    }
+
+    fn delegate_spend(&mut self, owner: &[u8], delegate: &[u8], allowance: u64, expiry: u64) -> Result<(), String> {
+        if expiry < self.block_slot {
+            return Err(format!("Delegation expiry {} is in the past (slot {})", expiry, self.block_slot));
+        }
+
+        let mut owner_account = self.get_account(owner).unwrap_or(Account::new(0)); 
+        
+        // Set allowance
+        owner_account.delegated_allowance.insert(delegate.to_vec(), Delegation { allowance, expiry });
+        
+        self.pending_changes.insert(owner.to_vec(), owner_account);
+        Ok(())
+    }
+
+    /// Pay `amount` from `owner` to `to` on the authority of `delegate`, using up its allowance
+    fn delegated_transfer(&mut self, owner: &[u8], delegate: &[u8], to: &[u8], amount: u64) -> Result<(), String> {
+        let delegation = self.get_delegation(owner, delegate).ok_or("No active delegation from owner")?;
+        if delegation.allowance < amount {
+            return Err(format!("Amount exceeds delegated allowance. Remaining: {}, Requested: {}", delegation.allowance, amount));
+        }
+
+        self.transfer_native(owner, to, amount)?;
+
+        let mut owner_account = self.get_account(owner).ok_or("Owner account not found")?;
+        Self::decrement_allowance(&mut owner_account.delegated_allowance, delegate, delegation.allowance - amount, |allowance| Delegation { allowance, ..delegation });
+        self.pending_changes.insert(owner.to_vec(), owner_account);
+        Ok(())
+    }
+
+    fn revoke_delegation(&mut self, owner: &[u8], delegate: &[u8]) -> Result<(), String> {
+        let mut owner_account = self.get_account(owner).ok_or("Owner account not found")?;
+        if owner_account.delegated_allowance.remove(delegate).is_none() {
+            return Err("No delegation to revoke".to_string());
+        }
+        self.pending_changes.insert(owner.to_vec(), owner_account);
+        Ok(())
+    }
+
+    /// Allowance `owner` granted to `delegate`, if it has not expired at the current block
+    pub fn get_delegation(&self, owner: &[u8], delegate: &[u8]) -> Option<Delegation> {
+        self.get_account(owner)?
+            .delegated_allowance
+            .get(delegate)
+            .copied()
+            .filter(|delegation| delegation.expiry >= self.block_slot)
+    }
+
+
+
+    pub fn increment_nonce(&mut self, address: &[u8]) {
+        let mut account = self.get_account(address).unwrap_or(Account::new(0));
+        account.nonce += 1;
+        self.pending_changes.insert(address.to_vec(), account);
+    }
+
+    pub fn apply_changes(&mut self) -> Result<(), String> {
+        for (address, account) in &self.pending_changes {
+            self.storage.store_account(address, account)
+                .map_err(|e| format!("Failed to store account: {}", e))?;
+        }
+        for asset in self.pending_assets.values() {
+            self.storage.store_asset(asset)?;
+        }
+        for collection in self.pending_collections.values() {
+            self.storage.store_collection(collection)?;
+        }
+        for item in self.pending_nfts.values() {
+            self.storage.store_nft(item)?;
+        }
+        for channel in self.pending_channels.values() {
+            self.storage.store_channel(channel)?;
+        }
+        for order in self.pending_orders.values() {
+            self.storage.store_order(order)?;
+        }
+        for multisig in self.pending_multisigs.values() {
+            self.storage.store_multisig(multisig)?;
+        }
+        for validator in self.pending_validators.values() {
+            self.storage.store_validator(validator)?;
+        }
+
+        // Update trie with the same data
+        for (key, value) in self.pending_trie_entries()? {
+            self.trie.insert(key, value);
+        }
+
+        self.discard_changes();
+        Ok(())
+    }
+
+    /// Trie key/value pairs for every uncommitted change
+    fn pending_trie_entries(&self) -> Result<Vec<TrieEntry>, String> {
+        let mut entries = Vec::new();
+
+        for (address, account) in &self.pending_changes {
+            let data = bincode::serialize(account)
+                .map_err(|e| format!("Failed to serialize account: {}", e))?;
+            entries.push((address.clone(), data));
+        }
+        for (asset_id, asset) in &self.pending_assets {
+            let data = bincode::serialize(asset)
+                .map_err(|e| format!("Failed to serialize asset: {}", e))?;
+            entries.push(([ASSET_TRIE_PREFIX, asset_id.as_slice()].concat(), data));
+        }
+        for (collection_id, collection) in &self.pending_collections {
+            let data = bincode::serialize(collection)
+                .map_err(|e| format!("Failed to serialize collection: {}", e))?;
+            entries.push(([COLLECTION_TRIE_PREFIX, collection_id.as_slice()].concat(), data));
+        }
+        for ((collection_id, item_id), item) in &self.pending_nfts {
+            let data = bincode::serialize(item)
+                .map_err(|e| format!("Failed to serialize NFT: {}", e))?;
+            entries.push((nft_trie_key(collection_id, *item_id), data));
+        }
+        for (channel_id, channel) in &self.pending_channels {
+            let data = bincode::serialize(channel)
+                .map_err(|e| format!("Failed to serialize channel: {}", e))?;
+            entries.push(([CHANNEL_TRIE_PREFIX, channel_id.as_slice()].concat(), data));
+        }
+        for (order_id, order) in &self.pending_orders {
+            let data = bincode::serialize(order)
+                .map_err(|e| format!("Failed to serialize order: {}", e))?;
+            entries.push(([ORDER_TRIE_PREFIX, order_id.as_slice()].concat(), data));
+        }
+        for (address, multisig) in &self.pending_multisigs {
+            let data = bincode::serialize(multisig)
+                .map_err(|e| format!("Failed to serialize multisig: {}", e))?;
+            entries.push(([MULTISIG_TRIE_PREFIX, address.as_slice()].concat(), data));
+        }
+        for (pubkey, validator) in &self.pending_validators {
+            let data = bincode::serialize(validator)
+                .map_err(|e| format!("Failed to serialize validator: {}", e))?;
+            entries.push(([VALIDATOR_TRIE_PREFIX, pubkey.as_slice()].concat(), data));
+        }
+
+        Ok(entries)
+    }
+
+    /// Compute the state root as it would be after committing the pending changes
+    /// The committed root is left untouched.
+    pub fn compute_root_hash(&mut self) -> Result<String, String> {
+        let committed_root = self.trie.root_ref();
+        for (key, value) in self.pending_trie_entries()? {
+            self.trie.insert(key, value);
+        }
+        let root = self.trie.root();
+        self.trie.reset_root(committed_root);
+        Ok(root)
+    }
+
+    /// Record the committed values that apply_changes is about to overwrite
+    pub fn undo_journal(&self) -> Result<UndoJournal, String> {
+        let mut journal = UndoJournal::default();
+        for address in self.pending_changes.keys() {
+            journal.accounts.push((address.clone(), self.storage.get_account(address)?));
+        }
+        for asset_id in self.pending_assets.keys() {
+            journal.assets.push((asset_id.clone(), self.storage.get_asset(asset_id)?));
+        }
+        for collection_id in self.pending_collections.keys() {
+            journal.collections.push((collection_id.clone(), self.storage.get_collection(collection_id)?));
+        }
+        for (collection_id, item_id) in self.pending_nfts.keys() {
+            journal.nfts.push(((collection_id.clone(), *item_id), self.storage.get_nft(collection_id, *item_id)?));
+        }
+        for channel_id in self.pending_channels.keys() {
+            journal.channels.push((channel_id.clone(), self.storage.get_channel(channel_id)?));
+        }
+        for order_id in self.pending_orders.keys() {
+            journal.orders.push((order_id.clone(), self.storage.get_order(order_id)?));
+        }
+        for address in self.pending_multisigs.keys() {
+            journal.multisigs.push((address.clone(), self.storage.get_multisig(address)?));
+        }
+        for pubkey in self.pending_validators.keys() {
+            journal.validators.push((pubkey.clone(), self.storage.get_validator(pubkey)?));
+        }
+        Ok(journal)
+    }
+
+    /// Write an undo journal back to storage, restoring the state before its block
+    /// The trie is not touched; call rebuild_trie once all journals are reverted.
+    pub fn revert(&mut self, journal: &UndoJournal) -> Result<(), String> {
+        self.discard_changes();
+        for (address, previous) in &journal.accounts {
+            match previous {
+                Some(account) => self.storage.store_account(address, account)?,
+                None => self.storage.delete_account(address)?,
+            }
+        }
+        for (asset_id, previous) in &journal.assets {
+            match previous {
+                Some(asset) => self.storage.store_asset(asset)?,
+                None => self.storage.delete_asset(asset_id)?,
+            }
+        }
+        for (collection_id, previous) in &journal.collections {
+            match previous {
+                Some(collection) => self.storage.store_collection(collection)?,
+                None => self.storage.delete_collection(collection_id)?,
+            }
+        }
+        for ((collection_id, item_id), previous) in &journal.nfts {
+            match previous {
+                Some(item) => self.storage.store_nft(item)?,
+                None => self.storage.delete_nft(collection_id, *item_id)?,
+            }
+        }
+        for (channel_id, previous) in &journal.channels {
+            match previous {
+                Some(channel) => self.storage.store_channel(channel)?,
+                None => self.storage.delete_channel(channel_id)?,
+            }
+        }
+        for (order_id, previous) in &journal.orders {
+            match previous {
+                Some(order) => self.storage.store_order(order)?,
+                None => self.storage.delete_order(order_id)?,
+            }
+        }
+        for (address, previous) in &journal.multisigs {
+            match previous {
+                Some(multisig) => self.storage.store_multisig(multisig)?,
+                None => self.storage.delete_multisig(address)?,
+            }
+        }
+        for (pubkey, previous) in &journal.validators {
+            match previous {
+                Some(validator) => self.storage.store_validator(validator)?,
+                None => self.storage.delete_validator(pubkey)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Capture the uncommitted changes so a failing transaction can be undone
+    pub fn checkpoint(&self) -> StateCheckpoint {
+        StateCheckpoint {
+            accounts: self.pending_changes.clone(),
+            assets: self.pending_assets.clone(),
+            collections: self.pending_collections.clone(),
+            nfts: self.pending_nfts.clone(),
+            channels: self.pending_channels.clone(),
+            orders: self.pending_orders.clone(),
+            multisigs: self.pending_multisigs.clone(),
+            validators: self.pending_validators.clone(),
+        }
+    }
+
+    /// Roll the uncommitted changes back to a checkpoint
+    pub fn restore(&mut self, checkpoint: StateCheckpoint) {
+        self.pending_changes = checkpoint.accounts;
+        self.pending_assets = checkpoint.assets;
+        self.pending_collections = checkpoint.collections;
+        self.pending_nfts = checkpoint.nfts;
+        self.pending_channels = checkpoint.channels;
+        self.pending_orders = checkpoint.orders;
+        self.pending_multisigs = checkpoint.multisigs;
+        self.pending_validators = checkpoint.validators;
+    }
+
+    pub fn discard_changes(&mut self) {
         self.pending_changes.clear();
+        self.pending_assets.clear();
+        self.pending_collections.clear();
+        self.pending_nfts.clear();
+        self.pending_channels.clear();
+        self.pending_orders.clear();
+        self.pending_multisigs.clear();
+        self.pending_validators.clear();
     }
 
     /// Get the current state root hash
@@ -452,7 +1614,12 @@ impl State {
 mod tests {
     use super::*;
     use tempfile::TempDir;
-    use crate::crypto::Crypto;
+    use crate::transaction::MIN_TRANSACTION_FEE;
+    use crate::consensus::BlockLimits;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    const CHAIN_ID: u32 = 1;
 
     fn create_test_state() -> (State, TempDir) {
         let temp_dir = TempDir::new().unwrap();
@@ -463,9 +1630,10 @@ mod tests {
     #[test]
     fn test_asset_creation_and_transfer() {
         let (mut state, _temp) = create_test_state();
-        let keypair = Crypto::generate_keypair();
-        let sender = keypair.public.to_bytes().to_vec();
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
         let receiver = vec![4, 5, 6];
+        state.set_balance(sender.clone(), 10); // Covers fees
 
         // 1. Create Asset
         let create_tx = Transaction::new(
@@ -477,8 +1645,11 @@ mod tests {
                 supply: 1000,
                 decimals: 8,
                 metadata: vec![],
+                max_supply: None,
             },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &keypair,
         );
 
@@ -497,6 +1668,8 @@ mod tests {
                 amount: 100,
             },
             1, // Nonce incremented
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &keypair,
         );
 
@@ -512,11 +1685,11 @@ mod tests {
     fn test_channel_lifecycle() {
         let (mut state, _temp) = create_test_state();
 
-        let sender_pair = Crypto::generate_keypair();
-        let sender = sender_pair.public.to_bytes().to_vec();
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
         
-        let partner_pair = Crypto::generate_keypair();
-        let partner = partner_pair.public.to_bytes().to_vec();
+        let partner_pair = SigningKey::generate(&mut OsRng);
+        let partner = partner_pair.verifying_key().to_bytes().to_vec();
 
         state.set_balance(sender.clone(), 1000);
 
@@ -526,47 +1699,149 @@ mod tests {
             amount: 500,
             duration: 100,
         };
-        let open_tx = Transaction::new(sender.clone(), vec![], open_data.clone(), 0, &sender_pair);
+        let open_tx = Transaction::new(sender.clone(), vec![], open_data.clone(), 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
         
         state.apply_transaction(&open_tx).expect("Failed to open channel");
 
-        // Verify balance deducted
-        assert_eq!(state.get_balance(&sender), 500);
+        // Verify deposit and fee deducted
+        assert_eq!(state.get_balance(&sender), 499);
         
         // Verify channel exists
         let channel_id = open_tx.calculate_asset_id().unwrap();
-        assert!(state.channels.contains_key(&channel_id));
-
-        // 2. Close Channel
-        let close_data = TransactionData::CloseChannel {
-            channel_id: channel_id.clone(),
-            balance_proof: vec![],
-            final_balance_a: 300, // Sender gets 300 back
-            final_balance_b: 200, // Partner gets 200
-        };
-        // Partner signs the close (or sender can if authorized, for now simplistic)
-        // Let's have sender close it due to simplified check
-        let close_tx = Transaction::new(sender.clone(), vec![], close_data, 1, &sender_pair);
+        assert!(state.get_channel(&channel_id).is_some());
 
-        state.apply_transaction(&close_tx).expect("Failed to close channel");
+        // 2. Close Channel with a final state signed by both partners
+        let mut proof = BalanceProof::new(channel_id.clone(), 7, 300, 200, true);
+        proof.sign_a(&sender_pair);
+
+        // A cannot close on its own signature alone
+        state.apply_changes().unwrap();
+        let close = |proof: &BalanceProof| Transaction::new(sender.clone(), vec![], TransactionData::CloseChannel { channel_id: channel_id.clone(), balance_proof: proof.clone() }, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        assert_eq!(state.apply_transaction(&close(&proof)).err().unwrap(), "Invalid partner B signature length");
+        state.discard_changes();
+
+        proof.sign_b(&partner_pair);
+        state.apply_transaction(&close(&proof)).expect("Failed to close channel");
 
         // Verify balances
-        assert_eq!(state.get_balance(&sender), 800); // 500 (remaining) + 300 (channel)
+        assert_eq!(state.get_balance(&sender), 798); // 499 (remaining) - 1 (fee) + 300 (channel)
         assert_eq!(state.get_balance(&partner), 200); // 0 + 200 (channel)
         
         // Verify closed
-        assert!(state.channels.get(&channel_id).unwrap().is_closed);
+        assert!(state.get_channel(&channel_id).unwrap().is_closed);
+    }
+
+    #[test]
+    fn test_channel_expiry() {
+        let (mut state, _temp) = create_test_state();
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let partner_pair = SigningKey::generate(&mut OsRng);
+        let partner = partner_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+
+        // Opened at slot 10 for 100 slots
+        state.begin_block(10);
+        let open_tx = Transaction::new(sender.clone(), vec![], TransactionData::OpenChannel { partner: partner.clone(), amount: 500, duration: 100 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        state.apply_transaction(&open_tx).unwrap();
+        let channel_id = open_tx.calculate_asset_id().unwrap();
+        assert_eq!(state.get_channel(&channel_id).unwrap().expiry, 110);
+        state.apply_changes().unwrap();
+
+        // After expiry the partner can no longer be paid out, only the opener refunded
+        state.begin_block(111);
+        let close = |nonce, balance_a, balance_b| {
+            let mut proof = BalanceProof::new(channel_id.clone(), 1, balance_a, balance_b, true);
+            proof.sign_a(&sender_pair);
+            proof.sign_b(&partner_pair);
+            Transaction::new(sender.clone(), vec![], TransactionData::CloseChannel { channel_id: channel_id.clone(), balance_proof: proof }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair)
+        };
+        assert_eq!(state.apply_transaction(&close(1, 300, 200)).err().unwrap(), "Channel expired at slot 110: the deposit can only be refunded to the opener");
+        state.discard_changes();
+        state.apply_transaction(&close(1, 500, 0)).unwrap();
+        assert_eq!(state.get_balance(&partner), 0);
+        assert!(state.get_channel(&channel_id).unwrap().is_closed);
+    }
+
+    #[test]
+    fn test_channel_unilateral_close() {
+        let (mut state, _temp) = create_test_state();
+        let sender_pair = SigningKey::generate(&mut OsRng);
+        let sender = sender_pair.verifying_key().to_bytes().to_vec();
+        let partner_pair = SigningKey::generate(&mut OsRng);
+        let partner = partner_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 1000);
+        state.set_balance(partner.clone(), 10);
+        state.set_channel_challenge_slots(50);
+
+        state.begin_block(10);
+        let open_tx = Transaction::new(sender.clone(), vec![], TransactionData::OpenChannel { partner: partner.clone(), amount: 500, duration: 1000 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        state.apply_transaction(&open_tx).unwrap();
+        let channel_id = open_tx.calculate_asset_id().unwrap();
+
+        let signed = |sequence, balance_a, balance_b| {
+            let mut proof = BalanceProof::new(channel_id.clone(), sequence, balance_a, balance_b, false);
+            proof.sign_a(&sender_pair);
+            proof.sign_b(&partner_pair);
+            proof
+        };
+
+        // A tries to close on a stale state that paid B less
+        let start_tx = Transaction::new(sender.clone(), vec![], TransactionData::StartChannelClose { channel_id: channel_id.clone(), balance_proof: Some(signed(1, 400, 100)) }, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        state.apply_transaction(&start_tx).unwrap();
+        assert_eq!(state.get_channel(&channel_id).unwrap().closing.unwrap().challenge_end_slot, 60);
+        state.apply_changes().unwrap();
+
+        // Funds stay locked during the challenge window
+        let settle = |nonce| Transaction::new(sender.clone(), vec![], TransactionData::SettleChannel { channel_id: channel_id.clone() }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &sender_pair);
+        state.begin_block(60);
+        assert_eq!(state.apply_transaction(&settle(2)).err().unwrap(), "Channel is in its challenge window until slot 60");
+        state.discard_changes();
+
+        // B answers with the newer state; an older one is rejected
+        let challenge = |nonce, proof| Transaction::new(partner.clone(), vec![], TransactionData::ChallengeChannelClose { channel_id: channel_id.clone(), balance_proof: proof }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &partner_pair);
+        assert_eq!(state.apply_transaction(&challenge(0, signed(1, 300, 200))).err().unwrap(), "Balance proof sequence 1 is not newer than 1");
+        state.discard_changes();
+        state.apply_transaction(&challenge(0, signed(5, 250, 250))).unwrap();
+        state.apply_changes().unwrap();
+
+        // Too late to challenge, and the newest state is paid out
+        state.begin_block(61);
+        assert_eq!(state.apply_transaction(&challenge(1, signed(6, 0, 500))).err().unwrap(), "Challenge window ended at slot 60");
+        state.discard_changes();
+        state.apply_transaction(&settle(2)).unwrap();
+        assert_eq!(state.get_balance(&sender), 1000 - 500 - 3 * MIN_TRANSACTION_FEE + 250);
+        assert_eq!(state.get_balance(&partner), 10 - MIN_TRANSACTION_FEE + 250);
+        assert!(state.get_channel(&channel_id).unwrap().is_closed);
+    }
+
+    #[test]
+    fn test_transaction_expiry() {
+        let (mut state, _temp) = create_test_state();
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 100);
+
+        let mut tx = Transaction::new(sender.clone(), vec![1; 32], TransactionData::NativeTransfer { amount: 10 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &keypair);
+        tx.valid_until_slot = Some(20);
+        tx.sign(&keypair);
+
+        state.begin_block(21);
+        assert_eq!(state.apply_transaction(&tx).err().unwrap(), "Transaction expired after slot 20");
+        state.begin_block(20);
+        state.apply_transaction(&tx).unwrap();
+        assert_eq!(state.get_balance(&[1; 32]), 10);
     }
 
     #[test]
     fn test_delegation() {
         let (mut state, _temp) = create_test_state();
 
-        let owner_pair = Crypto::generate_keypair();
-        let owner = owner_pair.public.to_bytes().to_vec();
+        let owner_pair = SigningKey::generate(&mut OsRng);
+        let owner = owner_pair.verifying_key().to_bytes().to_vec();
         
-        let delegate_pair = Crypto::generate_keypair();
-        let delegate = delegate_pair.public.to_bytes().to_vec();
+        let delegate_pair = SigningKey::generate(&mut OsRng);
+        let delegate = delegate_pair.verifying_key().to_bytes().to_vec();
 
         state.set_balance(owner.clone(), 100);
 
@@ -576,20 +1851,115 @@ mod tests {
             allowance: 50,
             expiry: 1000,
         };
-        let tx = Transaction::new(owner.clone(), vec![], delegate_data, 0, &owner_pair);
+        let tx = Transaction::new(owner.clone(), vec![], delegate_data, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair);
         
         state.apply_transaction(&tx).expect("Failed to set delegate");
 
-        let account = state.get_account(&owner).unwrap();
-        assert_eq!(*account.delegated_allowance.get(&delegate).unwrap(), 50);
+        assert_eq!(state.get_delegation(&owner, &delegate), Some(Delegation { allowance: 50, expiry: 1000 }));
+
+        // The allowance lapses after its expiry slot
+        state.begin_block(1001);
+        assert_eq!(state.get_delegation(&owner, &delegate), None);
+
+        // ...and can't be granted already expired
+        let late = TransactionData::DelegateSpend { delegate: delegate.clone(), allowance: 50, expiry: 1000 };
+        let tx = Transaction::new(owner.clone(), vec![], late, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair);
+        assert_eq!(state.apply_transaction(&tx).err().unwrap(), "Delegation expiry 1000 is in the past (slot 1001)");
+    }
+
+    #[test]
+    fn test_delegated_transfer() {
+        let (mut state, _temp) = create_test_state();
+        let owner_pair = SigningKey::generate(&mut OsRng);
+        let owner = owner_pair.verifying_key().to_bytes().to_vec();
+        let agent_pair = SigningKey::generate(&mut OsRng);
+        let agent = agent_pair.verifying_key().to_bytes().to_vec();
+        let merchant = vec![5; 32];
+        state.set_balance(owner.clone(), 100);
+        state.set_balance(agent.clone(), 10);
+
+        let grant = TransactionData::DelegateSpend { delegate: agent.clone(), allowance: 50, expiry: 1000 };
+        state.apply_transaction(&Transaction::new(owner.clone(), vec![], grant, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair)).unwrap();
+        state.apply_changes().unwrap();
+
+        let spend = |nonce, amount| Transaction::new(agent.clone(), merchant.clone(), TransactionData::DelegatedTransfer { owner: owner.clone(), amount }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &agent_pair);
+
+        // The agent pays the fee, the owner pays the amount
+        state.apply_transaction(&spend(0, 30)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_balance(&owner), 69);
+        assert_eq!(state.get_balance(&agent), 9);
+        assert_eq!(state.get_balance(&merchant), 30);
+        assert_eq!(state.get_delegation(&owner, &agent).unwrap().allowance, 20);
+
+        assert_eq!(state.apply_transaction(&spend(1, 21)).err().unwrap(), "Amount exceeds delegated allowance. Remaining: 20, Requested: 21");
+        state.discard_changes();
+
+        // Spending the rest removes the allowance
+        state.apply_transaction(&spend(1, 20)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_delegation(&owner, &agent), None);
+        assert!(state.get_account(&owner).unwrap().delegated_allowance.is_empty());
+
+        // A revoked delegation can't be spent
+        let grant = TransactionData::DelegateSpend { delegate: agent.clone(), allowance: 50, expiry: 1000 };
+        state.apply_transaction(&Transaction::new(owner.clone(), vec![], grant, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair)).unwrap();
+        let revoke = TransactionData::RevokeDelegation { delegate: agent.clone() };
+        state.apply_transaction(&Transaction::new(owner.clone(), vec![], revoke, 2, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&spend(2, 10)).err().unwrap(), "No active delegation from owner");
+    }
+
+    #[test]
+    fn test_asset_allowance() {
+        let (mut state, _temp) = create_test_state();
+        let owner_pair = SigningKey::generate(&mut OsRng);
+        let owner = owner_pair.verifying_key().to_bytes().to_vec();
+        let exchange_pair = SigningKey::generate(&mut OsRng);
+        let exchange = exchange_pair.verifying_key().to_bytes().to_vec();
+        let buyer = vec![5; 32];
+        state.set_balance(owner.clone(), 100);
+        state.set_balance(exchange.clone(), 10);
+
+        let create_tx = Transaction::new(owner.clone(), vec![], TransactionData::CreateAsset { name: "Gold".into(), symbol: "GLD".into(), supply: 100, decimals: 0, metadata: vec![], max_supply: None }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair);
+        state.apply_transaction(&create_tx).unwrap();
+        let asset_id = create_tx.calculate_asset_id().unwrap();
+        state.apply_changes().unwrap();
+
+        // Approvals are part of the account and so of the state root
+        let root_before = state.get_root_hash();
+        let approve = |nonce, amount| Transaction::new(owner.clone(), vec![], TransactionData::ApproveAsset { asset_id: asset_id.clone(), spender: exchange.clone(), amount }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &owner_pair);
+        state.apply_transaction(&approve(1, 40)).unwrap();
+        state.apply_changes().unwrap();
+        assert_ne!(state.get_root_hash(), root_before);
+        assert_eq!(state.get_asset_allowance(&owner, &asset_id, &exchange), 40);
+
+        // The exchange pulls the owner's tokens to the buyer and pays the fee itself
+        let pull = |nonce, amount| Transaction::new(exchange.clone(), buyer.clone(), TransactionData::TransferAssetFrom { asset_id: asset_id.clone(), owner: owner.clone(), amount }, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &exchange_pair);
+        state.apply_transaction(&pull(0, 25)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(*state.get_account(&owner).unwrap().assets.get(&asset_id).unwrap(), 75);
+        assert_eq!(*state.get_account(&buyer).unwrap().assets.get(&asset_id).unwrap(), 25);
+        assert_eq!(state.get_balance(&exchange), 9);
+        assert_eq!(state.get_asset_allowance(&owner, &asset_id, &exchange), 15);
+
+        assert_eq!(state.apply_transaction(&pull(1, 16)).err().unwrap(), "Amount exceeds asset allowance. Remaining: 15, Requested: 16");
+        state.discard_changes();
+
+        // Approving zero revokes
+        state.apply_transaction(&approve(2, 0)).unwrap();
+        state.apply_changes().unwrap();
+        assert!(state.get_account(&owner).unwrap().asset_allowances.is_empty());
+        assert_eq!(state.apply_transaction(&pull(1, 1)).err().unwrap(), "Amount exceeds asset allowance. Remaining: 0, Requested: 1");
     }
 
     #[test]
     fn test_insufficient_asset_balance() {
         let (mut state, _temp) = create_test_state();
-        let keypair = Crypto::generate_keypair();
-        let sender = keypair.public.to_bytes().to_vec();
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
         let receiver = vec![1, 2, 3];
+        state.set_balance(sender.clone(), 10); // Covers fees
 
         // Create asset
         let create_tx = Transaction::new(
@@ -601,8 +1971,11 @@ mod tests {
                 supply: 100,
                 decimals: 0,
                 metadata: vec![],
+                max_supply: None,
             },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &keypair,
         );
         state.apply_transaction(&create_tx).unwrap();
@@ -617,6 +1990,8 @@ mod tests {
                 amount: 101, // > 100
             },
             1,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &keypair,
         );
 
@@ -625,14 +2000,62 @@ mod tests {
         assert_eq!(result.err().unwrap(), "Insufficient asset balance");
     }
 
+    #[test]
+    fn test_asset_admin_controls() {
+        let (mut state, _temp) = create_test_state();
+        let issuer_pair = SigningKey::generate(&mut OsRng);
+        let issuer = issuer_pair.verifying_key().to_bytes().to_vec();
+        let admin_pair = SigningKey::generate(&mut OsRng);
+        let admin = admin_pair.verifying_key().to_bytes().to_vec();
+        let holder = vec![5; 32];
+        state.set_balance(issuer.clone(), 100);
+        state.set_balance(admin.clone(), 100);
+
+        let create_tx = Transaction::new(issuer.clone(), vec![], TransactionData::CreateAsset { name: "Dollar".into(), symbol: "USDX".into(), supply: 100, decimals: 2, metadata: vec![], max_supply: Some(1000) }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &issuer_pair);
+        state.apply_transaction(&create_tx).unwrap();
+        let asset_id = create_tx.calculate_asset_id().unwrap();
+
+        let tx = |keypair: &SigningKey, receiver: Vec<u8>, data, nonce| Transaction::new(keypair.verifying_key().to_bytes().to_vec(), receiver, data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair);
+        let mint = |amount| TransactionData::MintAsset { asset_id: asset_id.clone(), amount };
+
+        // Minting is capped by the max supply
+        state.apply_transaction(&tx(&issuer_pair, holder.clone(), mint(400), 1)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&tx(&issuer_pair, holder.clone(), mint(501), 2)).err().unwrap(), "Mint would exceed max supply of 1000");
+        state.discard_changes();
+
+        // Control moves to the new admin
+        state.apply_transaction(&tx(&issuer_pair, vec![], TransactionData::SetAssetAdmin { asset_id: asset_id.clone(), new_admin: admin.clone() }, 2)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&tx(&issuer_pair, holder.clone(), mint(1), 3)).err().unwrap(), "Only the asset admin can do this");
+        state.discard_changes();
+        state.apply_transaction(&tx(&admin_pair, holder.clone(), mint(500), 0)).unwrap();
+
+        // Burning from the issuer's balance frees room under the cap
+        state.apply_transaction(&tx(&issuer_pair, vec![], TransactionData::BurnAsset { asset_id: asset_id.clone(), amount: 30 }, 3)).unwrap();
+        assert_eq!(state.get_asset(&asset_id).unwrap().total_supply, 970);
+        assert_eq!(*state.get_account(&holder).unwrap().assets.get(&asset_id).unwrap(), 900);
+
+        // Frozen assets cannot move
+        state.apply_transaction(&tx(&admin_pair, vec![], TransactionData::FreezeAsset { asset_id: asset_id.clone() }, 1)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&tx(&issuer_pair, holder.clone(), TransactionData::TransferAsset { asset_id: asset_id.clone(), amount: 10 }, 4)).err().unwrap(), "Asset is frozen");
+        state.discard_changes();
+        state.apply_transaction(&tx(&admin_pair, vec![], TransactionData::UnfreezeAsset { asset_id: asset_id.clone() }, 2)).unwrap();
+        state.apply_transaction(&tx(&issuer_pair, holder.clone(), TransactionData::TransferAsset { asset_id: asset_id.clone(), amount: 10 }, 4)).unwrap();
+        assert_eq!(*state.get_account(&issuer).unwrap().assets.get(&asset_id).unwrap(), 60);
+    }
+
     #[test]
     fn test_mint_nft_not_owner() {
         let (mut state, _temp) = create_test_state();
-        let owner_pair = Crypto::generate_keypair();
-        let owner = owner_pair.public.to_bytes().to_vec();
+        let owner_pair = SigningKey::generate(&mut OsRng);
+        let owner = owner_pair.verifying_key().to_bytes().to_vec();
         
-        let attacker_pair = Crypto::generate_keypair();
-        let attacker = attacker_pair.public.to_bytes().to_vec();
+        let attacker_pair = SigningKey::generate(&mut OsRng);
+        let attacker = attacker_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(owner.clone(), 10); // Covers fees
+        state.set_balance(attacker.clone(), 10);
 
         // Owner creates collection
         let create_tx = Transaction::new(
@@ -642,8 +2065,11 @@ mod tests {
                 name: "Art".into(),
                 symbol: "ART".into(),
                 metadata: vec![],
+                max_items: None,
             },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &owner_pair,
         );
         state.apply_transaction(&create_tx).unwrap();
@@ -660,6 +2086,8 @@ mod tests {
                 recipient: attacker.clone(),
             },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &attacker_pair,
         );
 
@@ -667,4 +2095,353 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.err().unwrap(), "Only issuer can mint");
     }
+
+    #[test]
+    fn test_nft_approvals_burn_and_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = State::new(Storage::new(temp_dir.path()).unwrap());
+        let issuer_pair = SigningKey::generate(&mut OsRng);
+        let issuer = issuer_pair.verifying_key().to_bytes().to_vec();
+        let owner_pair = SigningKey::generate(&mut OsRng);
+        let owner = owner_pair.verifying_key().to_bytes().to_vec();
+        let market_pair = SigningKey::generate(&mut OsRng);
+        let market = market_pair.verifying_key().to_bytes().to_vec();
+        let buyer = vec![5; 32];
+        for address in [&issuer, &owner, &market] {
+            state.set_balance(address.clone(), 100);
+        }
+
+        let tx = |keypair: &SigningKey, receiver: Vec<u8>, data, nonce| Transaction::new(keypair.verifying_key().to_bytes().to_vec(), receiver, data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair);
+        let create_tx = tx(&issuer_pair, vec![], TransactionData::CreateCollection { name: "Art".into(), symbol: "ART".into(), metadata: vec![], max_items: Some(2) }, 0);
+        state.apply_transaction(&create_tx).unwrap();
+        let collection_id = create_tx.calculate_asset_id().unwrap();
+        let mint = |item_id| TransactionData::MintNFT { collection_id: collection_id.clone(), item_id, item_metadata: vec![item_id as u8], recipient: owner.clone() };
+        state.apply_transaction(&tx(&issuer_pair, vec![], mint(1), 1)).unwrap();
+        state.apply_transaction(&tx(&issuer_pair, vec![], mint(2), 2)).unwrap();
+        state.apply_changes().unwrap();
+
+        // The cap counts every item ever minted
+        assert_eq!(state.apply_transaction(&tx(&issuer_pair, vec![], mint(3), 3)).err().unwrap(), "Collection is capped at 2 items");
+        state.discard_changes();
+
+        // An address approved for one item can move that item only, and loses the approval with it
+        let transfer = |item_id| TransactionData::TransferNFT { collection_id: collection_id.clone(), item_id };
+        state.apply_transaction(&tx(&owner_pair, vec![], TransactionData::ApproveNFT { collection_id: collection_id.clone(), item_id: Some(1), operator: market.clone(), approved: true }, 0)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&tx(&market_pair, buyer.clone(), transfer(2), 0)).err().unwrap(), "Sender does not own this NFT");
+        state.discard_changes();
+        state.apply_transaction(&tx(&market_pair, buyer.clone(), transfer(1), 0)).unwrap();
+        let item = state.get_nft(&collection_id, 1).unwrap();
+        assert_eq!((item.owner, item.approved), (buyer.clone(), None));
+
+        // An operator for all items can move and burn the rest
+        state.apply_transaction(&tx(&owner_pair, vec![], TransactionData::ApproveNFT { collection_id: collection_id.clone(), item_id: None, operator: market.clone(), approved: true }, 1)).unwrap();
+        assert!(state.is_nft_operator(&owner, &collection_id, &market));
+        state.apply_transaction(&tx(&market_pair, vec![], TransactionData::BurnNFT { collection_id: collection_id.clone(), item_id: 2 }, 1)).unwrap();
+        assert!(state.get_nft(&collection_id, 2).is_none());
+        assert!(state.get_account(&owner).unwrap().nfts.get(&collection_id).unwrap().is_empty());
+        state.apply_changes().unwrap();
+
+        // A burned ID cannot be minted again
+        assert_eq!(state.apply_transaction(&tx(&issuer_pair, vec![], mint(2), 3)).err().unwrap(), "Item ID already exists");
+        state.discard_changes();
+
+        // Only the issuer updates metadata, until it is frozen
+        let update = |freeze| TransactionData::UpdateNFTMetadata { collection_id: collection_id.clone(), item_id: 1, metadata: b"v2".to_vec(), freeze };
+        assert_eq!(state.apply_transaction(&tx(&owner_pair, vec![], update(false), 2)).err().unwrap(), "Only issuer can update metadata");
+        state.discard_changes();
+        state.apply_transaction(&tx(&issuer_pair, vec![], update(true), 3)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&tx(&issuer_pair, vec![], update(false), 4)).err().unwrap(), "Item metadata is frozen");
+        state.discard_changes();
+
+        // Items are looked up on their own after a restart
+        let restarted = State::new(Storage::new(temp_dir.path()).unwrap());
+        assert_eq!(restarted.get_nft(&collection_id, 1).unwrap().metadata, b"v2".to_vec());
+        assert_eq!(restarted.get_root_hash(), state.get_root_hash());
+    }
+
+    #[test]
+    fn test_swap_orders() {
+        let (mut state, temp_dir) = create_test_state();
+        let maker_pair = SigningKey::generate(&mut OsRng);
+        let maker = maker_pair.verifying_key().to_bytes().to_vec();
+        let taker_pair = SigningKey::generate(&mut OsRng);
+        let taker = taker_pair.verifying_key().to_bytes().to_vec();
+        let poor_pair = SigningKey::generate(&mut OsRng);
+        state.set_balance(maker.clone(), 100);
+        state.set_balance(taker.clone(), 100);
+        state.set_balance(poor_pair.verifying_key().to_bytes().to_vec(), 10);
+
+        let tx = |keypair: &SigningKey, data, nonce| Transaction::new(keypair.verifying_key().to_bytes().to_vec(), vec![], data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair);
+        let create_asset = tx(&maker_pair, TransactionData::CreateAsset { name: "Gold".into(), symbol: "GLD".into(), supply: 100, decimals: 0, metadata: vec![], max_supply: None }, 0);
+        state.apply_transaction(&create_asset).unwrap();
+        let asset_id = create_asset.calculate_asset_id().unwrap();
+
+        // The offer is escrowed as soon as the order is created
+        let gold = |amount| OrderAsset::Asset { asset_id: asset_id.clone(), amount };
+        let create_order = tx(&maker_pair, TransactionData::CreateOrder { offer: gold(30), ask: OrderAsset::Native { amount: 50 }, taker: None, expiry: 100 }, 1);
+        state.apply_transaction(&create_order).unwrap();
+        let order_id = create_order.calculate_asset_id().unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(*state.get_account(&maker).unwrap().assets.get(&asset_id).unwrap(), 70);
+
+        // A taker who cannot pay the ask gets nothing
+        let fill = |keypair, order_id: &Vec<u8>, nonce| tx(keypair, TransactionData::FillOrder { order_id: order_id.clone() }, nonce);
+        assert_eq!(state.apply_transaction(&fill(&poor_pair, &order_id, 0)).err().unwrap(), "Insufficient native balance");
+        state.discard_changes();
+
+        // Both legs settle in the same transaction
+        state.apply_transaction(&fill(&taker_pair, &order_id, 0)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_balance(&maker), 148);
+        assert_eq!(state.get_balance(&taker), 49);
+        assert_eq!(*state.get_account(&taker).unwrap().assets.get(&asset_id).unwrap(), 30);
+        assert_eq!(state.get_order(&order_id).unwrap().filled_by, Some(taker.clone()));
+        assert_eq!(state.apply_transaction(&fill(&taker_pair, &order_id, 1)).err().unwrap(), "Order already closed");
+        state.discard_changes();
+
+        // An escrowed NFT belongs to the order until it is filled or cancelled
+        let create_collection = tx(&maker_pair, TransactionData::CreateCollection { name: "Art".into(), symbol: "ART".into(), metadata: vec![], max_items: None }, 2);
+        state.apply_transaction(&create_collection).unwrap();
+        let collection_id = create_collection.calculate_asset_id().unwrap();
+        state.apply_transaction(&tx(&maker_pair, TransactionData::MintNFT { collection_id: collection_id.clone(), item_id: 1, item_metadata: vec![], recipient: maker.clone() }, 3)).unwrap();
+        let nft = OrderAsset::NFT { collection_id: collection_id.clone(), item_id: 1 };
+        let create_order = tx(&maker_pair, TransactionData::CreateOrder { offer: nft, ask: gold(20), taker: Some(taker.clone()), expiry: 5 }, 4);
+        state.apply_transaction(&create_order).unwrap();
+        let nft_order_id = create_order.calculate_asset_id().unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_nft(&collection_id, 1).unwrap().owner, nft_order_id);
+        assert!(state.get_account(&maker).unwrap().nfts.get(&collection_id).unwrap().is_empty());
+
+        assert_eq!(state.apply_transaction(&fill(&poor_pair, &nft_order_id, 0)).err().unwrap(), "Order is reserved for another taker");
+        state.discard_changes();
+        state.begin_block(6);
+        assert_eq!(state.apply_transaction(&fill(&taker_pair, &nft_order_id, 1)).err().unwrap(), "Order expired at slot 5");
+        state.discard_changes();
+
+        // Only the maker cancels, and gets the offer back
+        assert_eq!(state.apply_transaction(&tx(&taker_pair, TransactionData::CancelOrder { order_id: nft_order_id.clone() }, 1)).err().unwrap(), "Only the maker can cancel an order");
+        state.discard_changes();
+        state.apply_transaction(&tx(&maker_pair, TransactionData::CancelOrder { order_id: nft_order_id.clone() }, 5)).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_nft(&collection_id, 1).unwrap().owner, maker);
+        assert_eq!(state.get_account(&maker).unwrap().nfts.get(&collection_id).unwrap(), &vec![1]);
+        assert!(state.get_order(&nft_order_id).unwrap().is_closed);
+
+        // Orders are part of the state root
+        let restarted = State::new(Storage::new(temp_dir.path()).unwrap());
+        assert_eq!(restarted.get_root_hash(), state.get_root_hash());
+    }
+
+    #[test]
+    fn test_multisig_account() {
+        let (mut state, temp_dir) = create_test_state();
+        let keys: Vec<SigningKey> = (0..3).map(|_| SigningKey::generate(&mut OsRng)).collect();
+        let signers: Vec<Vec<u8>> = keys.iter().map(|k| k.verifying_key().to_bytes().to_vec()).collect();
+        let creator = signers[0].clone();
+        let receiver = vec![9; 32];
+        state.set_balance(creator.clone(), 100);
+
+        // The address only depends on the signer set and threshold, so it can be funded up front
+        let address = Transaction::multisig_address(&signers, 2);
+        state.set_balance(address.clone(), 50);
+        let create = Transaction::new(creator.clone(), vec![], TransactionData::CreateMultisig { signers: signers.clone(), threshold: 2 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &keys[0]);
+        state.apply_transaction(&create).unwrap();
+        state.apply_changes().unwrap();
+        let multisig = state.get_multisig(&address).unwrap();
+        assert_eq!(multisig.threshold, 2);
+
+        let reversed: Vec<Vec<u8>> = signers.iter().rev().cloned().collect();
+        assert_eq!(Transaction::multisig_address(&reversed, 2), address);
+        let recreate = Transaction::new(creator.clone(), vec![], TransactionData::CreateMultisig { signers: reversed, threshold: 2 }, 1, MIN_TRANSACTION_FEE, CHAIN_ID, &keys[0]);
+        assert_eq!(state.apply_transaction(&recreate).err().unwrap(), "Multisig account already exists");
+        state.discard_changes();
+
+        // Spending takes cosignatures from two of the three signers
+        let mut spend = Transaction::new_multisig(address.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 20 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID);
+        spend.cosign(&keys[2]);
+        assert_eq!(spend.validate(CHAIN_ID, &BlockLimits::default(), Some(&multisig)).err().unwrap(), "Multisig needs 2 cosignatures, got 1");
+        spend.cosign(&keys[1]);
+        spend.validate(CHAIN_ID, &BlockLimits::default(), Some(&multisig)).unwrap();
+        state.apply_transaction(&spend).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_balance(&address), 29);
+        assert_eq!(state.get_balance(&receiver), 20);
+        assert_eq!(state.get_nonce(&address), 1);
+
+        // Multisig accounts are part of the state root
+        let restarted = State::new(Storage::new(temp_dir.path()).unwrap());
+        assert_eq!(restarted.get_root_hash(), state.get_root_hash());
+        assert_eq!(restarted.get_multisig(&address), Some(multisig));
+    }
+
+    #[test]
+    fn test_fee_deducted_from_sender() {
+        let (mut state, _temp) = create_test_state();
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        let receiver = vec![1, 2, 3];
+        state.set_balance(sender.clone(), 100);
+
+        let tx = Transaction::new(sender.clone(), receiver.clone(), TransactionData::NativeTransfer { amount: 40 }, 0, 5, CHAIN_ID, &keypair);
+        state.apply_transaction(&tx).unwrap();
+
+        assert_eq!(state.get_balance(&sender), 55);
+        assert_eq!(state.get_balance(&receiver), 40);
+
+        // Fee is charged even for types that move no native balance
+        let (mut state, _temp) = create_test_state();
+        let tx = Transaction::new(sender.clone(), vec![], TransactionData::NativeTransfer { amount: 1 }, 0, 5, CHAIN_ID, &keypair);
+        assert_eq!(state.apply_transaction(&tx).err().unwrap(), "Sender account not found");
+    }
+
+    #[test]
+    fn test_credit_balance_saturates() {
+        let (mut state, _temp) = create_test_state();
+        let account = vec![1, 2, 3];
+        state.set_balance(account.clone(), u64::MAX - 10);
+
+        state.credit_balance(&account, 100);
+        assert_eq!(state.get_balance(&account), u64::MAX);
+    }
+
+    #[test]
+    fn test_registries_persist_and_enter_state_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = State::new(Storage::new(temp_dir.path()).unwrap());
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        state.set_balance(sender.clone(), 10);
+        state.apply_changes().unwrap();
+        let root_before = state.get_root_hash();
+
+        let create_tx = Transaction::new(
+            sender.clone(),
+            vec![],
+            TransactionData::CreateCollection { name: "Art".into(), symbol: "ART".into(), metadata: vec![], max_items: None },
+            0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
+            &keypair,
+        );
+        state.apply_transaction(&create_tx).unwrap();
+        let collection_id = create_tx.calculate_asset_id().unwrap();
+
+        // Discarded registry writes are rolled back with the accounts
+        state.discard_changes();
+        assert!(state.get_collection(&collection_id).is_none());
+
+        state.apply_transaction(&create_tx).unwrap();
+        state.apply_changes().unwrap();
+        let root_after = state.get_root_hash();
+        assert_ne!(root_before, root_after);
+
+        // A restarted node sees the same registry and state root
+        let restarted = State::new(Storage::new(temp_dir.path()).unwrap());
+        assert_eq!(restarted.get_collection(&collection_id).unwrap().symbol, "ART");
+        assert_eq!(restarted.get_root_hash(), root_after);
+    }
+
+    #[test]
+    fn test_validator_register_and_unregister() {
+        let (mut state, _temp) = create_test_state();
+        state.set_unbonding_epochs(2);
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        let validator_pair = crate::crypto::Crypto::generate_keypair();
+        let validator_key = validator_pair.public.to_bytes().to_vec();
+        state.set_balance(sender.clone(), 5000);
+        state.begin_block(3 * SLOTS_PER_EPOCH);
+
+        let register = |nonce| {
+            let data = TransactionData::RegisterValidator {
+                stake: 2000,
+                validator_pubkey: validator_key.clone(),
+                proof_of_possession: crate::crypto::Crypto::prove_possession(&validator_pair, &sender),
+            };
+            Transaction::new(sender.clone(), vec![], data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, &keypair)
+        };
+        state.apply_transaction(&register(0)).unwrap();
+        assert_eq!(state.get_balance(&sender), 2999);
+        let validator = state.get_validator(&validator_key).unwrap();
+        assert_eq!((validator.stake, validator.registered_epoch), (2000, 4));
+        assert_eq!(validator.reward_address, sender);
+        assert!(state.get_validator(&sender).is_none());
+        state.apply_changes().unwrap();
+        assert_eq!(state.apply_transaction(&register(1)).err().unwrap(), "Validator already registered");
+        state.discard_changes();
+
+        // Only the registering account can take the validator out
+        state.begin_block(4 * SLOTS_PER_EPOCH);
+        let other_pair = SigningKey::generate(&mut OsRng);
+        let other = other_pair.verifying_key().to_bytes().to_vec();
+        state.set_balance(other.clone(), 100);
+        let unregister = |sender: &[u8], nonce, keypair: &SigningKey| {
+            let data = TransactionData::UnregisterValidator { validator_pubkey: validator_key.clone() };
+            Transaction::new(sender.to_vec(), vec![], data, nonce, MIN_TRANSACTION_FEE, CHAIN_ID, keypair)
+        };
+        assert_eq!(
+            state.apply_transaction(&unregister(&other, 0, &other_pair)).err().unwrap(),
+            "Only the registering account can unregister the validator"
+        );
+        state.discard_changes();
+
+        // Unregistering in epoch 4 exits at epoch 5 and unbonds until epoch 7
+        state.apply_transaction(&unregister(&sender, 1, &keypair)).unwrap();
+        state.apply_changes().unwrap();
+        let validator = state.get_validator(&validator_key).unwrap();
+        assert_eq!((validator.exit_epoch, validator.withdrawable_epoch), (Some(5), 7));
+        assert!(validator.is_active_at(4) && !validator.is_active_at(5));
+        assert_eq!(state.get_balance(&sender), 2998);
+
+        state.begin_block(6 * SLOTS_PER_EPOCH);
+        assert_eq!(state.get_balance(&sender), 2998);
+        assert_eq!(state.apply_transaction(&register(2)).err().unwrap(), "Validator is unbonding");
+        state.discard_changes();
+
+        // The stake comes back to the registering account once the unbonding period is over
+        state.begin_block(7 * SLOTS_PER_EPOCH);
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_balance(&sender), 4998);
+        assert_eq!(state.get_validator(&validator_key).unwrap().stake, 0);
+        assert_eq!(state.get_all_validators().len(), 1);
+
+        // ...and the validator may register again
+        state.apply_transaction(&register(2)).unwrap();
+        assert_eq!(state.get_validator(&validator_key).unwrap().stake, 2000);
+    }
+
+    #[test]
+    fn test_unbonding_stake_is_slashable() {
+        let (mut state, _temp) = create_test_state();
+        state.set_unbonding_epochs(2);
+        let pubkey = vec![7; 32];
+        state.set_balance(pubkey.clone(), 0);
+        let mut validator = ValidatorInfo::new(pubkey.clone(), 3000, 0);
+        validator.exit_epoch = Some(1);
+        validator.withdrawable_epoch = 3;
+        state.add_validator(validator);
+        state.apply_changes().unwrap();
+
+        assert_eq!(state.slash_validator(&pubkey).unwrap(), 3000);
+        state.begin_block(3 * SLOTS_PER_EPOCH);
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_balance(&pubkey), 0);
+        assert!(state.get_validator(&pubkey).unwrap().slashed);
+    }
+
+    #[test]
+    fn test_equivocation_reward_does_not_truncate_stake() {
+        let (mut state, _temp) = create_test_state();
+        let offender = vec![7; 32];
+        let reporter = vec![8; 32];
+        state.add_validator(ValidatorInfo::new(offender.clone(), 1999, 0));
+        state.apply_changes().unwrap();
+
+        // 10% of 1999, not 10% of 1900
+        state.punish_equivocation(&reporter, &offender).unwrap();
+        state.apply_changes().unwrap();
+        assert_eq!(state.get_balance(&reporter), 199);
+    }
 }
@@ -1,7 +1,12 @@
-use crate::block::Block;
-use crate::state::Account;
+use crate::block::{Block, BlockHeader};
+use crate::chain::BlockUndo;
+use crate::consensus::RandomnessMix;
+use crate::state::{Account, Asset, Collection, MultisigAccount, NFTItem, Order, PaymentChannel};
+use crate::transaction::BalanceProof;
+use crate::validator::ValidatorInfo;
 use crate::vote::Vote;
-use rocksdb::{DB, Options, IteratorMode, Direction};
+use rocksdb::{DB, Options, IteratorMode, Direction, WriteBatch};
+use std::sync::{Arc, Mutex};
 use std::path::Path;
 
 #[derive(Clone)]
@@ -10,6 +15,8 @@ use std::path::Path;
 // We should wrap it in Arc<DB> to allow cheap cloning of the Storage struct which is likely expected.
 pub struct Storage {
     db: std::sync::Arc<DB>,
+    /// Writes collected by `begin_batch`, shared by every clone of this handle
+    batch: Arc<Mutex<Option<WriteBatch>>>,
 }
 
 impl Storage {
@@ -19,7 +26,48 @@ impl Storage {
         opts.create_if_missing(true);
         
         let db = DB::open(&opts, path).map_err(|e| format!("Failed to open database: {}", e))?;
-        Ok(Self { db: std::sync::Arc::new(db) })
+        Ok(Self { db: std::sync::Arc::new(db), batch: Arc::new(Mutex::new(None)) })
+    }
+
+    /// Collect every write from here on into one batch, applied atomically by `commit_batch`
+    /// Reads keep seeing the database as it was until the batch is committed.
+    pub fn begin_batch(&self) {
+        *self.batch.lock().unwrap() = Some(WriteBatch::default());
+    }
+
+    /// Apply the open batch in one atomic write
+    pub fn commit_batch(&self) -> Result<(), String> {
+        match self.batch.lock().unwrap().take() {
+            Some(batch) => self.db.write(batch).map_err(|e| format!("Failed to write batch: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the open batch without writing any of it
+    pub fn discard_batch(&self) {
+        self.batch.lock().unwrap().take();
+    }
+
+    /// Write a key, into the open batch if there is one
+    fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), rocksdb::Error> {
+        match self.batch.lock().unwrap().as_mut() {
+            Some(batch) => {
+                batch.put(key, value);
+                Ok(())
+            }
+            None => self.db.put(key, value),
+        }
+    }
+
+    /// Delete a key, in the open batch if there is one
+    fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), rocksdb::Error> {
+        match self.batch.lock().unwrap().as_mut() {
+            Some(batch) => {
+                batch.delete(key);
+                Ok(())
+            }
+            None => self.db.delete(key),
+        }
     }
 
     /// Store a block
@@ -28,8 +76,7 @@ impl Storage {
         let value = bincode::serialize(block)
             .map_err(|e| format!("Failed to serialize block: {}", e))?;
         
-        self.db
-            .put(key.as_bytes(), value)
+        self.put(key.as_bytes(), value)
             .map_err(|e| format!("Failed to store block: {}", e))?;
         
         Ok(())
@@ -55,12 +102,18 @@ impl Storage {
     /// Store a block hash by height
     pub fn store_block_by_height(&self, height: u64, hash: &str) -> Result<(), String> {
         let key = format!("height:{}", height);
-        self.db
-            .put(key.as_bytes(), hash.as_bytes())
+        self.put(key.as_bytes(), hash.as_bytes())
             .map_err(|e| format!("Failed to store block by height: {}", e))?;
         Ok(())
     }
 
+    /// Remove a height index entry (block left the canonical chain)
+    pub fn delete_block_by_height(&self, height: u64) -> Result<(), String> {
+        let key = format!("height:{}", height);
+        self.delete(key.as_bytes())
+            .map_err(|e| format!("Failed to delete block by height: {}", e))
+    }
+
     /// Retrieve a block hash by height
     pub fn get_block_by_height(&self, height: u64) -> Result<Option<String>, String> {
         let key = format!("height:{}", height);
@@ -80,12 +133,34 @@ impl Storage {
 
     /// Store the current chain head
     pub fn store_head(&self, hash: &str) -> Result<(), String> {
-        self.db
-            .put(b"head", hash.as_bytes())
+        self.put(b"head", hash.as_bytes())
             .map_err(|e| format!("Failed to store head: {}", e))?;
         Ok(())
     }
 
+    /// Store the latest finalized block
+    pub fn store_finalized(&self, hash: &str) -> Result<(), String> {
+        self.put(b"finalized", hash.as_bytes())
+            .map_err(|e| format!("Failed to store finalized block: {}", e))?;
+        Ok(())
+    }
+
+    /// Retrieve the latest finalized block
+    pub fn get_finalized(&self) -> Result<Option<String>, String> {
+        let value = self.db
+            .get(b"finalized")
+            .map_err(|e| format!("Failed to get finalized block: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let hash = String::from_utf8(bytes.to_vec())
+                    .map_err(|e| format!("Failed to decode finalized block: {}", e))?;
+                Ok(Some(hash))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Retrieve the current chain head
     pub fn get_head(&self) -> Result<Option<String>, String> {
         let value = self.db
@@ -108,8 +183,7 @@ impl Storage {
         let value = bincode::serialize(account)
             .map_err(|e| format!("Failed to serialize account: {}", e))?;
         
-        self.db
-            .put(&key, value)
+        self.put(&key, value)
             .map_err(|e| format!("Failed to store account: {}", e))?;
         
         Ok(())
@@ -132,6 +206,13 @@ impl Storage {
         }
     }
 
+    /// Delete account state (reverting an account created by an orphaned block)
+    pub fn delete_account(&self, address: &[u8]) -> Result<(), String> {
+        let key = [b"account:", address].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete account: {}", e))
+    }
+
     /// Get all accounts (for rebuilding MPT on startup)
     pub fn get_all_accounts(&self) -> Result<Vec<(Vec<u8>, Account)>, String> {
         let mut accounts = Vec::new();
@@ -160,6 +241,344 @@ impl Storage {
         Ok(accounts)
     }
 
+    /// Store an asset definition
+    pub fn store_asset(&self, asset: &Asset) -> Result<(), String> {
+        let key = [b"asset:", asset.id.as_slice()].concat();
+        let value = bincode::serialize(asset)
+            .map_err(|e| format!("Failed to serialize asset: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store asset: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Retrieve an asset definition
+    pub fn get_asset(&self, asset_id: &[u8]) -> Result<Option<Asset>, String> {
+        let key = [b"asset:", asset_id].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get asset: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let asset = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize asset: {}", e))?;
+                Ok(Some(asset))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a asset (reverting one created by an orphaned block)
+    pub fn delete_asset(&self, asset_id: &[u8]) -> Result<(), String> {
+        let key = [b"asset:", asset_id].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete asset: {}", e))
+    }
+
+    /// Get all assets (for rebuilding MPT on startup)
+    pub fn get_all_assets(&self) -> Result<Vec<Asset>, String> {
+        self.scan_prefix(b"asset:", "asset")
+    }
+
+    /// Store an NFT collection
+    pub fn store_collection(&self, collection: &Collection) -> Result<(), String> {
+        let key = [b"collection:", collection.id.as_slice()].concat();
+        let value = bincode::serialize(collection)
+            .map_err(|e| format!("Failed to serialize collection: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store collection: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Retrieve an NFT collection
+    pub fn get_collection(&self, collection_id: &[u8]) -> Result<Option<Collection>, String> {
+        let key = [b"collection:", collection_id].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get collection: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let collection = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize collection: {}", e))?;
+                Ok(Some(collection))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a collection (reverting one created by an orphaned block)
+    pub fn delete_collection(&self, collection_id: &[u8]) -> Result<(), String> {
+        let key = [b"collection:", collection_id].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete collection: {}", e))
+    }
+
+    /// Get all NFT collections (for rebuilding MPT on startup)
+    pub fn get_all_collections(&self) -> Result<Vec<Collection>, String> {
+        self.scan_prefix(b"collection:", "collection")
+    }
+
+    /// Store an NFT item
+    /// Key: "nft:collection_id:item_id" -> NFTItem, so an item's owner is a single lookup
+    pub fn store_nft(&self, item: &NFTItem) -> Result<(), String> {
+        let key = Self::nft_key(&item.collection_id, item.id);
+        let value = bincode::serialize(item)
+            .map_err(|e| format!("Failed to serialize NFT: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store NFT: {}", e))
+    }
+
+    /// Retrieve an NFT item
+    pub fn get_nft(&self, collection_id: &[u8], item_id: u64) -> Result<Option<NFTItem>, String> {
+        let key = Self::nft_key(collection_id, item_id);
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get NFT: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let item = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize NFT: {}", e))?;
+                Ok(Some(item))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete an NFT item (reverting one minted by an orphaned block)
+    pub fn delete_nft(&self, collection_id: &[u8], item_id: u64) -> Result<(), String> {
+        self.delete(Self::nft_key(collection_id, item_id))
+            .map_err(|e| format!("Failed to delete NFT: {}", e))
+    }
+
+    /// Get all NFT items (for rebuilding MPT on startup)
+    pub fn get_all_nfts(&self) -> Result<Vec<NFTItem>, String> {
+        self.scan_prefix(b"nft:", "NFT")
+    }
+
+    fn nft_key(collection_id: &[u8], item_id: u64) -> Vec<u8> {
+        [b"nft:".as_slice(), collection_id, &item_id.to_be_bytes()].concat()
+    }
+
+    /// Store a payment channel
+    pub fn store_channel(&self, channel: &PaymentChannel) -> Result<(), String> {
+        let key = [b"channel:", channel.id.as_slice()].concat();
+        let value = bincode::serialize(channel)
+            .map_err(|e| format!("Failed to serialize channel: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store channel: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Retrieve a payment channel
+    pub fn get_channel(&self, channel_id: &[u8]) -> Result<Option<PaymentChannel>, String> {
+        let key = [b"channel:", channel_id].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get channel: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let channel = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize channel: {}", e))?;
+                Ok(Some(channel))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a channel (reverting one created by an orphaned block)
+    pub fn delete_channel(&self, channel_id: &[u8]) -> Result<(), String> {
+        let key = [b"channel:", channel_id].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete channel: {}", e))
+    }
+
+    /// Get all payment channels (for rebuilding MPT on startup)
+    pub fn get_all_channels(&self) -> Result<Vec<PaymentChannel>, String> {
+        self.scan_prefix(b"channel:", "channel")
+    }
+
+    /// Store a swap order
+    pub fn store_order(&self, order: &Order) -> Result<(), String> {
+        let key = [b"order:", order.id.as_slice()].concat();
+        let value = bincode::serialize(order)
+            .map_err(|e| format!("Failed to serialize order: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store order: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Retrieve a swap order
+    pub fn get_order(&self, order_id: &[u8]) -> Result<Option<Order>, String> {
+        let key = [b"order:", order_id].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get order: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let order = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize order: {}", e))?;
+                Ok(Some(order))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete an order (reverting one created by an orphaned block)
+    pub fn delete_order(&self, order_id: &[u8]) -> Result<(), String> {
+        let key = [b"order:", order_id].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete order: {}", e))
+    }
+
+    /// Get all swap orders (for rebuilding MPT on startup)
+    pub fn get_all_orders(&self) -> Result<Vec<Order>, String> {
+        self.scan_prefix(b"order:", "order")
+    }
+
+    /// Store a multisig account's signer set
+    pub fn store_multisig(&self, multisig: &MultisigAccount) -> Result<(), String> {
+        let key = [b"multisig:", multisig.address.as_slice()].concat();
+        let value = bincode::serialize(multisig)
+            .map_err(|e| format!("Failed to serialize multisig: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store multisig: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Retrieve the signer set of a multisig account
+    pub fn get_multisig(&self, address: &[u8]) -> Result<Option<MultisigAccount>, String> {
+        let key = [b"multisig:", address].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get multisig: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let multisig = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize multisig: {}", e))?;
+                Ok(Some(multisig))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a multisig account (reverting one created by an orphaned block)
+    pub fn delete_multisig(&self, address: &[u8]) -> Result<(), String> {
+        let key = [b"multisig:", address].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete multisig: {}", e))
+    }
+
+    /// Get all multisig accounts (for rebuilding MPT on startup)
+    pub fn get_all_multisigs(&self) -> Result<Vec<MultisigAccount>, String> {
+        self.scan_prefix(b"multisig:", "multisig")
+    }
+
+    /// Store the latest off-chain state of a payment channel this node takes part in
+    /// Key: "channel_state:channel_id" -> BalanceProof
+    pub fn store_channel_state(&self, proof: &BalanceProof) -> Result<(), String> {
+        let key = [b"channel_state:", proof.channel_id.as_slice()].concat();
+        let value = bincode::serialize(proof)
+            .map_err(|e| format!("Failed to serialize channel state: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store channel state: {}", e))
+    }
+
+    /// Retrieve the latest off-chain state of a payment channel
+    pub fn get_channel_state(&self, channel_id: &[u8]) -> Result<Option<BalanceProof>, String> {
+        let key = [b"channel_state:", channel_id].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get channel state: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let proof = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize channel state: {}", e))?;
+                Ok(Some(proof))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Store a validator record
+    pub fn store_validator(&self, validator: &ValidatorInfo) -> Result<(), String> {
+        let key = [b"validator:", validator.pubkey.as_slice()].concat();
+        let value = bincode::serialize(validator)
+            .map_err(|e| format!("Failed to serialize validator: {}", e))?;
+
+        self.put(&key, value)
+            .map_err(|e| format!("Failed to store validator: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Retrieve a validator record
+    pub fn get_validator(&self, pubkey: &[u8]) -> Result<Option<ValidatorInfo>, String> {
+        let key = [b"validator:", pubkey].concat();
+        let value = self.db
+            .get(&key)
+            .map_err(|e| format!("Failed to get validator: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let validator = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize validator: {}", e))?;
+                Ok(Some(validator))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a validator record (reverting one created by an orphaned block)
+    pub fn delete_validator(&self, pubkey: &[u8]) -> Result<(), String> {
+        let key = [b"validator:", pubkey].concat();
+        self.delete(&key)
+            .map_err(|e| format!("Failed to delete validator: {}", e))
+    }
+
+    /// Get all validator records (for rebuilding the validator set on startup)
+    pub fn get_all_validators(&self) -> Result<Vec<ValidatorInfo>, String> {
+        self.scan_prefix(b"validator:", "validator")
+    }
+
+    /// Deserialize every value stored under a key prefix
+    fn scan_prefix<T: serde::de::DeserializeOwned>(&self, prefix: &[u8], what: &str) -> Result<Vec<T>, String> {
+        let mut values = Vec::new();
+
+        let iter = self.db.iterator(IteratorMode::From(prefix, Direction::Forward));
+
+        for item in iter {
+            let (key, value) = item.map_err(|e| format!("Failed to scan {}s: {}", what, e))?;
+
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            let decoded: T = bincode::deserialize(&value)
+                .map_err(|e| format!("Failed to deserialize {}: {}", what, e))?;
+            values.push(decoded);
+        }
+
+        Ok(values)
+    }
+
     /// Flush all pending writes
     #[allow(dead_code)]
     pub fn flush(&self) -> Result<(), String> {
@@ -175,17 +594,58 @@ impl Storage {
     pub fn delete_block(&self, hash: &str) -> Result<(), String> {
         // Delete block data
         let block_key = format!("block:{}", hash);
-        self.db
-            .delete(block_key.as_bytes())
+        self.delete(block_key.as_bytes())
             .map_err(|e| format!("Failed to delete block: {}", e))?;
+
+        // Delete its undo record
+        let undo_key = format!("undo:{}", hash);
+        self.delete(undo_key.as_bytes())
+            .map_err(|e| format!("Failed to delete undo record: {}", e))?;
             
         Ok(())
     }
 
+    /// Store the undo record of a committed block
+    /// Key: "undo:block_hash" -> serialized BlockUndo
+    pub fn store_undo(&self, block_hash: &str, undo: &BlockUndo) -> Result<(), String> {
+        let key = format!("undo:{}", block_hash);
+        let value = bincode::serialize(undo)
+            .map_err(|e| format!("Failed to serialize undo record: {}", e))?;
+
+        self.put(key.as_bytes(), value)
+            .map_err(|e| format!("Failed to store undo record: {}", e))?;
+        Ok(())
+    }
+
+    /// Retrieve the undo record of a committed block
+    pub fn get_undo(&self, block_hash: &str) -> Result<Option<BlockUndo>, String> {
+        let key = format!("undo:{}", block_hash);
+        let value = self.db
+            .get(key.as_bytes())
+            .map_err(|e| format!("Failed to get undo record: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let undo = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize undo record: {}", e))?;
+                Ok(Some(undo))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Prune blocks older than a certain height
+    /// Only finalized history is pruned: the target is capped at the finalized block.
     /// Returns the number of blocks deleted
     pub fn prune_blocks_before(&self, target_height: u64, chain_head_hash: &str) -> Result<u64, String> {
         let mut deleted_count = 0;
+
+        // Blocks after the finalized one may still be reorged and need their undo records
+        let finalized_height = match self.get_finalized()? {
+            Some(hash) => self.get_block(&hash)?.map(|b| b.header.slot).unwrap_or(0),
+            None => 0,
+        };
+        let target_height = target_height.min(finalized_height);
         
         // Iterate through all keys to find "height:X" keys
         // Note: Ideally we would scan a range, but "height:" prefix with string numbers doesn't order lexicographically correct (height:10 < height:2).
@@ -202,7 +662,7 @@ impl Storage {
             // We need to get the value first to return it, as delete doesn't return the old value in RocksDB
             let height_key_bytes = height_key.as_bytes();
             if let Some(hash_bytes) = self.db.get(height_key_bytes).map_err(|e| e.to_string())? {
-                self.db.delete(height_key_bytes).map_err(|e| e.to_string())?;
+                self.delete(height_key_bytes).map_err(|e| e.to_string())?;
                 let hash = String::from_utf8(hash_bytes.to_vec())
                     .map_err(|e| format!("Failed to decode hash: {}", e))?;
                 
@@ -213,6 +673,9 @@ impl Storage {
                 
                 // Delete the actual block data
                 self.delete_block(&hash)?;
+                self.delete_votes(&hash)?;
+                self.delete_block_weight(&hash)?;
+                self.delete_block_randomness(&hash)?;
                 deleted_count += 1;
             }
         }
@@ -220,23 +683,97 @@ impl Storage {
         Ok(deleted_count)
     }
 
-    /// Store a seen block header (for slashing detection)
-    /// Key: "header:slot:pubkey" -> block_hash
-    pub fn store_seen_header(&self, slot: u64, pubkey: &[u8], block_hash: &str) -> Result<(), String> {
-        let key = format!("header:{}:{}", slot, hex::encode(pubkey));
-        self.db
-            .put(key.as_bytes(), block_hash.as_bytes())
+    /// Store the chain weight of a block (number of blocks from genesis up to it)
+    /// Key: "weight:hash" -> weight
+    pub fn store_block_weight(&self, hash: &str, weight: u64) -> Result<(), String> {
+        let key = format!("weight:{}", hash);
+        self.put(key.as_bytes(), weight.to_le_bytes())
+            .map_err(|e| format!("Failed to store block weight: {}", e))?;
+        Ok(())
+    }
+
+    /// Get the chain weight of a block
+    pub fn get_block_weight(&self, hash: &str) -> Result<Option<u64>, String> {
+        let key = format!("weight:{}", hash);
+        let value = self.db.get(key.as_bytes())
+            .map_err(|e| format!("Failed to get block weight: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let bytes: [u8; 8] = bytes[..].try_into()
+                    .map_err(|_| "Failed to decode block weight".to_string())?;
+                Ok(Some(u64::from_le_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the chain weight of a block
+    pub fn delete_block_weight(&self, hash: &str) -> Result<(), String> {
+        let key = format!("weight:{}", hash);
+        self.delete(key.as_bytes())
+            .map_err(|e| format!("Failed to delete block weight: {}", e))
+    }
+
+    /// Store the epoch randomness and VRF mix as of a block
+    /// Key: "randomness:hash" -> serialized mix
+    pub fn store_block_randomness(&self, hash: &str, mix: &RandomnessMix) -> Result<(), String> {
+        let key = format!("randomness:{}", hash);
+        let value = bincode::serialize(mix)
+            .map_err(|e| format!("Failed to serialize randomness mix: {}", e))?;
+        self.put(key.as_bytes(), value)
+            .map_err(|e| format!("Failed to store randomness mix: {}", e))?;
+        Ok(())
+    }
+
+    /// Get the epoch randomness and VRF mix as of a block
+    pub fn get_block_randomness(&self, hash: &str) -> Result<Option<RandomnessMix>, String> {
+        let key = format!("randomness:{}", hash);
+        let value = self.db.get(key.as_bytes())
+            .map_err(|e| format!("Failed to get randomness mix: {}", e))?;
+
+        match value {
+            Some(bytes) => {
+                let mix = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize randomness mix: {}", e))?;
+                Ok(Some(mix))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the randomness mix of a block
+    pub fn delete_block_randomness(&self, hash: &str) -> Result<(), String> {
+        let key = format!("randomness:{}", hash);
+        self.delete(key.as_bytes())
+            .map_err(|e| format!("Failed to delete randomness mix: {}", e))
+    }
+
+    /// Store a seen block header (kept as equivocation evidence)
+    /// Key: "header:slot:pubkey" -> serialized header
+    pub fn store_seen_header(&self, header: &BlockHeader) -> Result<(), String> {
+        let key = format!("header:{}:{}", header.slot, hex::encode(&header.validator_pubkey));
+        let value = bincode::serialize(header)
+            .map_err(|e| format!("Failed to serialize seen header: {}", e))?;
+        self.put(key.as_bytes(), value)
             .map_err(|e| format!("Failed to store seen header: {}", e))?;
         Ok(())
     }
 
     /// Get a seen block header
-    pub fn get_seen_header(&self, slot: u64, pubkey: &[u8]) -> Result<Option<String>, String> {
+    pub fn get_seen_header(&self, slot: u64, pubkey: &[u8]) -> Result<Option<BlockHeader>, String> {
         let key = format!("header:{}:{}", slot, hex::encode(pubkey));
         let value = self.db.get(key.as_bytes())
             .map_err(|e| format!("Failed to get seen header: {}", e))?;
-            
-        Ok(value.map(|v| String::from_utf8_lossy(&v).to_string()))
+
+        match value {
+            Some(bytes) => {
+                let header = bincode::deserialize(&bytes)
+                    .map_err(|e| format!("Failed to deserialize seen header: {}", e))?;
+                Ok(Some(header))
+            }
+            None => Ok(None),
+        }
     }
 
     /// Store a vote
@@ -246,7 +783,7 @@ impl Storage {
         let value = bincode::serialize(vote)
             .map_err(|e| format!("Failed to serialize vote: {}", e))?;
             
-        self.db.put(key.as_bytes(), value)
+        self.put(key.as_bytes(), value)
             .map_err(|e| format!("Failed to store vote: {}", e))?;
         Ok(())
     }
@@ -271,6 +808,31 @@ impl Storage {
         Ok(votes)
     }
 
+    /// Get every stored vote (for reloading pending votes on startup)
+    pub fn get_all_votes(&self) -> Result<Vec<Vote>, String> {
+        self.scan_prefix(b"vote:", "vote")
+    }
+
+    /// Delete all votes for a block
+    pub fn delete_votes(&self, block_hash: &str) -> Result<(), String> {
+        let prefix = format!("vote:{}", block_hash);
+        let iter = self.db.iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward));
+
+        let mut keys = Vec::new();
+        for item in iter {
+            let (key, _) = item.map_err(|e| format!("Failed to scan votes: {}", e))?;
+            if !key.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            keys.push(key);
+        }
+        for key in keys {
+            self.delete(&key).map_err(|e| format!("Failed to delete vote: {}", e))?;
+        }
+
+        Ok(())
+    }
+
     // Faucet claim tracking methods
     
     /// Record a faucet claim for an address
@@ -279,7 +841,7 @@ impl Storage {
         let key = [b"faucet:", address].concat();
         let value = timestamp.to_le_bytes();
         
-        self.db.put(&key, value.as_ref())
+        self.put(&key, value.as_ref())
             .map_err(|e| format!("Failed to record faucet claim: {}", e))?;
         Ok(())
     }
@@ -335,8 +897,7 @@ impl Storage {
     /// Key: "tx_index:tx_hash_hex" -> block_hash_hex
     pub fn store_transaction_index(&self, tx_hash: &str, block_hash: &str) -> Result<(), String> {
         let key = format!("tx_index:{}", tx_hash);
-        self.db
-            .put(key.as_bytes(), block_hash.as_bytes())
+        self.put(key.as_bytes(), block_hash.as_bytes())
             .map_err(|e| format!("Failed to store transaction index: {}", e))?;
         Ok(())
     }
@@ -351,6 +912,13 @@ impl Storage {
         Ok(value.map(|v| String::from_utf8_lossy(&v).to_string()))
     }
 
+    /// Remove the block index of a transaction (its block was reverted)
+    pub fn delete_transaction_index(&self, tx_hash: &str) -> Result<(), String> {
+        let key = format!("tx_index:{}", tx_hash);
+        self.delete(key.as_bytes())
+            .map_err(|e| format!("Failed to delete transaction index: {}", e))
+    }
+
     // Address History Indexing
     
     /// Add a transaction to an address's history
@@ -373,11 +941,11 @@ impl Storage {
         
         // 2. Store item at current count
         let item_key = format!("history:{}:{}", address, count);
-        self.db.put(item_key.as_bytes(), tx_hash.as_bytes())
+        self.put(item_key.as_bytes(), tx_hash.as_bytes())
             .map_err(|e| format!("Failed to store history item: {}", e))?;
             
         // 3. Increment count
-        self.db.put(count_key.as_bytes(), (count + 1).to_le_bytes().as_ref())
+        self.put(count_key.as_bytes(), (count + 1).to_le_bytes().as_ref())
             .map_err(|e| format!("Failed to update history count: {}", e))?;
             
         Ok(())
@@ -461,6 +1029,36 @@ mod tests {
         assert_eq!(retrieved, Some(hash.to_string()));
     }
 
+    #[test]
+    fn test_batched_writes_apply_together() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        storage.store_head("old_head").unwrap();
+
+        // Nothing in a batch is visible before it is committed, also through clones
+        let clone = storage.clone();
+        clone.begin_batch();
+        storage.store_head("new_head").unwrap();
+        storage.store_block_by_height(1, "new_head").unwrap();
+        assert_eq!(storage.get_head().unwrap(), Some("old_head".to_string()));
+
+        // A discarded batch leaves the database untouched
+        storage.discard_batch();
+        assert_eq!(storage.get_head().unwrap(), Some("old_head".to_string()));
+        assert_eq!(storage.get_block_by_height(1).unwrap(), None);
+
+        storage.begin_batch();
+        storage.store_head("new_head").unwrap();
+        storage.store_block_by_height(1, "new_head").unwrap();
+        storage.commit_batch().unwrap();
+        assert_eq!(storage.get_head().unwrap(), Some("new_head".to_string()));
+        assert_eq!(storage.get_block_by_height(1).unwrap(), Some("new_head".to_string()));
+
+        // Without a batch, writes go straight through again
+        storage.store_head("latest").unwrap();
+        assert_eq!(clone.get_head().unwrap(), Some("latest".to_string()));
+    }
+
     #[test]
     fn test_store_and_retrieve_account() {
         let temp_dir = TempDir::new().unwrap();
@@ -476,6 +1074,30 @@ mod tests {
         assert_eq!(retrieved.unwrap().balance, 1000);
     }
 
+    #[test]
+    fn test_store_and_retrieve_asset() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+
+        let asset = Asset {
+            id: vec![1; 32],
+            issuer: vec![2; 32],
+            name: "TestCoin".to_string(),
+            symbol: "TST".to_string(),
+            total_supply: 1000,
+            decimals: 8,
+            metadata: vec![],
+            admin: vec![2; 32],
+            max_supply: None,
+            is_frozen: false,
+        };
+
+        storage.store_asset(&asset).unwrap();
+        assert_eq!(storage.get_asset(&asset.id).unwrap().unwrap().symbol, "TST");
+        assert!(storage.get_asset(&[3; 32]).unwrap().is_none());
+        assert_eq!(storage.get_all_assets().unwrap().len(), 1);
+    }
+
     #[test]
     fn test_store_and_retrieve_block_by_height() {
         let temp_dir = TempDir::new().unwrap();
@@ -490,6 +1112,43 @@ mod tests {
         assert_eq!(retrieved, Some(hash.to_string()));
     }
 
+    #[test]
+    fn test_prune_stops_at_finalized_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+
+        let mut hashes = Vec::new();
+        for slot in 1..=5 {
+            let header = BlockHeader {
+                parent_hash: "0".to_string(),
+                slot,
+                epoch: 0,
+                vrf_output: vec![],
+                vrf_proof: vec![],
+                validator_pubkey: vec![],
+                producer_signature: vec![],
+                state_root: "".to_string(),
+                tx_root: "".to_string(),
+                extra_witnesses: vec![],
+                timestamp: 0,
+            };
+            let block = Block::new(header, vec![]);
+            storage.store_block(&block).unwrap();
+            storage.store_block_by_height(slot, &block.hash).unwrap();
+            hashes.push(block.hash);
+        }
+
+        // Nothing is finalized yet, so nothing may be pruned
+        assert_eq!(storage.prune_blocks_before(5, &hashes[4]).unwrap(), 0);
+
+        storage.store_finalized(&hashes[2]).unwrap();
+        assert_eq!(storage.get_finalized().unwrap(), Some(hashes[2].clone()));
+        assert_eq!(storage.prune_blocks_before(5, &hashes[4]).unwrap(), 2);
+        assert!(storage.get_block(&hashes[1]).unwrap().is_none());
+        assert!(storage.get_block(&hashes[2]).unwrap().is_some());
+        assert!(storage.get_block(&hashes[3]).unwrap().is_some());
+    }
+
     #[test]
     fn test_faucet_claim_tracking() {
         let temp_dir = TempDir::new().unwrap();
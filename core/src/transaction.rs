@@ -1,7 +1,17 @@
 use serde::{Deserialize, Serialize};
 use ed25519_dalek::{Signature, VerifyingKey, Signer, SigningKey, Verifier};
 use sha2::{Digest, Sha256};
+use crate::block::{Block, BlockHeader};
+use crate::consensus::BlockLimits;
+use crate::state::MultisigAccount;
 
+/// Minimum flat fee (in native units) every transaction must pay
+pub const MIN_TRANSACTION_FEE: u64 = 1;
+
+/// Largest signer set a multisig account can have
+pub const MAX_MULTISIG_SIGNERS: usize = 16;
+
+/// Encoded with bincode, which stores the variant index: new variants go at the end
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 pub enum TransactionData {
     NativeTransfer {
@@ -13,6 +23,7 @@ pub enum TransactionData {
         supply: u64,
         decimals: u8,
         metadata: Vec<u8>,
+        max_supply: Option<u64>, // Hard cap on minting; unlimited if unset
     },
     TransferAsset {
         asset_id: Vec<u8>,
@@ -22,6 +33,7 @@ pub enum TransactionData {
         name: String,
         symbol: String,
         metadata: Vec<u8>,
+        max_items: Option<u64>, // Cap on items ever minted; unlimited if unset
     },
     MintNFT {
         collection_id: Vec<u8>,
@@ -39,21 +51,253 @@ pub enum TransactionData {
         amount: u64,
         duration: u64, // Duration in slots or blocks
     },
+    // Cooperative close: settles a final state co-signed by both partners at once
     CloseChannel {
         channel_id: Vec<u8>,
-        balance_proof: Vec<u8>, 
-        final_balance_a: u64,
-        final_balance_b: u64,
+        balance_proof: BalanceProof,
     },
     DelegateSpend {
         delegate: Vec<u8>,
         allowance: u64,
-        expiry: u64, // Epoch or slot
+        expiry: u64, // Last slot the allowance can be used in
     },
+    // Bonds the sender's stake to a schnorrkel block-signing key; rewards go to the sender
     RegisterValidator {
         stake: u64,
+        validator_pubkey: Vec<u8>,
+        proof_of_possession: Vec<u8>, // Validator key's signature over the sender
+    },
+    // Sent by the account that registered the validator
+    UnregisterValidator {
+        validator_pubkey: Vec<u8>,
+    },
+    // Slashing
+    SubmitEquivocationEvidence {
+        header_a: Box<BlockHeader>,
+        header_b: Box<BlockHeader>, // Two signed headers for the same slot by the same validator
+    },
+    DelegatedTransfer {
+        owner: Vec<u8>, // Account whose allowance the delegate (sender) spends
+        amount: u64,
+    },
+    RevokeDelegation {
+        delegate: Vec<u8>,
+    },
+    // Unilateral close: the latest co-signed state (or the opening state if none)
+    // is paid out once the challenge window has passed
+    StartChannelClose {
+        channel_id: Vec<u8>,
+        balance_proof: Option<BalanceProof>,
+    },
+    ChallengeChannelClose {
+        channel_id: Vec<u8>,
+        balance_proof: BalanceProof, // Must be newer than the pending close
+    },
+    SettleChannel {
+        channel_id: Vec<u8>,
+    },
+    // Asset admin only; mints to the transaction receiver
+    MintAsset {
+        asset_id: Vec<u8>,
+        amount: u64,
+    },
+    // Burns from the sender's own balance
+    BurnAsset {
+        asset_id: Vec<u8>,
+        amount: u64,
+    },
+    SetAssetAdmin {
+        asset_id: Vec<u8>,
+        new_admin: Vec<u8>,
+    },
+    // Pauses transfers, mints and burns of the asset
+    FreezeAsset {
+        asset_id: Vec<u8>,
+    },
+    UnfreezeAsset {
+        asset_id: Vec<u8>,
+    },
+    // Lets the spender pull up to `amount` of the sender's asset (0 revokes)
+    ApproveAsset {
+        asset_id: Vec<u8>,
+        spender: Vec<u8>,
+        amount: u64,
+    },
+    // Sent by the spender; moves the owner's asset to the transaction receiver
+    TransferAssetFrom {
+        asset_id: Vec<u8>,
+        owner: Vec<u8>,
+        amount: u64,
+    },
+    // Approve (or revoke) an operator for one item, or for all of the sender's items if `item_id` is unset
+    ApproveNFT {
+        collection_id: Vec<u8>,
+        item_id: Option<u64>,
+        operator: Vec<u8>,
+        approved: bool,
+    },
+    BurnNFT {
+        collection_id: Vec<u8>,
+        item_id: u64,
+    },
+    // Collection issuer only; `freeze` locks the item's metadata for good
+    UpdateNFTMetadata {
+        collection_id: Vec<u8>,
+        item_id: u64,
+        metadata: Vec<u8>,
+        freeze: bool,
+    },
+    // Escrows `offer` until a taker fills the order by paying `ask`, or the maker cancels it
+    CreateOrder {
+        offer: OrderAsset,
+        ask: OrderAsset,
+        taker: Option<Vec<u8>>, // Only this account may fill the order if set
+        expiry: u64, // Last slot the order can be filled in
+    },
+    FillOrder {
+        order_id: Vec<u8>,
+    },
+    // Maker only; returns the escrowed offer
+    CancelOrder {
+        order_id: Vec<u8>,
+    },
+    // Registers the m-of-n account derived from the signer set and threshold
+    CreateMultisig {
+        signers: Vec<Vec<u8>>,
+        threshold: u32,
+    },
+}
+
+/// One side of an order: native coin, an amount of a fungible asset, or a single NFT
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
+pub enum OrderAsset {
+    Native {
+        amount: u64,
+    },
+    Asset {
+        asset_id: Vec<u8>,
+        amount: u64,
     },
-    UnregisterValidator,
+    NFT {
+        collection_id: Vec<u8>,
+        item_id: u64,
+    },
+}
+
+impl OrderAsset {
+    fn hash_into(&self, hasher: &mut Sha256) {
+        match self {
+            OrderAsset::Native { amount } => {
+                hasher.update(b"Native");
+                hasher.update(amount.to_le_bytes());
+            }
+            OrderAsset::Asset { asset_id, amount } => {
+                hasher.update(b"Asset");
+                hasher.update(asset_id);
+                hasher.update(amount.to_le_bytes());
+            }
+            OrderAsset::NFT { collection_id, item_id } => {
+                hasher.update(b"NFT");
+                hasher.update(collection_id);
+                hasher.update(item_id.to_le_bytes());
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            OrderAsset::Native { amount } | OrderAsset::Asset { amount, .. } if *amount == 0 => {
+                Err("Amount must be greater than 0".to_string())
+            }
+            OrderAsset::Asset { asset_id, .. } if asset_id.is_empty() => {
+                Err("Asset ID cannot be empty".to_string())
+            }
+            OrderAsset::NFT { collection_id, .. } if collection_id.is_empty() => {
+                Err("Collection ID cannot be empty".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Off-chain payment channel state, co-signed by both partners
+/// Every update increases `sequence`; on-chain, the highest sequence wins.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct BalanceProof {
+    pub channel_id: Vec<u8>,
+    pub sequence: u64,
+    pub balance_a: u64,
+    pub balance_b: u64,
+    pub is_final: bool, // Both partners agree to close on this state
+    pub signature_a: Vec<u8>,
+    pub signature_b: Vec<u8>,
+}
+
+impl BalanceProof {
+    /// Unsigned channel state
+    pub fn new(channel_id: Vec<u8>, sequence: u64, balance_a: u64, balance_b: u64, is_final: bool) -> Self {
+        Self { channel_id, sequence, balance_a, balance_b, is_final, signature_a: vec![], signature_b: vec![] }
+    }
+
+    /// Message both partners sign
+    pub fn signing_hash(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"nocostcoin-channel-state");
+        hasher.update(&self.channel_id);
+        hasher.update(self.sequence.to_le_bytes());
+        hasher.update(self.balance_a.to_le_bytes());
+        hasher.update(self.balance_b.to_le_bytes());
+        hasher.update([self.is_final as u8]);
+        hasher.finalize().to_vec()
+    }
+
+    /// Sign as partner A (the opener)
+    pub fn sign_a(&mut self, keypair: &SigningKey) {
+        self.signature_a = keypair.sign(&self.signing_hash()).to_bytes().to_vec();
+    }
+
+    /// Sign as partner B
+    pub fn sign_b(&mut self, keypair: &SigningKey) {
+        self.signature_b = keypair.sign(&self.signing_hash()).to_bytes().to_vec();
+    }
+
+    /// Check both partners' signatures
+    pub fn verify(&self, partner_a: &[u8], partner_b: &[u8]) -> Result<(), String> {
+        self.verify_a(partner_a)?;
+        self.verify_b(partner_b)
+    }
+
+    /// Check partner A's signature (the payer's, for states offered off-chain)
+    pub fn verify_a(&self, partner_a: &[u8]) -> Result<(), String> {
+        Self::verify_partner(&self.signing_hash(), partner_a, &self.signature_a, "A")
+    }
+
+    /// Check partner B's signature
+    pub fn verify_b(&self, partner_b: &[u8]) -> Result<(), String> {
+        Self::verify_partner(&self.signing_hash(), partner_b, &self.signature_b, "B")
+    }
+
+    fn verify_partner(message: &[u8], partner: &[u8], signature: &[u8], name: &str) -> Result<(), String> {
+        let key: [u8; 32] = partner.try_into().map_err(|_| format!("Invalid partner {} key length", name))?;
+        let public_key = VerifyingKey::from_bytes(&key).map_err(|_| format!("Invalid partner {} public key", name))?;
+        let signature: [u8; 64] = signature.try_into().map_err(|_| format!("Invalid partner {} signature length", name))?;
+        public_key
+            .verify(message, &Signature::from_bytes(&signature))
+            .map_err(|_| format!("Invalid partner {} signature on balance proof", name))
+    }
+
+    fn hash_into(&self, hasher: &mut Sha256) {
+        hasher.update(self.signing_hash());
+        hasher.update(&self.signature_a);
+        hasher.update(&self.signature_b);
+    }
+}
+
+/// One signer's signature on a transaction sent from a multisig account
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Cosignature {
+    pub signer: Vec<u8>,
+    pub signature: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
@@ -61,8 +305,12 @@ pub struct Transaction {
     pub sender: Vec<u8>,   // Public key bytes
     pub receiver: Vec<u8>, // Public key bytes (Optional for some types)
     pub nonce: u64,
+    pub fee: u64,          // Paid by the sender to the block producer
+    pub chain_id: u32,     // Network the transaction is valid on (replay protection)
     pub data: TransactionData,
     pub signature: Vec<u8>,
+    pub valid_until_slot: Option<u64>, // Last slot the transaction may be included in
+    pub cosignatures: Vec<Cosignature>, // Used instead of `signature` when the sender is a multisig account
 }
 
 impl Transaction {
@@ -71,22 +319,80 @@ impl Transaction {
         receiver: Vec<u8>, 
         data: TransactionData, 
         nonce: u64, 
+        fee: u64,
+        chain_id: u32,
         keypair: &SigningKey
     ) -> Self {
         let mut tx = Self {
             sender,
             receiver,
             nonce,
+            fee,
+            chain_id,
+            valid_until_slot: None,
             data,
             signature: vec![],
+            cosignatures: vec![],
         };
-        
-        let message = tx.hash();
+        tx.sign(keypair);
+        tx
+    }
+
+    /// Unsigned transaction from a multisig account, to be passed around for `cosign`
+    pub fn new_multisig(
+        multisig: Vec<u8>,
+        receiver: Vec<u8>,
+        data: TransactionData,
+        nonce: u64,
+        fee: u64,
+        chain_id: u32,
+    ) -> Self {
+        Self {
+            sender: multisig,
+            receiver,
+            nonce,
+            fee,
+            chain_id,
+            valid_until_slot: None,
+            data,
+            signature: vec![],
+            cosignatures: vec![],
+        }
+    }
+
+    /// Sign the transaction hash with the sender's key, replacing any previous signature
+    /// Needed again after changing a signed field such as `valid_until_slot`.
+    pub fn sign(&mut self, keypair: &SigningKey) {
+        let message = self.hash();
         // Ed25519 signs the message directly, no context string needed
         let signature = keypair.sign(&message);
-        tx.signature = signature.to_bytes().to_vec();
-        
-        tx
+        self.signature = signature.to_bytes().to_vec();
+    }
+
+    /// Add one signer's approval of a multisig transaction, replacing their previous one
+    pub fn cosign(&mut self, keypair: &SigningKey) {
+        let signer = keypair.verifying_key().to_bytes().to_vec();
+        let signature = keypair.sign(&self.hash()).to_bytes().to_vec();
+        self.cosignatures.retain(|c| c.signer != signer);
+        self.cosignatures.push(Cosignature { signer, signature });
+    }
+
+    /// Account address of the `threshold`-of-n multisig over `signers`, whatever their order
+    pub fn multisig_address(signers: &[Vec<u8>], threshold: u32) -> Vec<u8> {
+        let mut sorted = signers.to_vec();
+        sorted.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(b"nocostcoin-multisig");
+        hasher.update(threshold.to_le_bytes());
+        for signer in &sorted {
+            hasher.update(signer);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    /// Whether the transaction can no longer be included in a block at `slot`
+    pub fn is_expired(&self, slot: u64) -> bool {
+        self.valid_until_slot.is_some_and(|until| slot > until)
     }
 
     /// Create transaction hash for signing
@@ -95,6 +401,12 @@ impl Transaction {
         hasher.update(&self.sender);
         hasher.update(&self.receiver);
         hasher.update(&self.nonce.to_le_bytes());
+        hasher.update(&self.fee.to_le_bytes());
+        hasher.update(self.chain_id.to_le_bytes());
+        if let Some(until) = self.valid_until_slot {
+            hasher.update(b"valid_until_slot");
+            hasher.update(until.to_le_bytes());
+        }
         
         // Hash the data enum
         match &self.data {
@@ -102,24 +414,82 @@ impl Transaction {
                 hasher.update(b"NativeTransfer");
                 hasher.update(amount.to_le_bytes());
             },
-            TransactionData::CreateAsset { name, symbol, supply, decimals, metadata } => {
+            TransactionData::CreateAsset { name, symbol, supply, decimals, metadata, max_supply } => {
                 hasher.update(b"CreateAsset");
+                // Variable-length fields are length-prefixed and the cap is tagged, so no
+                // field's bytes can be re-read as another's under the same signature
+                hasher.update((name.len() as u64).to_le_bytes());
                 hasher.update(name.as_bytes());
+                hasher.update((symbol.len() as u64).to_le_bytes());
                 hasher.update(symbol.as_bytes());
                 hasher.update(supply.to_le_bytes());
                 hasher.update(&[*decimals]);
+                hasher.update((metadata.len() as u64).to_le_bytes());
                 hasher.update(metadata);
+                match max_supply {
+                    Some(max_supply) => {
+                        hasher.update([1]);
+                        hasher.update(max_supply.to_le_bytes());
+                    }
+                    None => hasher.update([0]),
+                }
             },
             TransactionData::TransferAsset { asset_id, amount } => {
                 hasher.update(b"TransferAsset");
                 hasher.update(asset_id);
                 hasher.update(amount.to_le_bytes());
             },
-            TransactionData::CreateCollection { name, symbol, metadata } => {
+            TransactionData::ApproveAsset { asset_id, spender, amount } => {
+                hasher.update(b"ApproveAsset");
+                hasher.update(asset_id);
+                hasher.update(spender);
+                hasher.update(amount.to_le_bytes());
+            },
+            TransactionData::TransferAssetFrom { asset_id, owner, amount } => {
+                hasher.update(b"TransferAssetFrom");
+                hasher.update(asset_id);
+                hasher.update(owner);
+                hasher.update(amount.to_le_bytes());
+            },
+            TransactionData::MintAsset { asset_id, amount } => {
+                hasher.update(b"MintAsset");
+                hasher.update(asset_id);
+                hasher.update(amount.to_le_bytes());
+            },
+            TransactionData::BurnAsset { asset_id, amount } => {
+                hasher.update(b"BurnAsset");
+                hasher.update(asset_id);
+                hasher.update(amount.to_le_bytes());
+            },
+            TransactionData::SetAssetAdmin { asset_id, new_admin } => {
+                hasher.update(b"SetAssetAdmin");
+                hasher.update(asset_id);
+                hasher.update(new_admin);
+            },
+            TransactionData::FreezeAsset { asset_id } => {
+                hasher.update(b"FreezeAsset");
+                hasher.update(asset_id);
+            },
+            TransactionData::UnfreezeAsset { asset_id } => {
+                hasher.update(b"UnfreezeAsset");
+                hasher.update(asset_id);
+            },
+            TransactionData::CreateCollection { name, symbol, metadata, max_items } => {
                 hasher.update(b"CreateCollection");
+                // Length-prefixed and tagged like CreateAsset
+                hasher.update((name.len() as u64).to_le_bytes());
                 hasher.update(name.as_bytes());
+                hasher.update((symbol.len() as u64).to_le_bytes());
                 hasher.update(symbol.as_bytes());
+                hasher.update((metadata.len() as u64).to_le_bytes());
                 hasher.update(metadata);
+                match max_items {
+                    Some(max_items) => {
+                        hasher.update([1]);
+                        hasher.update(max_items.to_le_bytes());
+                    }
+                    None => hasher.update([0]),
+                }
             },
             TransactionData::MintNFT { collection_id, item_id, item_metadata, recipient } => {
                 hasher.update(b"MintNFT");
@@ -132,6 +502,52 @@ impl Transaction {
                 hasher.update(b"TransferNFT");
                 hasher.update(collection_id);
                 hasher.update(item_id.to_le_bytes());
+            },
+            TransactionData::ApproveNFT { collection_id, item_id, operator, approved } => {
+                hasher.update(b"ApproveNFT");
+                hasher.update(collection_id);
+                match item_id {
+                    Some(item_id) => {
+                        hasher.update([1]);
+                        hasher.update(item_id.to_le_bytes());
+                    }
+                    None => hasher.update([0]),
+                }
+                hasher.update(operator);
+                hasher.update([*approved as u8]);
+            },
+            TransactionData::BurnNFT { collection_id, item_id } => {
+                hasher.update(b"BurnNFT");
+                hasher.update(collection_id);
+                hasher.update(item_id.to_le_bytes());
+            },
+            TransactionData::UpdateNFTMetadata { collection_id, item_id, metadata, freeze } => {
+                hasher.update(b"UpdateNFTMetadata");
+                hasher.update(collection_id);
+                hasher.update(item_id.to_le_bytes());
+                hasher.update(metadata);
+                hasher.update([*freeze as u8]);
+            },
+            TransactionData::CreateOrder { offer, ask, taker, expiry } => {
+                hasher.update(b"CreateOrder");
+                offer.hash_into(&mut hasher);
+                ask.hash_into(&mut hasher);
+                match taker {
+                    Some(taker) => {
+                        hasher.update([1]);
+                        hasher.update(taker);
+                    }
+                    None => hasher.update([0]),
+                }
+                hasher.update(expiry.to_le_bytes());
+            },
+            TransactionData::FillOrder { order_id } => {
+                hasher.update(b"FillOrder");
+                hasher.update(order_id);
+            },
+            TransactionData::CancelOrder { order_id } => {
+                hasher.update(b"CancelOrder");
+                hasher.update(order_id);
             },
              TransactionData::OpenChannel { partner, amount, duration } => {
                 hasher.update(b"OpenChannel");
@@ -139,12 +555,30 @@ impl Transaction {
                 hasher.update(amount.to_le_bytes());
                 hasher.update(duration.to_le_bytes());
             },
-            TransactionData::CloseChannel { channel_id, balance_proof, final_balance_a, final_balance_b } => {
+            TransactionData::CloseChannel { channel_id, balance_proof } => {
                 hasher.update(b"CloseChannel");
                 hasher.update(channel_id);
-                hasher.update(balance_proof);
-                hasher.update(final_balance_a.to_le_bytes());
-                hasher.update(final_balance_b.to_le_bytes());
+                balance_proof.hash_into(&mut hasher);
+            },
+            TransactionData::StartChannelClose { channel_id, balance_proof } => {
+                hasher.update(b"StartChannelClose");
+                hasher.update(channel_id);
+                match balance_proof {
+                    Some(proof) => {
+                        hasher.update([1]);
+                        proof.hash_into(&mut hasher);
+                    }
+                    None => hasher.update([0]),
+                }
+            },
+            TransactionData::ChallengeChannelClose { channel_id, balance_proof } => {
+                hasher.update(b"ChallengeChannelClose");
+                hasher.update(channel_id);
+                balance_proof.hash_into(&mut hasher);
+            },
+            TransactionData::SettleChannel { channel_id } => {
+                hasher.update(b"SettleChannel");
+                hasher.update(channel_id);
             },
             TransactionData::DelegateSpend { delegate, allowance, expiry } => {
                 hasher.update(b"DelegateSpend");
@@ -152,12 +586,38 @@ impl Transaction {
                 hasher.update(allowance.to_le_bytes());
                 hasher.update(expiry.to_le_bytes());
             },
-            TransactionData::RegisterValidator { stake } => {
+            TransactionData::DelegatedTransfer { owner, amount } => {
+                hasher.update(b"DelegatedTransfer");
+                hasher.update(owner);
+                hasher.update(amount.to_le_bytes());
+            },
+            TransactionData::RevokeDelegation { delegate } => {
+                hasher.update(b"RevokeDelegation");
+                hasher.update(delegate);
+            },
+            TransactionData::CreateMultisig { signers, threshold } => {
+                hasher.update(b"CreateMultisig");
+                hasher.update((signers.len() as u64).to_le_bytes());
+                for signer in signers {
+                    hasher.update(signer);
+                }
+                hasher.update(threshold.to_le_bytes());
+            },
+            TransactionData::RegisterValidator { stake, validator_pubkey, proof_of_possession } => {
                 hasher.update(b"RegisterValidator");
                 hasher.update(stake.to_le_bytes());
+                hasher.update((validator_pubkey.len() as u64).to_le_bytes());
+                hasher.update(validator_pubkey);
+                hasher.update(proof_of_possession);
             },
-            TransactionData::UnregisterValidator => {
+            TransactionData::UnregisterValidator { validator_pubkey } => {
                 hasher.update(b"UnregisterValidator");
+                hasher.update(validator_pubkey);
+            },
+            TransactionData::SubmitEquivocationEvidence { header_a, header_b } => {
+                hasher.update(b"SubmitEquivocationEvidence");
+                hasher.update(Block::calculate_hash(header_a).as_bytes());
+                hasher.update(Block::calculate_hash(header_b).as_bytes());
             },
 
         }
@@ -189,12 +649,32 @@ impl Transaction {
                 hasher.update(b"channel");
                 Some(hasher.finalize().to_vec())
             },
+            TransactionData::CreateOrder { .. } => {
+                let mut hasher = Sha256::new();
+                hasher.update(&self.sender);
+                hasher.update(self.nonce.to_le_bytes());
+                hasher.update(b"order");
+                Some(hasher.finalize().to_vec())
+            },
             _ => None
         }
     }
 
-    /// Validate transaction signature
-    pub fn validate_signature(&self) -> Result<(), String> {
+    /// Validate transaction signature for the network with `chain_id`
+    /// `multisig` is the stored account of the sender if it is a multisig one;
+    /// its transactions need cosignatures from at least `threshold` of its signers.
+    pub fn validate_signature(&self, chain_id: u32, multisig: Option<&MultisigAccount>) -> Result<(), String> {
+        if self.chain_id != chain_id {
+            return Err(format!("Transaction is for chain {}, expected {}", self.chain_id, chain_id));
+        }
+
+        if let Some(account) = multisig {
+            return self.verify_cosignatures(account);
+        }
+        if !self.cosignatures.is_empty() {
+            return Err("Sender is not a multisig account".to_string());
+        }
+
         if self.signature.is_empty() {
             return Err("Empty signature".to_string());
         }
@@ -217,12 +697,65 @@ impl Transaction {
         Ok(())
     }
 
+    /// Check the cosignatures against the multisig account's signer set
+    fn verify_cosignatures(&self, account: &MultisigAccount) -> Result<(), String> {
+        if account.address != self.sender {
+            return Err("Multisig account does not match sender".to_string());
+        }
+        if !self.signature.is_empty() {
+            return Err("Multisig transactions are signed with cosignatures only".to_string());
+        }
+
+        let message = self.hash();
+        let mut signed: Vec<&Vec<u8>> = Vec::new();
+        for cosignature in &self.cosignatures {
+            if !account.signers.contains(&cosignature.signer) {
+                return Err(format!("Cosigner {} is not a signer of the account", hex::encode(&cosignature.signer)));
+            }
+            if signed.contains(&&cosignature.signer) {
+                return Err(format!("Duplicate cosignature from {}", hex::encode(&cosignature.signer)));
+            }
+
+            let key: [u8; 32] = cosignature.signer.as_slice().try_into().map_err(|_| "Invalid cosigner key length")?;
+            let public_key = VerifyingKey::from_bytes(&key).map_err(|_| "Invalid cosigner public key")?;
+            let signature: [u8; 64] = cosignature.signature.as_slice().try_into().map_err(|_| "Invalid cosignature length")?;
+            public_key
+                .verify(&message, &Signature::from_bytes(&signature))
+                .map_err(|_| format!("Cosignature verification failed for {}", hex::encode(&cosignature.signer)))?;
+            signed.push(&cosignature.signer);
+        }
+
+        if signed.len() < account.threshold as usize {
+            return Err(format!("Multisig needs {} cosignatures, got {}", account.threshold, signed.len()));
+        }
+        Ok(())
+    }
+
+    /// Size of the metadata blob carried by the transaction, if any
+    pub fn metadata_len(&self) -> usize {
+        match &self.data {
+            TransactionData::CreateAsset { metadata, .. } => metadata.len(),
+            TransactionData::CreateCollection { metadata, .. } => metadata.len(),
+            TransactionData::MintNFT { item_metadata, .. } => item_metadata.len(),
+            TransactionData::UpdateNFTMetadata { metadata, .. } => metadata.len(),
+            _ => 0,
+        }
+    }
+
     /// Validate transaction logic (amount, addresses, data)
-    pub fn validate_logic(&self) -> Result<(), String> {
+    pub fn validate_logic(&self, limits: &BlockLimits) -> Result<(), String> {
         if self.sender.is_empty() {
             return Err("Sender address is empty".to_string());
         }
 
+        if self.fee < MIN_TRANSACTION_FEE {
+            return Err(format!("Fee must be at least {}", MIN_TRANSACTION_FEE));
+        }
+
+        if self.metadata_len() as u64 > limits.max_tx_metadata_bytes {
+            return Err(format!("Metadata is {} bytes, limit is {}", self.metadata_len(), limits.max_tx_metadata_bytes));
+        }
+
         match &self.data {
             TransactionData::NativeTransfer { amount } => {
                 if *amount == 0 {
@@ -235,13 +768,75 @@ impl Transaction {
                     return Err("Cannot send to self".to_string());
                 }
             },
-            TransactionData::CreateAsset { name, symbol, supply, .. } => {
+            TransactionData::CreateAsset { name, symbol, supply, max_supply, .. } => {
                 if name.is_empty() || symbol.is_empty() {
                     return Err("Asset name and symbol cannot be empty".to_string());
                 }
                 if *supply == 0 {
                     return Err("Supply must be greater than 0".to_string());
                 }
+                if let Some(max_supply) = max_supply {
+                    if supply > max_supply {
+                        return Err(format!("Supply exceeds max supply of {}", max_supply));
+                    }
+                }
+            },
+            TransactionData::ApproveAsset { asset_id, spender, .. } => {
+                if asset_id.is_empty() {
+                    return Err("Asset ID cannot be empty".to_string());
+                }
+                if spender.is_empty() {
+                    return Err("Spender address is empty".to_string());
+                }
+                if *spender == self.sender {
+                    return Err("Cannot approve self as spender".to_string());
+                }
+            },
+            TransactionData::TransferAssetFrom { asset_id, owner, amount } => {
+                if asset_id.is_empty() {
+                    return Err("Asset ID cannot be empty".to_string());
+                }
+                if *amount == 0 {
+                    return Err("Amount must be greater than 0".to_string());
+                }
+                if self.receiver.is_empty() {
+                    return Err("Receiver address is empty".to_string());
+                }
+                if *owner == self.sender {
+                    return Err("Cannot spend from own account as spender".to_string());
+                }
+            },
+            TransactionData::MintAsset { asset_id, amount } => {
+                if asset_id.is_empty() {
+                    return Err("Asset ID cannot be empty".to_string());
+                }
+                if *amount == 0 {
+                    return Err("Amount must be greater than 0".to_string());
+                }
+                if self.receiver.is_empty() {
+                    return Err("Receiver address is empty".to_string());
+                }
+            },
+            TransactionData::BurnAsset { asset_id, amount } => {
+                if asset_id.is_empty() {
+                    return Err("Asset ID cannot be empty".to_string());
+                }
+                if *amount == 0 {
+                    return Err("Amount must be greater than 0".to_string());
+                }
+            },
+            TransactionData::SetAssetAdmin { asset_id, new_admin } => {
+                if asset_id.is_empty() {
+                    return Err("Asset ID cannot be empty".to_string());
+                }
+                if new_admin.len() != 32 {
+                    return Err("Invalid new admin address".to_string());
+                }
+            },
+            TransactionData::FreezeAsset { asset_id } | TransactionData::UnfreezeAsset { asset_id } => {
+                if asset_id.is_empty() {
+                    return Err("Asset ID cannot be empty".to_string());
+                }
             },
             TransactionData::TransferAsset { asset_id: _, amount } => {
                 if *amount == 0 {
@@ -251,10 +846,13 @@ impl Transaction {
                     return Err("Receiver address is empty".to_string());
                 }
             },
-            TransactionData::CreateCollection { name, symbol, .. } => {
+            TransactionData::CreateCollection { name, symbol, max_items, .. } => {
                 if name.is_empty() || symbol.is_empty() {
                     return Err("Collection name and symbol cannot be empty".to_string());
                 }
+                if *max_items == Some(0) {
+                    return Err("Max items must be greater than 0".to_string());
+                }
             },
             TransactionData::MintNFT { collection_id, .. } => {
                 if collection_id.is_empty() {
@@ -269,15 +867,74 @@ impl Transaction {
                     return Err("Receiver address is empty".to_string());
                 }
             },
-             TransactionData::OpenChannel { partner, amount, .. } => {
+            TransactionData::ApproveNFT { collection_id, operator, .. } => {
+                if collection_id.is_empty() {
+                    return Err("Collection ID cannot be empty".to_string());
+                }
+                if operator.is_empty() {
+                    return Err("Operator address is empty".to_string());
+                }
+                if *operator == self.sender {
+                    return Err("Cannot approve self as operator".to_string());
+                }
+            },
+            TransactionData::BurnNFT { collection_id, .. } | TransactionData::UpdateNFTMetadata { collection_id, .. } => {
+                if collection_id.is_empty() {
+                    return Err("Collection ID cannot be empty".to_string());
+                }
+            },
+            TransactionData::CreateOrder { offer, ask, taker, .. } => {
+                offer.validate()?;
+                ask.validate()?;
+                if matches!((offer, ask), (OrderAsset::Native { .. }, OrderAsset::Native { .. })) {
+                    return Err("Order cannot swap native coin for native coin".to_string());
+                }
+                if offer == ask {
+                    return Err("Order offer and ask are the same".to_string());
+                }
+                if taker.as_ref() == Some(&self.sender) {
+                    return Err("Cannot reserve an order for yourself".to_string());
+                }
+            },
+            TransactionData::FillOrder { order_id } | TransactionData::CancelOrder { order_id } => {
+                if order_id.is_empty() {
+                    return Err("Order ID required".to_string());
+                }
+            },
+             TransactionData::OpenChannel { partner, amount, duration } => {
                 if partner.is_empty() {
                     return Err("Partner address required".to_string());
                 }
                 if *amount == 0 {
                     return Err("Channel must have initial deposit".to_string());
                 }
+                if *duration == 0 {
+                    return Err("Channel duration must be > 0".to_string());
+                }
+            },
+            TransactionData::CloseChannel { channel_id, balance_proof }
+            | TransactionData::ChallengeChannelClose { channel_id, balance_proof } => {
+                if channel_id.is_empty() {
+                    return Err("Channel ID required".to_string());
+                }
+                if &balance_proof.channel_id != channel_id {
+                    return Err("Balance proof is for a different channel".to_string());
+                }
+                if matches!(self.data, TransactionData::CloseChannel { .. }) && !balance_proof.is_final {
+                    return Err("Cooperative close requires a final balance proof".to_string());
+                }
             },
-            TransactionData::CloseChannel { channel_id, .. } => {
+            TransactionData::StartChannelClose { channel_id, balance_proof } => {
+                if channel_id.is_empty() {
+                    return Err("Channel ID required".to_string());
+                }
+                if let Some(proof) = balance_proof {
+                    if &proof.channel_id != channel_id {
+                        return Err("Balance proof is for a different channel".to_string());
+                    }
+                }
+            },
+            TransactionData::SettleChannel { channel_id } => {
                 if channel_id.is_empty() {
                     return Err("Channel ID required".to_string());
                 }
@@ -290,22 +947,92 @@ impl Transaction {
                     return Err("Allowance must be > 0".to_string());
                 }
             },
-            TransactionData::RegisterValidator { stake } => {
+            TransactionData::DelegatedTransfer { owner, amount } => {
+                if owner.is_empty() {
+                    return Err("Owner address required".to_string());
+                }
+                if *owner == self.sender {
+                    return Err("Cannot spend from own account as delegate".to_string());
+                }
+                if *amount == 0 {
+                    return Err("Amount must be greater than 0".to_string());
+                }
+                if self.receiver.is_empty() {
+                    return Err("Receiver address is empty".to_string());
+                }
+            },
+            TransactionData::RevokeDelegation { delegate } => {
+                if delegate.is_empty() {
+                    return Err("Delegate address required".to_string());
+                }
+            },
+            TransactionData::CreateMultisig { signers, threshold } => {
+                if signers.is_empty() || signers.len() > MAX_MULTISIG_SIGNERS {
+                    return Err(format!("Multisig needs between 1 and {} signers", MAX_MULTISIG_SIGNERS));
+                }
+                if signers.iter().any(|signer| signer.len() != 32) {
+                    return Err("Invalid signer address".to_string());
+                }
+                if signers.iter().enumerate().any(|(i, signer)| signers[..i].contains(signer)) {
+                    return Err("Duplicate multisig signer".to_string());
+                }
+                if *threshold == 0 || *threshold as usize > signers.len() {
+                    return Err(format!("Threshold must be between 1 and {}", signers.len()));
+                }
+            },
+            TransactionData::RegisterValidator { stake, validator_pubkey, proof_of_possession } => {
                  if *stake == 0 {
                      return Err("Stake must be > 0".to_string());
                  }
+                 let pubkey = schnorrkel::PublicKey::from_bytes(validator_pubkey)
+                     .map_err(|_| "Invalid validator pubkey".to_string())?;
+                 if !crate::crypto::Crypto::verify_possession(&pubkey, &self.sender, proof_of_possession) {
+                     return Err("Invalid proof of possession".to_string());
+                 }
+            },
+            TransactionData::UnregisterValidator { validator_pubkey } => {
+                if validator_pubkey.is_empty() {
+                    return Err("Validator pubkey required".to_string());
+                }
+            },
+            TransactionData::SubmitEquivocationEvidence { header_a, header_b } => {
+                Self::verify_equivocation(header_a, header_b)?;
             },
-            TransactionData::UnregisterValidator => {},
 
         }
 
         Ok(())
     }
 
+    /// Check that two headers prove their producer signed conflicting blocks for one slot
+    fn verify_equivocation(header_a: &BlockHeader, header_b: &BlockHeader) -> Result<(), String> {
+        if header_a.slot != header_b.slot {
+            return Err("Evidence headers are for different slots".to_string());
+        }
+        if header_a.validator_pubkey != header_b.validator_pubkey {
+            return Err("Evidence headers are from different validators".to_string());
+        }
+        // Producer signatures are randomized, so compare what was signed
+        if Block::signing_hash(header_a) == Block::signing_hash(header_b) {
+            return Err("Evidence headers are identical".to_string());
+        }
+
+        let pubkey = schnorrkel::PublicKey::from_bytes(&header_a.validator_pubkey)
+            .map_err(|_| "Invalid validator pubkey in evidence".to_string())?;
+        for header in [header_a, header_b] {
+            let signing_hash = Block::signing_hash(header);
+            if !crate::crypto::Crypto::verify_block_signature(&pubkey, &signing_hash, &header.producer_signature) {
+                return Err("Invalid producer signature in evidence".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
     /// Full validation (signature + logic)
-    pub fn validate(&self) -> Result<(), String> {
-        self.validate_logic()?;
-        self.validate_signature()?;
+    pub fn validate(&self, chain_id: u32, limits: &BlockLimits, multisig: Option<&MultisigAccount>) -> Result<(), String> {
+        self.validate_logic(limits)?;
+        self.validate_signature(chain_id, multisig)?;
         Ok(())
     }
 }
@@ -315,6 +1042,8 @@ mod tests {
     use super::*;
     use rand::rngs::OsRng;
 
+    const CHAIN_ID: u32 = 1;
+
     #[test]
     fn test_native_transfer() {
         let mut csprng = OsRng;
@@ -327,9 +1056,48 @@ mod tests {
             receiver,
             TransactionData::NativeTransfer { amount: 100 },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
+            &keypair,
+        );
+        assert!(tx.validate(CHAIN_ID, &BlockLimits::default(), None).is_ok());
+    }
+
+    #[test]
+    fn test_fee_below_minimum_rejected() {
+        let mut csprng = OsRng;
+        let keypair = SigningKey::generate(&mut csprng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+
+        let tx = Transaction::new(
+            sender,
+            vec![4, 5, 6],
+            TransactionData::NativeTransfer { amount: 100 },
+            0,
+            0,
+            CHAIN_ID,
             &keypair,
         );
-        assert!(tx.validate().is_ok());
+        assert!(tx.validate(CHAIN_ID, &BlockLimits::default(), None).is_err());
+    }
+
+    #[test]
+    fn test_fee_covered_by_signature() {
+        let mut csprng = OsRng;
+        let keypair = SigningKey::generate(&mut csprng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+
+        let mut tx = Transaction::new(
+            sender,
+            vec![4, 5, 6],
+            TransactionData::NativeTransfer { amount: 100 },
+            0,
+            5,
+            CHAIN_ID,
+            &keypair,
+        );
+        tx.fee = 50;
+        assert!(tx.validate_signature(CHAIN_ID, None).is_err());
     }
 
     #[test]
@@ -346,14 +1114,230 @@ mod tests {
                 symbol: "GLD".to_string(), 
                 supply: 1000, 
                 decimals: 8, 
-                metadata: vec![] 
+                metadata: vec![],
+                max_supply: None,
             },
             0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
             &keypair,
         );
-        assert!(tx.validate().is_ok());
+        assert!(tx.validate(CHAIN_ID, &BlockLimits::default(), None).is_ok());
         assert!(tx.calculate_asset_id().is_some());
     }
-}
 
+    #[test]
+    fn test_create_asset_hash_not_malleable() {
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        let create = |metadata: Vec<u8>, max_supply| TransactionData::CreateAsset {
+            name: "Gold".to_string(),
+            symbol: "GLD".to_string(),
+            supply: 1000,
+            decimals: 8,
+            metadata,
+            max_supply,
+        };
+        let tx = Transaction::new(sender, vec![], create(b"gold".to_vec(), Some(5000)), 0, MIN_TRANSACTION_FEE, CHAIN_ID, &keypair);
+        assert!(tx.validate_signature(CHAIN_ID, None).is_ok());
+
+        // The cap moved into the metadata must not carry the same signature
+        let mut stripped = tx.clone();
+        let mut metadata = b"gold".to_vec();
+        metadata.extend_from_slice(b"max_supply");
+        metadata.extend_from_slice(&5000u64.to_le_bytes());
+        stripped.data = create(metadata, None);
+        assert_eq!(stripped.validate_signature(CHAIN_ID, None).err().unwrap(), "Signature verification failed");
+    }
+
+    #[test]
+    fn test_create_collection_hash_not_malleable() {
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        let create = |metadata: Vec<u8>, max_items| TransactionData::CreateCollection {
+            name: "Punks".to_string(),
+            symbol: "PNK".to_string(),
+            metadata,
+            max_items,
+        };
+        let tx = Transaction::new(sender, vec![], create(b"punks".to_vec(), Some(100)), 0, MIN_TRANSACTION_FEE, CHAIN_ID, &keypair);
+        assert!(tx.validate_signature(CHAIN_ID, None).is_ok());
+
+        // The cap moved into the metadata must not carry the same signature
+        let mut stripped = tx.clone();
+        let mut metadata = b"punks".to_vec();
+        metadata.extend_from_slice(b"max_items");
+        metadata.extend_from_slice(&100u64.to_le_bytes());
+        stripped.data = create(metadata, None);
+        assert_eq!(stripped.validate_signature(CHAIN_ID, None).err().unwrap(), "Signature verification failed");
+    }
+
+    #[test]
+    fn test_chain_id_replay_protection() {
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+
+        let tx = Transaction::new(sender, vec![4, 5, 6], TransactionData::NativeTransfer { amount: 100 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &keypair);
+        assert!(tx.validate_signature(CHAIN_ID, None).is_ok());
+        assert_eq!(tx.validate_signature(2, None).err().unwrap(), "Transaction is for chain 1, expected 2");
+
+        // Relabelling the chain ID invalidates the signature
+        let mut replayed = tx.clone();
+        replayed.chain_id = 2;
+        assert_eq!(replayed.validate_signature(2, None).err().unwrap(), "Signature verification failed");
+    }
+
+    #[test]
+    fn test_register_validator_proof_of_possession() {
+        let wallet = SigningKey::generate(&mut OsRng);
+        let sender = wallet.verifying_key().to_bytes().to_vec();
+        let validator = crate::crypto::Crypto::generate_keypair();
+        let register = |proof_of_possession: Vec<u8>| {
+            let data = TransactionData::RegisterValidator { stake: 2000, validator_pubkey: validator.public.to_bytes().to_vec(), proof_of_possession };
+            Transaction::new(sender.clone(), vec![], data, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &wallet)
+        };
+
+        let proof = crate::crypto::Crypto::prove_possession(&validator, &sender);
+        assert!(register(proof).validate(CHAIN_ID, &BlockLimits::default(), None).is_ok());
+
+        // A proof made for another account, or by another key, does not bond the key
+        let other_account = crate::crypto::Crypto::prove_possession(&validator, &[9; 32]);
+        let other_key = crate::crypto::Crypto::prove_possession(&crate::crypto::Crypto::generate_keypair(), &sender);
+        for proof in [other_account, other_key] {
+            assert_eq!(register(proof).validate_logic(&BlockLimits::default()).err().unwrap(), "Invalid proof of possession");
+        }
+    }
+
+    #[test]
+    fn test_metadata_size_limit() {
+        let keypair = SigningKey::generate(&mut OsRng);
+        let sender = keypair.verifying_key().to_bytes().to_vec();
+        let limits = BlockLimits { max_tx_metadata_bytes: 8, ..BlockLimits::default() };
+
+        let mint = |item_metadata: Vec<u8>| Transaction::new(
+            sender.clone(),
+            vec![],
+            TransactionData::MintNFT { collection_id: vec![1], item_id: 1, item_metadata, recipient: vec![2] },
+            0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
+            &keypair,
+        );
+        assert!(mint(vec![0; 8]).validate(CHAIN_ID, &limits, None).is_ok());
+        assert_eq!(mint(vec![0; 9]).validate(CHAIN_ID, &limits, None).err().unwrap(), "Metadata is 9 bytes, limit is 8");
+    }
+
+    #[test]
+    fn test_multisig_cosignatures() {
+        let keys: Vec<SigningKey> = (0..3).map(|_| SigningKey::generate(&mut OsRng)).collect();
+        let signers: Vec<Vec<u8>> = keys.iter().map(|k| k.verifying_key().to_bytes().to_vec()).collect();
+        let address = Transaction::multisig_address(&signers, 2);
+        let mut sorted = signers.clone();
+        sorted.sort();
+        let account = MultisigAccount { address: address.clone(), signers: sorted, threshold: 2 };
 
+        let mut tx = Transaction::new_multisig(address.clone(), vec![4, 5, 6], TransactionData::NativeTransfer { amount: 100 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID);
+        tx.cosign(&keys[0]);
+        tx.cosign(&keys[0]); // Re-signing replaces, it does not count twice
+        assert_eq!(tx.validate_signature(CHAIN_ID, Some(&account)).err().unwrap(), "Multisig needs 2 cosignatures, got 1");
+        tx.cosign(&keys[1]);
+        assert!(tx.validate_signature(CHAIN_ID, Some(&account)).is_ok());
+
+        // Cosignatures cover the transaction hash
+        let mut tampered = tx.clone();
+        tampered.fee = 50;
+        assert!(tampered.validate_signature(CHAIN_ID, Some(&account)).is_err());
+
+        // Only the stored signers count, each at most once
+        let outsider = SigningKey::generate(&mut OsRng);
+        let mut forged = Transaction::new_multisig(address.clone(), vec![4, 5, 6], TransactionData::NativeTransfer { amount: 100 }, 0, MIN_TRANSACTION_FEE, CHAIN_ID);
+        forged.cosign(&keys[0]);
+        forged.cosign(&outsider);
+        assert!(forged.validate_signature(CHAIN_ID, Some(&account)).err().unwrap().contains("is not a signer"));
+        let mut duplicated = tx.clone();
+        duplicated.cosignatures[1] = duplicated.cosignatures[0].clone();
+        assert!(duplicated.validate_signature(CHAIN_ID, Some(&account)).err().unwrap().starts_with("Duplicate cosignature"));
+
+        // A multisig sender cannot fall back to a single signature, nor can a normal account use cosignatures
+        let mut single = tx.clone();
+        single.sign(&keys[0]);
+        assert_eq!(single.validate_signature(CHAIN_ID, Some(&account)).err().unwrap(), "Multisig transactions are signed with cosignatures only");
+        assert_eq!(tx.validate_signature(CHAIN_ID, None).err().unwrap(), "Sender is not a multisig account");
+
+        let create = |threshold| Transaction::new(signers[0].clone(), vec![], TransactionData::CreateMultisig { signers: signers.clone(), threshold }, 0, MIN_TRANSACTION_FEE, CHAIN_ID, &keys[0]);
+        assert!(create(3).validate(CHAIN_ID, &BlockLimits::default(), None).is_ok());
+        assert_eq!(create(4).validate(CHAIN_ID, &BlockLimits::default(), None).err().unwrap(), "Threshold must be between 1 and 3");
+    }
+
+    #[test]
+    fn test_balance_proof_signatures() {
+        let key_a = SigningKey::generate(&mut OsRng);
+        let key_b = SigningKey::generate(&mut OsRng);
+        let partner_a = key_a.verifying_key().to_bytes().to_vec();
+        let partner_b = key_b.verifying_key().to_bytes().to_vec();
+
+        let mut proof = BalanceProof::new(vec![1; 32], 3, 60, 40, false);
+        proof.sign_a(&key_a);
+        proof.sign_b(&key_b);
+        assert!(proof.verify(&partner_a, &partner_b).is_ok());
+
+        // Signatures cover the split and the sequence
+        let mut tampered = proof.clone();
+        tampered.balance_a = 0;
+        tampered.balance_b = 100;
+        assert_eq!(tampered.verify(&partner_a, &partner_b).err().unwrap(), "Invalid partner A signature on balance proof");
+
+        // Each partner must sign with their own key
+        assert!(proof.verify(&partner_b, &partner_a).is_err());
+    }
+
+    #[test]
+    fn test_equivocation_evidence_validation() {
+        use crate::block::BlockHeader;
+
+        let producer = crate::crypto::Crypto::generate_keypair();
+        let signed_header = |timestamp: i64, slot: u64| {
+            let mut block = Block::new(BlockHeader {
+                parent_hash: "parent".to_string(),
+                slot,
+                epoch: 0,
+                vrf_output: vec![],
+                vrf_proof: vec![],
+                validator_pubkey: producer.public.to_bytes().to_vec(),
+                producer_signature: vec![],
+                state_root: "".to_string(),
+                tx_root: "".to_string(),
+                extra_witnesses: vec![],
+                timestamp,
+            }, vec![]);
+            block.sign(&producer);
+            block.header
+        };
+
+        let reporter = SigningKey::generate(&mut OsRng);
+        let sender = reporter.verifying_key().to_bytes().to_vec();
+        let evidence = |header_a, header_b| Transaction::new(
+            sender.clone(),
+            vec![],
+            TransactionData::SubmitEquivocationEvidence { header_a: Box::new(header_a), header_b: Box::new(header_b) },
+            0,
+            MIN_TRANSACTION_FEE,
+            CHAIN_ID,
+            &reporter,
+        );
+
+        assert!(evidence(signed_header(1, 5), signed_header(2, 5)).validate(CHAIN_ID, &BlockLimits::default(), None).is_ok());
+        let header = signed_header(1, 5);
+        assert_eq!(evidence(header.clone(), header).validate(CHAIN_ID, &BlockLimits::default(), None).err().unwrap(), "Evidence headers are identical");
+
+        // The same header signed twice is not a conflict, even though the signatures differ
+        let (resigned_a, resigned_b) = (signed_header(1, 5), signed_header(1, 5));
+        assert_ne!(resigned_a.producer_signature, resigned_b.producer_signature);
+        assert_eq!(evidence(resigned_a, resigned_b).validate(CHAIN_ID, &BlockLimits::default(), None).err().unwrap(), "Evidence headers are identical");
+        assert_eq!(evidence(signed_header(1, 5), signed_header(1, 6)).validate(CHAIN_ID, &BlockLimits::default(), None).err().unwrap(), "Evidence headers are for different slots");
+
+        let mut forged = signed_header(2, 5);
+        forged.timestamp = 3;
+        assert_eq!(evidence(signed_header(1, 5), forged).validate(CHAIN_ID, &BlockLimits::default(), None).err().unwrap(), "Invalid producer signature in evidence");
+    }
+}
@@ -54,8 +54,20 @@ impl MerklePatriciaTrie {
     }
 
     /// Get the current root hash
+    /// An empty trie hashes to SHA-256 of the empty string so roots are never blank
     pub fn root(&self) -> String {
-        self.root.clone().unwrap_or_else(|| String::from(""))
+        self.root.clone().unwrap_or_else(|| hex::encode(Sha256::digest([])))
+    }
+
+    /// Root node reference, used to roll back speculative inserts
+    pub(crate) fn root_ref(&self) -> Option<String> {
+        self.root.clone()
+    }
+
+    /// Reset the root to a previously captured reference
+    /// Nodes are content-addressed, so older roots stay resolvable
+    pub(crate) fn reset_root(&mut self, root: Option<String>) {
+        self.root = root;
     }
 
     /// Convert bytes to nibbles (4-bit values)
@@ -104,22 +116,18 @@ impl MerklePatriciaTrie {
                     Some(Node::Branch { mut children, value: branch_value }) => {
                         let nibble = path[0] as usize;
                         let rest = path[1..].to_vec();
-                        
-                        if rest.is_empty() {
-                            // Insert value at this branch
-                            let new_branch = Node::Branch { children, value: Some(value) };
-                            let new_hash = new_branch.hash();
-                            self.nodes.insert(new_hash.clone(), new_branch);
-                            new_hash
-                        } else {
-                            // Recurse into child
-                            let child_hash = self.insert_at(children[nibble].clone(), rest, value);
-                            children[nibble] = Some(child_hash);
-                            let new_branch = Node::Branch { children, value: branch_value };
-                            let new_hash = new_branch.hash();
-                            self.nodes.insert(new_hash.clone(), new_branch);
-                            new_hash
-                        }
+
+                        // Recurse into child; a key ending on this nibble lives in an
+                        // empty-key leaf, the same shape split_leaf produces and get_at reads.
+                        // It used to be written into the branch's own value slot, which lost
+                        // updates and made the root depend on insertion order. Roots of such
+                        // tries changed with the fix, so older databases must be resynced.
+                        let child_hash = self.insert_at(children[nibble].clone(), rest, value);
+                        children[nibble] = Some(child_hash);
+                        let new_branch = Node::Branch { children, value: branch_value };
+                        let new_hash = new_branch.hash();
+                        self.nodes.insert(new_hash.clone(), new_branch);
+                        new_hash
                     }
                     Some(Node::Extension { path: ext_path, child }) => {
                         let common = Self::common_prefix(&ext_path, &path);
@@ -365,4 +373,40 @@ mod tests {
         assert_ne!(root1, root2);
         assert_eq!(trie.get(&[1, 2, 3]), Some(vec![200]));
     }
+
+    #[test]
+    fn test_update_sibling_of_last_nibble_split() {
+        let mut trie = MerklePatriciaTrie::new();
+        trie.insert(vec![1, 2, 3], vec![100]);
+        trie.insert(vec![1, 2, 4], vec![200]);
+        trie.insert(vec![1, 2, 3], vec![150]);
+
+        assert_eq!(trie.get(&[1, 2, 3]), Some(vec![150]));
+
+        // Updating in place matches building the final state from scratch
+        let mut fresh = MerklePatriciaTrie::new();
+        fresh.insert(vec![1, 2, 3], vec![150]);
+        fresh.insert(vec![1, 2, 4], vec![200]);
+        assert_eq!(trie.root(), fresh.root());
+    }
+
+    #[test]
+    fn test_sibling_keys_ending_on_branch_nibble() {
+        // Both keys end on a nibble of the same branch and are updated in turn
+        let mut trie = MerklePatriciaTrie::new();
+        trie.insert(vec![1, 2, 3], vec![100]);
+        trie.insert(vec![1, 2, 4], vec![200]);
+        trie.insert(vec![1, 2, 3], vec![150]);
+        trie.insert(vec![1, 2, 4], vec![250]);
+
+        // Neither update overwrites the other
+        assert_eq!(trie.get(&[1, 2, 3]), Some(vec![150]));
+        assert_eq!(trie.get(&[1, 2, 4]), Some(vec![250]));
+
+        // The root only depends on the final contents, not the insertion order
+        let mut fresh = MerklePatriciaTrie::new();
+        fresh.insert(vec![1, 2, 4], vec![250]);
+        fresh.insert(vec![1, 2, 3], vec![150]);
+        assert_eq!(trie.root(), fresh.root());
+    }
 }
@@ -2,30 +2,142 @@ use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 
 pub const MIN_STAKE: u64 = 1000;
+/// Epochs withdrawn stake stays locked (and slashable) after a validator exits
+pub const DEFAULT_UNBONDING_EPOCHS: u64 = 24;
+/// Share of a slashed stake paid to whoever submits the evidence; the rest is burned
+pub const EQUIVOCATION_REPORTER_REWARD_PERCENT: u64 = 10;
+/// Active slot coefficient `f` is given in basis points (10000 = every slot has a leader)
+pub const ACTIVE_SLOT_COEFF_SCALE: u64 = 10_000;
+
+// Fixed-point arithmetic for the leader threshold: values are scaled by 2^FRAC_BITS
+const FRAC_BITS: u32 = 60;
+const FIXED_ONE: i128 = 1 << FRAC_BITS;
+const FIXED_LN2: i128 = 799_144_290_325_165_978; // ln(2) * 2^60
+
+fn fixed_mul(a: i128, b: i128) -> i128 {
+    (a * b) >> FRAC_BITS
+}
+
+fn fixed_div(a: i128, b: i128) -> i128 {
+    (a << FRAC_BITS) / b
+}
+
+/// Natural log of a fixed-point value in (0, 1]
+fn fixed_ln(x: i128) -> i128 {
+    // Range reduction: x = m * 2^-e with m in [1, 2)
+    let mut m = x;
+    let mut e = 0;
+    while m < FIXED_ONE {
+        m <<= 1;
+        e += 1;
+    }
+
+    // ln(m) = 2 * atanh(z) with z = (m - 1) / (m + 1) in [0, 1/3)
+    let z = fixed_div(m - FIXED_ONE, m + FIXED_ONE);
+    let z2 = fixed_mul(z, z);
+    let mut term = z;
+    let mut sum = 0;
+    let mut k = 1;
+    while term != 0 {
+        sum += term / k;
+        term = fixed_mul(term, z2);
+        k += 2;
+    }
+
+    2 * sum - e * FIXED_LN2
+}
+
+/// e^y of a non-positive fixed-point value
+fn fixed_exp(y: i128) -> i128 {
+    // Range reduction: y = r - k * ln(2) with r in (-ln(2), 0]
+    let k = -y / FIXED_LN2;
+    let r = y + k * FIXED_LN2;
+
+    // Taylor series for e^r
+    let mut term = FIXED_ONE;
+    let mut sum = FIXED_ONE;
+    let mut n = 1;
+    while term != 0 {
+        term = fixed_mul(term, r) / n;
+        sum += term;
+        n += 1;
+    }
+
+    if k >= FRAC_BITS as i128 {
+        0
+    } else {
+        sum >> k
+    }
+}
+
+/// Praos leader threshold `phi(sigma) = 1 - (1 - f)^sigma` in units of 2^-64
+/// A VRF value (first 8 bytes, little endian) below the threshold wins the slot.
+/// Computed in fixed point only, so every node agrees on it bit for bit.
+pub fn leader_threshold(stake: u64, total_stake: u64, active_slot_coeff_bps: u64) -> u128 {
+    if stake == 0 || total_stake == 0 || active_slot_coeff_bps == 0 {
+        return 0;
+    }
+    if active_slot_coeff_bps >= ACTIVE_SLOT_COEFF_SCALE {
+        return 1 << 64;
+    }
+
+    let sigma = fixed_div(stake as i128, total_stake as i128).min(FIXED_ONE);
+    let one_minus_f = fixed_div((ACTIVE_SLOT_COEFF_SCALE - active_slot_coeff_bps) as i128, ACTIVE_SLOT_COEFF_SCALE as i128);
+    let phi = FIXED_ONE - fixed_exp(fixed_mul(sigma, fixed_ln(one_minus_f)));
+
+    (phi.max(0) as u128) << (64 - FRAC_BITS)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ValidatorInfo {
     pub pubkey: Vec<u8>,
     pub stake: u64,
-    pub registered_epoch: u64,
+    pub registered_epoch: u64, // First epoch the validator is active in
     pub slashed: bool,
+    pub exit_epoch: Option<u64>, // First epoch the validator is no longer active in
+    pub withdrawable_epoch: u64, // Epoch the unbonding stake is returned
+    pub reward_address: Vec<u8>, // Ed25519 account paid the block rewards and fees
 }
 
 impl ValidatorInfo {
+    /// Validator paid at its own key, which must then be an Ed25519 account key
     pub fn new(pubkey: Vec<u8>, stake: u64, epoch: u64) -> Self {
         Self {
+            reward_address: pubkey.clone(),
             pubkey,
             stake,
             registered_epoch: epoch,
             slashed: false,
+            exit_epoch: None,
+            withdrawable_epoch: 0,
         }
     }
+
+    /// Pay rewards to a separate account; needed when `pubkey` is a schnorrkel block-signing key
+    pub fn with_reward_address(mut self, reward_address: Vec<u8>) -> Self {
+        self.reward_address = reward_address;
+        self
+    }
+
+    /// Whether the validator takes part in consensus during `epoch`
+    pub fn is_active_at(&self, epoch: u64) -> bool {
+        !self.slashed
+            && self.stake > 0
+            && self.registered_epoch <= epoch
+            && self.exit_epoch.is_none_or(|exit| epoch < exit)
+    }
+
+    /// Whether the validator has exited and is waiting for its stake
+    pub fn is_unbonding(&self) -> bool {
+        self.exit_epoch.is_some() && self.stake > 0 && !self.slashed
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidatorSet {
     validators: HashMap<Vec<u8>, ValidatorInfo>, // pubkey -> ValidatorInfo
-    total_stake: u64,
+    total_stake: u64, // Stake of validators active in `epoch`
+    epoch: u64,
 }
 
 impl ValidatorSet {
@@ -33,7 +145,32 @@ impl ValidatorSet {
         Self {
             validators: HashMap::new(),
             total_stake: 0,
+            epoch: 0,
+        }
+    }
+
+    /// Build the set for `epoch` from stored validator records
+    /// Records with no stake (withdrawn) are left out. Pending, unbonding and
+    /// slashed validators are kept so they can still be slashed, but only
+    /// validators active in `epoch` count towards the total stake.
+    pub fn from_validators(records: Vec<ValidatorInfo>, epoch: u64) -> Self {
+        let mut set = Self::new();
+        set.epoch = epoch;
+        for validator in records {
+            if validator.stake == 0 && !validator.slashed {
+                continue;
+            }
+            if validator.is_active_at(epoch) {
+                set.total_stake += validator.stake;
+            }
+            set.validators.insert(validator.pubkey.clone(), validator);
         }
+        set
+    }
+
+    /// Epoch this set was built for
+    pub fn epoch(&self) -> u64 {
+        self.epoch
     }
 
     /// Register a new validator with stake
@@ -47,8 +184,10 @@ impl ValidatorSet {
         }
 
         let validator = ValidatorInfo::new(pubkey.clone(), stake, epoch);
+        if validator.is_active_at(self.epoch) {
+            self.total_stake += stake;
+        }
         self.validators.insert(pubkey, validator);
-        self.total_stake += stake;
 
         Ok(())
     }
@@ -59,7 +198,9 @@ impl ValidatorSet {
         let validator = self.validators.remove(pubkey)
             .ok_or("Validator not found")?;
         
-        self.total_stake -= validator.stake;
+        if validator.is_active_at(self.epoch) {
+            self.total_stake -= validator.stake;
+        }
         Ok(validator.stake)
     }
 
@@ -72,11 +213,13 @@ impl ValidatorSet {
             return Err("Validator already slashed".to_string());
         }
 
+        // Remove stake from total
+        if validator.is_active_at(self.epoch) {
+            self.total_stake -= validator.stake;
+        }
+
         validator.slashed = true;
         let slashed_amount = validator.stake;
-        
-        // Remove stake from total
-        self.total_stake -= validator.stake;
         validator.stake = 0;
         
         println!("Validator {:?} slashed for {} stake", pubkey, slashed_amount);
@@ -89,10 +232,10 @@ impl ValidatorSet {
         self.validators.get(pubkey)
     }
 
-    /// Check if address is a validator
+    /// Check if address is a validator active in this set's epoch
     #[allow(dead_code)]
     pub fn is_validator(&self, pubkey: &[u8]) -> bool {
-        self.validators.contains_key(pubkey)
+        self.validators.get(pubkey).is_some_and(|v| v.is_active_at(self.epoch))
     }
 
     /// Get total stake
@@ -107,45 +250,30 @@ impl ValidatorSet {
     }
 
     /// Calculate if a VRF output wins leadership for a slot
-    /// Uses stake-weighted probability
-    /// Calculate if a VRF output wins leadership for a slot
-    /// Uses stake-weighted probability
-    pub fn is_slot_leader(&self, pubkey: &[u8], vrf_output: &[u8]) -> bool {
+    /// Uses the stake-weighted Praos threshold for active slot coefficient `f`
+    pub fn is_slot_leader(&self, pubkey: &[u8], vrf_output: &[u8], active_slot_coeff_bps: u64) -> bool {
         let validator = match self.get_validator(pubkey) {
-            Some(v) => v,
-            None => return false,
+            Some(v) if v.is_active_at(self.epoch) => v,
+            _ => return false,
         };
 
-        if self.total_stake == 0 {
+        if self.total_stake == 0 || vrf_output.is_empty() {
             return false;
         }
 
-        // Calculate threshold based on stake proportion
         // Higher stake = higher chance of being selected
-        let stake_ratio = validator.stake as f64 / self.total_stake as f64;
-        
-        // Convert VRF output to a value between 0 and 1
-        let vrf_value = self.vrf_to_probability(vrf_output);
-        
-        // Validator wins if VRF value < stake ratio
-        vrf_value < stake_ratio
-    }
+        let threshold = leader_threshold(validator.stake, self.total_stake, active_slot_coeff_bps);
 
-    /// Convert VRF output bytes to probability [0, 1]
-    /// Convert VRF output bytes to probability [0, 1]
-    fn vrf_to_probability(&self, vrf_output: &[u8]) -> f64 {
-        if vrf_output.is_empty() {
-            return 1.0;
-        }
+        // Validator wins if VRF value < threshold
+        (Self::vrf_to_u64(vrf_output) as u128) < threshold
+    }
 
-        // Take first 8 bytes and convert to u64
+    /// Take the first 8 bytes of a VRF output as a little endian integer
+    fn vrf_to_u64(vrf_output: &[u8]) -> u64 {
         let mut bytes = [0u8; 8];
         let len = vrf_output.len().min(8);
         bytes[..len].copy_from_slice(&vrf_output[..len]);
-        let value = u64::from_le_bytes(bytes);
-        
-        // Normalize to [0, 1]
-        value as f64 / u64::MAX as f64
+        u64::from_le_bytes(bytes)
     }
 
     /// Get all validators for an epoch
@@ -153,7 +281,7 @@ impl ValidatorSet {
     pub fn get_validators_for_epoch(&self, epoch: u64) -> Vec<ValidatorInfo> {
         self.validators
             .values()
-            .filter(|v| v.registered_epoch <= epoch)
+            .filter(|v| v.is_active_at(epoch))
             .cloned()
             .collect()
     }
@@ -212,4 +340,57 @@ mod tests {
         set.register_validator(pubkey.clone(), 2000, 0).unwrap();
         assert!(set.is_validator(&pubkey));
     }
+
+    #[test]
+    fn test_epoch_activation() {
+        let mut pending = ValidatorInfo::new(vec![1], 2000, 5);
+        let active = ValidatorInfo::new(vec![2], 3000, 0);
+        assert!(!pending.is_active_at(4));
+        assert!(pending.is_active_at(5));
+
+        let set = ValidatorSet::from_validators(vec![pending.clone(), active.clone()], 4);
+        assert!(!set.is_validator(&[1]));
+        assert!(set.is_validator(&[2]));
+        assert_eq!(set.get_total_stake(), 3000);
+
+        // Exited validators leave the active set but stay slashable while unbonding
+        pending.exit_epoch = Some(6);
+        let mut set = ValidatorSet::from_validators(vec![pending, active], 6);
+        assert!(!set.is_validator(&[1]));
+        assert_eq!(set.get_total_stake(), 3000);
+        assert_eq!(set.slash_validator(&[1]).unwrap(), 2000);
+        assert_eq!(set.get_total_stake(), 3000);
+    }
+
+    #[test]
+    fn test_leader_threshold_fixed_point() {
+        let scale = 2f64.powi(64);
+        for (stake, total, bps) in [(1, 2, 5000), (1, 10, 500), (3, 4, 9999), (1, 1_000_000, 100), (7, 7, 2500)] {
+            let f = bps as f64 / 10_000.0;
+            let sigma = stake as f64 / total as f64;
+            let expected = 1.0 - (1.0 - f).powf(sigma);
+            let got = leader_threshold(stake, total, bps) as f64 / scale;
+            assert!((got - expected).abs() < 1e-12, "stake {}/{} f {}: {} vs {}", stake, total, f, got, expected);
+        }
+
+        // A sole validator leads a fraction f of slots; f = 1 means every slot
+        assert_eq!(leader_threshold(5, 5, 10_000), 1 << 64);
+        assert_eq!(leader_threshold(0, 5, 5000), 0);
+        assert_eq!(leader_threshold(5, 5, 0), 0);
+    }
+
+    #[test]
+    fn test_is_slot_leader_threshold() {
+        let mut set = ValidatorSet::new();
+        set.register_validator(vec![1], 2000, 0).unwrap();
+        set.register_validator(vec![2], 2000, 0).unwrap();
+
+        // Half the stake at f = 0.5: threshold is 1 - 0.5^0.5 ~ 0.2929
+        let below = ((0.29 * 2f64.powi(64)) as u64).to_le_bytes();
+        let above = ((0.30 * 2f64.powi(64)) as u64).to_le_bytes();
+        assert!(set.is_slot_leader(&[1], &below, 5000));
+        assert!(!set.is_slot_leader(&[1], &above, 5000));
+        assert!(!set.is_slot_leader(&[3], &below, 5000));
+        assert!(!set.is_slot_leader(&[1], &[], 5000));
+    }
 }
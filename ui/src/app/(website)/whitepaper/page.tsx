@@ -118,9 +118,10 @@ export default function Whitepaper() {
                             <p className="mt-2"><strong>Solution</strong>: Off-chain payment channels.</p>
                             <ol className="list-decimal pl-6 space-y-2 mt-2">
                                 <li><code>OpenChannel</code>: Lock funds on-chain</li>
-                                <li>Exchange signed balance updates off-chain (millisecond latency)</li>
-                                <li><code>CloseChannel</code>: Settle final state on-chain</li>
+                                <li>Exchange balance proofs off-chain (millisecond latency), co-signed by both partners with an increasing sequence number</li>
+                                <li><code>CloseChannel</code>: Settle a final co-signed proof on-chain</li>
                             </ol>
+                            <p className="mt-2">If a partner stops responding, the other can close alone with <code>StartChannelClose</code>. The counterparty then has a challenge window (about 30 minutes by default) to submit a newer proof with <code>ChallengeChannelClose</code> before <code>SettleChannel</code> releases the funds.</p>
 
                             <h3 className="text-2xl font-semibold text-white mb-4 mt-8">4.4 Delegated Spending (AI Agent Wallets)</h3>
                             <p><strong>Problem</strong>: You want an AI agent to trade for you but don&apos;t want to give it your private key.</p>
@@ -135,6 +136,34 @@ export default function Whitepaper() {
 }`}
                             </pre>
                             <p className="mt-4">The protocol enforces the limit. If the agent is compromised, you only lose 100 tokens.</p>
+
+                            <h3 className="text-2xl font-semibold text-white mb-4 mt-8">4.5 Atomic Swaps</h3>
+                            <p><strong>Problem</strong>: Trading coins, assets or NFTs between two parties takes two transactions, and whoever pays first has to trust the other.</p>
+                            <p className="mt-2"><strong>Solution</strong>: On-chain escrow orders.</p>
+                            <pre className="bg-slate-900 p-4 rounded-lg overflow-x-auto text-sm text-slate-300 mt-4">
+                                {`Transaction {
+    data: CreateOrder {
+        offer: OrderAsset::NFT { collection_id, item_id: 7 },
+        ask: OrderAsset::Asset { asset_id: usdn, amount: 250 },
+        taker: None,              // or Some(pubkey) to reserve the order
+        expiry: current_slot + 14400, // last slot it can be filled in
+    }
+}`}
+                            </pre>
+                            <p className="mt-4"><code>CreateOrder</code> escrows the offer. <code>FillOrder</code> pays the ask to the maker and the offer to the taker in one transaction, so both legs settle or neither does. The maker can <code>CancelOrder</code> before a fill to get the offer back.</p>
+
+                            <h3 className="text-2xl font-semibold text-white mb-4 mt-8">4.6 Multisignature Accounts</h3>
+                            <p><strong>Problem</strong>: A treasury held by one key is lost or stolen with that key.</p>
+                            <p className="mt-2"><strong>Solution</strong>: m-of-n accounts verified by consensus.</p>
+                            <pre className="bg-slate-900 p-4 rounded-lg overflow-x-auto text-sm text-slate-300 mt-4">
+                                {`Transaction {
+    data: CreateMultisig {
+        signers: vec![alice, bob, carol],
+        threshold: 2,
+    }
+}`}
+                            </pre>
+                            <p className="mt-4">The account address is derived from the threshold and the signer set. Its transactions carry <code>cosignatures</code> instead of a single signature, and are only valid once <code>threshold</code> distinct signers have signed.</p>
                         </section>
 
                         <section>
@@ -14,7 +14,7 @@ export async function POST(request: Request) {
 
         // Transform the frontend transaction format to the backend format if needed
         // Backend SubmitTransaction expects a "Transaction" object with:
-        // { sender: [bytes], receiver: [bytes], nonce: u64, data: { NativeTransfer: { amount: u64 } }, signature: [bytes] }
+        // { sender: [bytes], receiver: [bytes], nonce: u64, fee: u64, chain_id: u32, data: { NativeTransfer: { amount: u64 } }, signature: [bytes] }
         // BUT the API endpoint likely expects the serialized structure or a JSON representation that serde deserializes.
         // Let's assume the API server uses serde_json to deserialize the struct.
         // We need to match:
@@ -22,8 +22,12 @@ export async function POST(request: Request) {
         //     pub sender: Vec<u8>,
         //     pub receiver: Vec<u8>,
         //     pub nonce: u64,
+        //     pub fee: u64,
+        //     pub chain_id: u32,
         //     pub data: TransactionData,
         //     pub signature: Vec<u8>,
+        //     pub valid_until_slot: Option<u64>,
+        //     pub cosignatures: Vec<Cosignature>,
         // }
         // TransactionData::NativeTransfer { amount: u64 }
 
@@ -33,8 +37,12 @@ export async function POST(request: Request) {
             sender: hexToBytes(body.sender),
             receiver: hexToBytes(body.receiver),
             nonce: body.nonce,
+            fee: body.fee ?? 1,
+            chain_id: body.chain_id,
             data: { NativeTransfer: { amount: body.amount } },
-            signature: hexToBytes(body.signature)
+            signature: hexToBytes(body.signature),
+            valid_until_slot: null,
+            cosignatures: []
         };
 
         console.log('[API] Transformed TX:', JSON.stringify(tx));
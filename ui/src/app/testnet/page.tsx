@@ -135,12 +135,20 @@ export default function TestnetDashboard() {
             const account = await accountRes.json();
             const currentNonce = account.nonce;
 
+            // Transactions are only valid on the network they are signed for
+            const statsRes = await fetch('/api/node/stats', { cache: 'no-store' });
+            if (!statsRes.ok) {
+                throw new Error(`Failed to fetch chain ID: ${statsRes.status}`);
+            }
+            const { chain_id: chainId } = await statsRes.json();
+
             // 2. Sign Transaction
             const signedTx = await BrowserWallet.createAndSignTransaction(
                 wallet,
                 confirmTransaction.to,
                 confirmTransaction.amount,
-                currentNonce
+                currentNonce,
+                chainId
             );
 
             // 3. Send to Node
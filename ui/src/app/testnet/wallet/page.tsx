@@ -308,12 +308,20 @@ function SendView({ onBack, address, balance, onSuccess }: any) {
 
             const currentNonce = account.nonce;
 
+            // Transactions are only valid on the network they are signed for
+            const statsRes = await fetch('/api/node/stats', { cache: 'no-store' });
+            if (!statsRes.ok) {
+                throw new Error(`Failed to fetch chain ID: ${statsRes.status}`);
+            }
+            const { chain_id: chainId } = await statsRes.json();
+
             // 2. Sign
             const signedTx = await BrowserWallet.createAndSignTransaction(
                 wallet,
                 receiver,
                 parseInt(amount), // Assuming integer amount for now
-                currentNonce
+                currentNonce,
+                chainId
             );
 
             console.log("Sending transaction...", signedTx);
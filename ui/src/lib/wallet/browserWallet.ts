@@ -438,10 +438,15 @@ export interface Transaction {
     sender: string;       // Hex
     receiver: string;     // Hex
     nonce: number;
+    fee: number;
+    chain_id: number;     // Network the transaction is signed for
     amount: number;
     signature?: string;   // Hex
 }
 
+// Minimum flat fee required by the node (MIN_TRANSACTION_FEE)
+export const MIN_TRANSACTION_FEE = 1;
+
 // Helper for Hex conversion
 function hexToBytes(hex: string): Uint8Array {
     if (hex.length % 2 !== 0) throw new Error("Invalid hex string");
@@ -462,7 +467,9 @@ export async function createAndSignTransaction(
     wallet: Wallet,
     receiverHex: string,
     amount: number,
-    nonce: number
+    nonce: number,
+    chainId: number,
+    fee: number = MIN_TRANSACTION_FEE
 ): Promise<Transaction> {
     if (!wallet.privateKey) throw new Error('Wallet is locked or private key invalid');
 
@@ -470,9 +477,9 @@ export async function createAndSignTransaction(
     const receiverBytes = hexToBytes(receiverHex);
 
     // Create Hash buffer
-    // Layout: sender(32) + receiver(32) + nonce(8) + tag("NativeTransfer")(14) + amount(8)
+    // Layout: sender(32) + receiver(32) + nonce(8) + fee(8) + chain_id(4) + tag("NativeTransfer")(14) + amount(8)
     const tag = new TextEncoder().encode("NativeTransfer");
-    const bufferSize = 32 + 32 + 8 + tag.length + 8;
+    const bufferSize = 32 + 32 + 8 + 8 + 4 + tag.length + 8;
     const buffer = new Uint8Array(bufferSize);
     const view = new DataView(buffer.buffer);
 
@@ -490,6 +497,14 @@ export async function createAndSignTransaction(
     view.setBigUint64(offset, BigInt(nonce), true);
     offset += 8;
 
+    // Fee (u64 le)
+    view.setBigUint64(offset, BigInt(fee), true);
+    offset += 8;
+
+    // Chain ID (u32 le)
+    view.setUint32(offset, chainId, true);
+    offset += 4;
+
     // Tag
     buffer.set(tag, offset);
     offset += tag.length;
@@ -514,6 +529,8 @@ export async function createAndSignTransaction(
         sender: wallet.publicKey,
         receiver: receiverHex,
         nonce,
+        fee,
+        chain_id: chainId,
         amount,
         signature: bytesToHex(signatureBytes)
     };